@@ -26,9 +26,11 @@ generate_enums! {
     ////////////
 
     Agree: 1
+    CopyKey: 29
     CreateObject: 2
     // TODO: why do Decrypt and DeriveKey both have discriminant 3?!
     Decrypt: 3
+    DeriveDeviceKey: 37
     DeriveKey: 4
     DeserializeKey: 5
     Encrypt: 6
@@ -39,20 +41,28 @@ generate_enums! {
     FindObjects: 9
     GenerateKey: 10
     GenerateSecretKey: 11
+    GetAttributes: 26
+    ListKeysFirst: 27
+    ListKeysNext: 28
+    MoveKey: 30
     // GenerateKeypair: 6
     Hash: 12
+    ImportKey: 40
     // TODO: add ReadDir{First,Next}, not loading data, if needed for efficiency
     ReadDirFilesFirst: 13
     ReadDirFilesNext: 14
     ReadFile: 15
     // ReadCounter: 7
     RandomBytes: 16
+    RewrapKeys: 38
     SerializeKey: 17
     Sign: 18
+    SlotKey: 39
     WriteFile: 19
     UnsafeInjectKey: 20
     UnsafeInjectSharedKey: 21
     UnwrapKey: 22
+    UpgradeHandle: 44
     Verify: 23
     WrapKey: 24
 
@@ -98,6 +108,31 @@ generate_enums! {
     ReadCertificate: 61
     WriteCertificate: 62
 
+    ////////////
+    // Grants //
+    ////////////
+
+    GrantKey: 70
+    ListGrantedKeys: 71
+    RevokeGrant: 72
+
+    //////////
+    // PINs //
+    //////////
+
+    ChangePin: 80
+    Logout: 81
+    PinRetries: 82
+    SetPin: 83
+    VerifyPin: 84
+
+    //////////////
+    // Sessions //
+    //////////////
+
+    CloseSession: 90
+    OpenSession: 91
+
     ///////////
     // Other //
     ///////////
@@ -120,11 +155,17 @@ pub mod request {
             // only Ed255 + P256
             - private_key: ObjectHandle
 
+        // as C_CopyObject, the copy gets a new handle
+        CopyKey:
+            - key: ObjectHandle
+            - location: Location
+
         // examples:
         // - store public keys from external source
         // - store certificates
         CreateObject:
             - attributes: Attributes
+            - location: Location
 
         DebugDumpStore:
 
@@ -146,6 +187,12 @@ pub mod request {
         //   - prefix: Option<Letters>
         //   - name: ShortData
 
+        // symmetric key derived from the platform's hardware-unique key,
+        // the same for the same client and info
+        DeriveDeviceKey:
+            - info: ShortData
+            - attributes: StorageAttributes
+
         // examples:
         // - public key from private key
         // - Diffie-Hellman
@@ -182,7 +229,9 @@ pub mod request {
           - key: ObjectHandle
 
         FindObjects:
-            // - attributes: Attributes
+            - template: ObjectTemplate
+            // number of matching objects to skip, for paging
+            - offset: usize
 
         GenerateKey:
             - mechanism: Mechanism        // -> implies key type
@@ -201,19 +250,36 @@ pub mod request {
         //     // private_key_template: PrivateKeyTemplate
         //     // public_key_template: PublicKeyTemplate
 
-        // GetAttributes:
-        //     - object: ObjectHandle
-        //     - attributes: Attributes
+        GetAttributes:
+            - object: ObjectHandle
 
         Hash:
           - mechanism: Mechanism
           - message: Message
 
+        // private key generated elsewhere, raw or as PKCS#8 `PrivateKeyInfo`
+        ImportKey:
+          - mechanism: Mechanism
+          - serialized_key: Message
+          - format: KeySerialization
+          - attributes: StorageAttributes
+
+        ListKeysFirst:
+          - kind: Option<crate::key::Kind>
+          - secrecy: Option<crate::key::Secrecy>
+
+        ListKeysNext:
+
         LocateFile:
           - location: Location
           - dir: Option<PathBuf>
           - filename: PathBuf
 
+        // keeps the handle
+        MoveKey:
+          - key: ObjectHandle
+          - location: Location
+
         ReadDirFilesFirst:
           - location: Location
           - dir: PathBuf
@@ -251,6 +317,11 @@ pub mod request {
         RandomBytes:
           - count: usize
 
+        // rewrap all keys of the client under a new KEK, or finish an interrupted rotation
+        // the secret is mixed into the new KEK, e.g. one derived from a new PIN
+        RewrapKeys:
+          - secret: ShortData
+
         SerializeKey:
           - mechanism: Mechanism
           - key: ObjectHandle
@@ -262,6 +333,10 @@ pub mod request {
           - message: Message
           - format: SignatureSerialization
 
+        // the key in a well-known slot, if any
+        SlotKey:
+          - slot: SpecialId
+
         WriteFile:
           - location: Location
           - path: PathBuf
@@ -277,6 +352,7 @@ pub mod request {
           - location: Location
           - raw_key: ShortData
 
+        // restores the attributes of the wrapped key, which `attributes` may restrict further
         UnwrapKey:
           - mechanism: Mechanism
           - wrapping_key: ObjectHandle
@@ -284,6 +360,10 @@ pub mod request {
           - associated_data: Message
           - attributes: StorageAttributes
 
+        // a handle to a key of the client, issued before handles were tagged
+        UpgradeHandle:
+          - handle: ObjectHandle
+
         Verify:
           - mechanism: Mechanism
           - key: ObjectHandle
@@ -292,6 +372,7 @@ pub mod request {
           - format: SignatureSerialization
 
         // this should always be an AEAD algorithm
+        // in the envelope of `service::wrap`, authenticating the key's attributes
         WrapKey:
           - mechanism: Mechanism
           - wrapping_key: ObjectHandle
@@ -323,6 +404,40 @@ pub mod request {
           - location: Location
           - der: Message
 
+        // Grants
+        GrantKey:
+          - key: ObjectHandle
+          - grantee: PathBuf
+          - usage: crate::key::Usage
+
+        ListGrantedKeys:
+          - owner: PathBuf
+
+        RevokeGrant:
+          - key: ObjectHandle
+          - grantee: PathBuf
+
+        // PINs
+        ChangePin:
+          - old_pin: ShortData
+          - new_pin: ShortData
+
+        Logout:
+
+        PinRetries:
+
+        SetPin:
+          - pin: ShortData
+          - max_retries: u8
+
+        VerifyPin:
+          - pin: ShortData
+
+        // Sessions
+        CloseSession:
+
+        OpenSession:
+
     }
 }
 
@@ -342,6 +457,9 @@ pub mod reply {
         Attest:
             - certificate: Id
 
+        CopyKey:
+            - key: ObjectHandle
+
         CreateObject:
             - object: ObjectHandle
 
@@ -361,6 +479,9 @@ pub mod reply {
         DeleteAllKeys:
             - count: usize
 
+        DeriveDeviceKey:
+            - key: ObjectHandle
+
         DeriveKey:
             - key: ObjectHandle
 
@@ -389,12 +510,31 @@ pub mod reply {
         //     - private_key: ObjectHandle
         //     - public_key: ObjectHandle
 
+        GetAttributes:
+            - object_type: ObjectType
+            - location: Location
+            // for keys, their metadata (never the material)
+            - key: Option<crate::key::Info>
+            // for other objects, their attributes as created
+            - attributes: Option<Attributes>
+
         Hash:
           - hash: ShortData
 
+        ImportKey:
+            - key: ObjectHandle
+
+        ListKeysFirst:
+          - entry: Option<KeyEntry>
+
+        ListKeysNext:
+          - entry: Option<KeyEntry>
+
         LocateFile:
           - path: Option<PathBuf>
 
+        MoveKey:
+
         ReadDirFilesFirst:
           - data: Option<Message>
 
@@ -423,12 +563,20 @@ pub mod reply {
         RandomBytes:
             - bytes: Message
 
+        RewrapKeys:
+            // of the KEK the keys are now encrypted under
+            - version: u8
+            - count: usize
+
         SerializeKey:
             - serialized_key: Message
 
         Sign:
             - signature: Signature
 
+        SlotKey:
+            - key: Option<ObjectHandle>
+
         WriteFile:
 
         Verify:
@@ -440,9 +588,13 @@ pub mod reply {
         UnsafeInjectSharedKey:
             - key: ObjectHandle
 
+        // None if the wrapped key fails authentication, e.g. under another wrapping key
         UnwrapKey:
             - key: Option<ObjectHandle>
 
+        UpgradeHandle:
+            - handle: ObjectHandle
+
         WrapKey:
             - wrapped_key: Message
 
@@ -468,6 +620,35 @@ pub mod reply {
 
         WriteCertificate:
           - id: Id
+
+        // Grants
+        GrantKey:
+
+        ListGrantedKeys:
+          - keys: Vec<ObjectHandle, config::MAX_OBJECT_HANDLES>
+
+        RevokeGrant:
+          - revoked: bool
+
+        // PINs
+        ChangePin:
+
+        Logout:
+
+        PinRetries:
+          // if there is a PIN
+          - retries: Option<u8>
+
+        SetPin:
+
+        VerifyPin:
+
+        // Sessions
+        CloseSession:
+          // of deleted keys
+          - count: usize
+
+        OpenSession:
     }
 
 }
@@ -101,7 +101,7 @@ pub enum ClientError {
 pub type ClientResult<'c, T, C> = core::result::Result<FutureResult<'c, T, C>, ClientError>;
 
 /// All-in-one trait bounding on the sub-traits.
-pub trait Client: CertificateClient + CryptoClient + CounterClient + FilesystemClient + ManagementClient + UiClient {}
+pub trait Client: CertificateClient + CryptoClient + CounterClient + FilesystemClient + ManagementClient + PinClient + UiClient {}
 
 impl<S: Syscall> Client for ClientImplementation<S> {}
 
@@ -230,6 +230,7 @@ impl<S: Syscall> CryptoClient for ClientImplementation<S> {}
 impl<S: Syscall> CounterClient for ClientImplementation<S> {}
 impl<S: Syscall> FilesystemClient for ClientImplementation<S> {}
 impl<S: Syscall> ManagementClient for ClientImplementation<S> {}
+impl<S: Syscall> PinClient for ClientImplementation<S> {}
 impl<S: Syscall> UiClient for ClientImplementation<S> {}
 
 /// Read/Write + Delete certificates
@@ -300,6 +301,35 @@ pub trait CryptoClient: PollClient {
         Ok(r)
     }
 
+    /// Delete the volatile keys created in the session, returning their number.
+    fn close_session(&mut self)
+        -> ClientResult<'_, reply::CloseSession, Self>
+    {
+        let r = self.request(request::CloseSession {})?;
+        r.client.syscall();
+        Ok(r)
+    }
+
+    /// Copies the key to the location, the copy gets a new handle.
+    fn copy_key(&mut self, key: ObjectHandle, location: Location)
+        -> ClientResult<'_, reply::CopyKey, Self>
+    {
+        let r = self.request(request::CopyKey { key, location })?;
+        r.client.syscall();
+        Ok(r)
+    }
+
+    fn create_object(&mut self, attributes: Attributes, location: Location)
+        -> ClientResult<'_, reply::CreateObject, Self>
+    {
+        let r = self.request(request::CreateObject {
+            attributes,
+            location,
+        })?;
+        r.client.syscall();
+        Ok(r)
+    }
+
     fn decrypt<'c>(&'c mut self, mechanism: Mechanism, key: ObjectHandle,
                        message: &[u8], associated_data: &[u8],
                        nonce: &[u8], tag: &[u8],
@@ -335,6 +365,16 @@ pub trait CryptoClient: PollClient {
         Ok(r)
     }
 
+    /// Derive a symmetric key from the hardware-unique key, if the platform has one.
+    fn derive_device_key(&mut self, info: &[u8], attributes: StorageAttributes)
+        -> ClientResult<'_, reply::DeriveDeviceKey, Self>
+    {
+        let info = ShortData::try_from_slice(info).map_err(|_| ClientError::DataTooLarge)?;
+        let r = self.request(request::DeriveDeviceKey { info, attributes })?;
+        r.client.syscall();
+        Ok(r)
+    }
+
     fn derive_key(&mut self, mechanism: Mechanism, base_key: ObjectHandle, additional_data: Option<MediumData>, attributes: StorageAttributes)
         -> ClientResult<'_, reply::DeriveKey, Self>
     {
@@ -382,6 +422,18 @@ pub trait CryptoClient: PollClient {
         Ok(r)
     }
 
+    /// Returns the first `MAX_OBJECT_HANDLES` matches after skipping `offset` matches.
+    fn find_objects(&mut self, template: ObjectTemplate, offset: usize)
+        -> ClientResult<'_, reply::FindObjects, Self>
+    {
+        let r = self.request(request::FindObjects {
+            template,
+            offset,
+        })?;
+        r.client.syscall();
+        Ok(r)
+    }
+
     fn generate_key(&mut self, mechanism: Mechanism, attributes: StorageAttributes)
         -> ClientResult<'_, reply::GenerateKey, Self>
     {
@@ -404,6 +456,24 @@ pub trait CryptoClient: PollClient {
         Ok(r)
     }
 
+    /// Metadata of a key (never its material), or the attributes of another object.
+    fn get_attributes(&mut self, object: ObjectHandle)
+        -> ClientResult<'_, reply::GetAttributes, Self>
+    {
+        let r = self.request(request::GetAttributes { object })?;
+        r.client.syscall();
+        Ok(r)
+    }
+
+    /// Permit another client to use the secret key for the operations, but not to export it.
+    fn grant_key(&mut self, key: ObjectHandle, grantee: PathBuf, usage: crate::key::Usage)
+        -> ClientResult<'_, reply::GrantKey, Self>
+    {
+        let r = self.request(request::GrantKey { key, grantee, usage })?;
+        r.client.syscall();
+        Ok(r)
+    }
+
     fn hash(&mut self, mechanism: Mechanism, message: Message)
         -> ClientResult<'_, reply::Hash, Self>
     {
@@ -412,6 +482,62 @@ pub trait CryptoClient: PollClient {
         Ok(r)
     }
 
+    /// Import a private key generated elsewhere, if the service allows this client to.
+    fn import_key<'c>(&'c mut self, mechanism: Mechanism, serialized_key: &[u8],
+                      format: KeySerialization, attributes: StorageAttributes)
+        -> ClientResult<'c, reply::ImportKey, Self>
+    {
+        let serialized_key = Message::try_from_slice(serialized_key).map_err(|_| ClientError::DataTooLarge)?;
+        let r = self.request(request::ImportKey { mechanism, serialized_key, format, attributes })?;
+        r.client.syscall();
+        Ok(r)
+    }
+
+    /// Handles of the keys the owner granted to this client.
+    fn list_granted_keys(&mut self, owner: PathBuf)
+        -> ClientResult<'_, reply::ListGrantedKeys, Self>
+    {
+        let r = self.request(request::ListGrantedKeys { owner })?;
+        r.client.syscall();
+        Ok(r)
+    }
+
+    /// List the keys of all locations, optionally only those of a kind or secrecy.
+    fn list_keys_first(&mut self, kind: Option<crate::key::Kind>, secrecy: Option<crate::key::Secrecy>)
+        -> ClientResult<'_, reply::ListKeysFirst, Self>
+    {
+        let r = self.request(request::ListKeysFirst { kind, secrecy } )?;
+        r.client.syscall();
+        Ok(r)
+    }
+
+    fn list_keys_next(&mut self)
+        -> ClientResult<'_, reply::ListKeysNext, Self>
+    {
+        let r = self.request(request::ListKeysNext {} )?;
+        r.client.syscall();
+        Ok(r)
+    }
+
+    /// Moves the key to the location, keeping its handle.
+    fn move_key(&mut self, key: ObjectHandle, location: Location)
+        -> ClientResult<'_, reply::MoveKey, Self>
+    {
+        let r = self.request(request::MoveKey { key, location })?;
+        r.client.syscall();
+        Ok(r)
+    }
+
+    /// Start tracking created volatile keys, to delete them when the session is closed
+    /// or the client is added to the service again.
+    fn open_session(&mut self)
+        -> ClientResult<'_, reply::OpenSession, Self>
+    {
+        let r = self.request(request::OpenSession {})?;
+        r.client.syscall();
+        Ok(r)
+    }
+
     fn random_bytes(&mut self, count: usize)
         -> ClientResult<'_, reply::RandomBytes, Self>
     {
@@ -420,6 +546,26 @@ pub trait CryptoClient: PollClient {
         Ok(r)
     }
 
+    /// Withdraw a grant of the key, returning whether there was one.
+    fn revoke_grant(&mut self, key: ObjectHandle, grantee: PathBuf)
+        -> ClientResult<'_, reply::RevokeGrant, Self>
+    {
+        let r = self.request(request::RevokeGrant { key, grantee })?;
+        r.client.syscall();
+        Ok(r)
+    }
+
+    /// Rotate the key-encryption key, mixing in the secret, or finish a rotation
+    /// interrupted by power loss.
+    fn rewrap_keys(&mut self, secret: &[u8])
+        -> ClientResult<'_, reply::RewrapKeys, Self>
+    {
+        let secret = ShortData::try_from_slice(secret).map_err(|_| ClientError::DataTooLarge)?;
+        let r = self.request(request::RewrapKeys { secret })?;
+        r.client.syscall();
+        Ok(r)
+    }
+
     fn serialize_key(&mut self, mechanism: Mechanism, key: ObjectHandle, format: KeySerialization)
         -> ClientResult<'_, reply::SerializeKey, Self>
     {
@@ -451,6 +597,15 @@ pub trait CryptoClient: PollClient {
         Ok(r)
     }
 
+    /// The key in a well-known slot, see `StorageAttributes::set_slot`.
+    fn slot_key(&mut self, slot: SpecialId)
+        -> ClientResult<'_, reply::SlotKey, Self>
+    {
+        let r = self.request(request::SlotKey { slot })?;
+        r.client.syscall();
+        Ok(r)
+    }
+
     fn verify<'c>(
         &'c mut self,
         mechanism: Mechanism,
@@ -478,7 +633,7 @@ pub trait CryptoClient: PollClient {
     /// of trussed::key::Kind::Shared.
     ///
     /// The implementations have been removed, so replace your usage with the
-    /// methoed `unsafe_inject_shared_key`.
+    /// methoed `unsafe_inject_shared_key`, or `import_key` for private keys.
     fn unsafe_inject_key(&mut self, mechanism: Mechanism, raw_key: &[u8], persistence: Location)
         -> ClientResult<'_, reply::UnsafeInjectKey, Self>
     {
@@ -518,6 +673,16 @@ pub trait CryptoClient: PollClient {
         Ok(r)
     }
 
+    /// Exchange a handle persisted before handles were tagged for a tagged one,
+    /// if it refers to a key of the client.
+    fn upgrade_handle(&mut self, handle: ObjectHandle)
+        -> ClientResult<'_, reply::UpgradeHandle, Self>
+    {
+        let r = self.request(request::UpgradeHandle { handle })?;
+        r.client.syscall();
+        Ok(r)
+    }
+
     fn wrap_key(&mut self, mechanism: Mechanism, wrapping_key: ObjectHandle, key: ObjectHandle,
                        associated_data: &[u8])
         -> ClientResult<'_, reply::WrapKey, Self>
@@ -689,6 +854,57 @@ pub trait ManagementClient: PollClient {
 }
 
 
+/// The client's PIN, which private keys require to be verified
+pub trait PinClient: PollClient {
+
+    fn change_pin(&mut self, old_pin: &[u8], new_pin: &[u8])
+        -> ClientResult<'_, reply::ChangePin, Self>
+    {
+        let old_pin = ShortData::try_from_slice(old_pin).map_err(|_| ClientError::DataTooLarge)?;
+        let new_pin = ShortData::try_from_slice(new_pin).map_err(|_| ClientError::DataTooLarge)?;
+        let r = self.request(request::ChangePin { old_pin, new_pin })?;
+        r.client.syscall();
+        Ok(r)
+    }
+
+    /// Forget that the PIN was verified, which also happens on reboot.
+    fn logout(&mut self)
+        -> ClientResult<'_, reply::Logout, Self>
+    {
+        let r = self.request(request::Logout {})?;
+        r.client.syscall();
+        Ok(r)
+    }
+
+    fn pin_retries(&mut self)
+        -> ClientResult<'_, reply::PinRetries, Self>
+    {
+        let r = self.request(request::PinRetries {})?;
+        r.client.syscall();
+        Ok(r)
+    }
+
+    /// Set the initial PIN, which is locked after `max_retries` consecutive failed attempts.
+    fn set_pin(&mut self, pin: &[u8], max_retries: u8)
+        -> ClientResult<'_, reply::SetPin, Self>
+    {
+        let pin = ShortData::try_from_slice(pin).map_err(|_| ClientError::DataTooLarge)?;
+        let r = self.request(request::SetPin { pin, max_retries })?;
+        r.client.syscall();
+        Ok(r)
+    }
+
+    fn verify_pin(&mut self, pin: &[u8])
+        -> ClientResult<'_, reply::VerifyPin, Self>
+    {
+        let pin = ShortData::try_from_slice(pin).map_err(|_| ClientError::DataTooLarge)?;
+        let r = self.request(request::VerifyPin { pin })?;
+        r.client.syscall();
+        Ok(r)
+    }
+
+}
+
 /// User-interfacing functionality.
 pub trait UiClient: PollClient {
 
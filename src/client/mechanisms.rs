@@ -147,6 +147,12 @@ pub trait Ed255: CryptoClient {
         self.deserialize_key(Mechanism::Ed255, serialized_key, format, attributes)
     }
 
+    fn import_ed255_key<'c>(&'c mut self, serialized_key: &[u8], format: KeySerialization, attributes: StorageAttributes)
+        -> ClientResult<'c, reply::ImportKey, Self>
+    {
+        self.import_key(Mechanism::Ed255, serialized_key, format, attributes)
+    }
+
     fn serialize_ed255_key(&mut self, key: ObjectHandle, format: KeySerialization)
         -> ClientResult<'_, reply::SerializeKey, Self>
     {
@@ -188,6 +194,12 @@ pub trait P256: CryptoClient {
         self.deserialize_key(Mechanism::P256, serialized_key, format, attributes)
     }
 
+    fn import_p256_key<'c>(&'c mut self, serialized_key: &[u8], format: KeySerialization, attributes: StorageAttributes)
+        -> ClientResult<'c, reply::ImportKey, Self>
+    {
+        self.import_key(Mechanism::P256, serialized_key, format, attributes)
+    }
+
     fn serialize_p256_key(&mut self, key: ObjectHandle, format: KeySerialization)
         -> ClientResult<'_, reply::SerializeKey, Self>
     {
@@ -206,6 +218,16 @@ pub trait P256: CryptoClient {
         self.sign(Mechanism::P256, key, message, format)
     }
 
+    /// Signatures with deterministic nonces (RFC 6979), for instance to reproduce test vectors.
+    ///
+    /// The regular `sign_p256` also derives its nonces via RFC 6979, but hedges them with
+    /// fresh entropy, which is preferable whenever reproducibility is not required.
+    fn sign_p256_deterministic<'c>(&'c mut self, key: ObjectHandle, message: &[u8], format: SignatureSerialization)
+        -> ClientResult<'c, reply::Sign, Self>
+    {
+        self.sign(Mechanism::P256Deterministic, key, message, format)
+    }
+
     fn verify_p256<'c>(&'c mut self, key: ObjectHandle, message: &[u8], signature: &[u8])
         -> ClientResult<'c, reply::Verify, Self>
     {
@@ -288,6 +310,12 @@ pub trait X255: CryptoClient {
         self.derive_key(Mechanism::X255, secret_key, None, StorageAttributes::new().set_persistence(persistence))
     }
 
+    fn import_x255_key<'c>(&'c mut self, serialized_key: &[u8], format: KeySerialization, attributes: StorageAttributes)
+        -> ClientResult<'c, reply::ImportKey, Self>
+    {
+        self.import_key(Mechanism::X255, serialized_key, format, attributes)
+    }
+
     fn agree_x255(&mut self, private_key: ObjectHandle, public_key: ObjectHandle, persistence: Location)
         -> ClientResult<'_, reply::Agree, Self>
     {
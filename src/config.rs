@@ -13,12 +13,29 @@ pub type MAX_OBJECT_HANDLES = consts::U16;
 pub type MAX_LABEL_LENGTH = consts::U256;
 pub type MAX_MEDIUM_DATA_LENGTH = consts::U256;
 pub type MAX_PATH_LENGTH = consts::U256;
+pub type MAX_KEY_ATTRIBUTES_LENGTH = consts::U128;
+pub type MAX_KEY_LABEL_LENGTH = consts::U32;
 pub type MAX_KEY_MATERIAL_LENGTH = consts::U128;
-pub type MAX_SERIALIZED_KEY_LENGTH = <MAX_KEY_MATERIAL_LENGTH as core::ops::Add<consts::U4>>::Output;
+pub type MAX_KEY_POLICY_MECHANISMS = consts::U8;
+// version, flags, kind, length of attributes, attributes, material
+pub type MAX_SERIALIZED_KEY_LENGTH = <<MAX_KEY_MATERIAL_LENGTH as core::ops::Add<MAX_KEY_ATTRIBUTES_LENGTH>>::Output
+    as core::ops::Add<consts::U7>>::Output;
+// marker, KEK version, nonce, serialized key, tag of encrypted key files
+pub type MAX_STORED_KEY_LENGTH = <MAX_SERIALIZED_KEY_LENGTH as core::ops::Add<consts::U30>>::Output;
+// postcard-serialized `Attributes` of data objects and certificates
+pub type MAX_SERIALIZED_OBJECT_LENGTH = consts::U2048;
 pub type MAX_SERVICE_CLIENTS = consts::U5;
+// volatile keys a client can create in a session
+pub type MAX_SESSION_KEYS = consts::U16;
 pub type MAX_SHORT_DATA_LENGTH = consts::U128;
-pub type MAX_SIGNATURE_LENGTH = consts::U72;
+pub type MAX_SIGNATURE_LENGTH = consts::U128;
 pub type MAX_USER_ATTRIBUTE_LENGTH = consts::U256;
 
 pub const USER_ATTRIBUTE_NUMBER: u8 = 37;
 
+// volatile key files a client can have, in or out of a session, as RAM is shared by all clients
+pub const MAX_VOLATILE_KEYS: usize = 32;
+
+// how long the service waits for the user to touch before using a key with a touch policy
+pub const KEY_TOUCH_TIMEOUT_MILLISECONDS: u32 = 30_000;
+
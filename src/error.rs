@@ -12,13 +12,31 @@ pub enum Error {
     HostMemory = 0x0000_0002,
     GeneralError = 0x0000_0005,
     FunctionFailed = 0x0000_0006,
+    // request is malformed, e.g. allows no PIN retries
+    ArgumentsBad = 0x0000_0007,
+    // client has as many volatile keys as it may hold
+    DeviceMemory = 0x0000_0031,
     // supposed to support "stub" function for everything,
     // returning this error
     FunctionNotSupported = 0x0000_0054,
+    // key's usage policy does not permit the requested operation or mechanism
+    KeyFunctionNotPermitted = 0x0000_0068,
+    // key may not leave the service, or not in plaintext
+    KeyUnextractable = 0x0000_006A,
     // unknown, or cannot be used in this token with selected function
     MechanismInvalid = 0x0000_0070,
     MechanismParamInvalid = 0x0000_0071,
     ObjectHandleInvalid = 0x0000_0082,
+    PinIncorrect = 0x0000_00A0,
+    PinLenRange = 0x0000_00A2,
+    PinLocked = 0x0000_00A4,
+    // client has a session open already
+    SessionExists = 0x0000_00B6,
+    // key is private, and the client's PIN is not verified
+    UserNotLoggedIn = 0x0000_0101,
+    UserPinNotInitialized = 0x0000_0102,
+    // malformed or unauthentic envelope, or of an unknown version
+    WrappedKeyInvalid = 0x0000_0110,
 
     // our errors
     AeadError,
@@ -27,16 +45,33 @@ pub enum Error {
     FilesystemReadFailure,
     FilesystemWriteFailure,
     ImplementationError,
+    // client is not allowed to import private keys
+    ImportNotAllowed,
     InternalError,
+    // path leaves the client's directory
+    InvalidPath,
     InvalidSerializedKey,
     InvalidSerializationFormat,
+    // all KEK versions were used, rotating again would reuse one
+    KekVersionsExhausted,
+    // key's uses are exhausted, or its not-after time has passed
+    KeyExpired,
     MechanismNotAvailable,
     NonceOverflow,
     NoSuchCertificate,
     NoSuchKey,
     NotJustLetters,
+    PinAlreadySet,
     RequestNotAvailable,
+    // session has as many volatile keys as it can hold
+    SessionFull,
     SignDataTooLarge,
+    // key slot holds a key, which the request does not allow to replace
+    SlotOccupied,
+    // key's touch policy requires user presence, which was not given in time
+    UserNotPresent,
+    // stored key failed authentication under the key-encryption key
+    TamperedKey,
     WrongKeyKind,
     WrongMessageLength,
     WrongSignatureLength,
@@ -1,3 +1,5 @@
+use core::convert::TryInto;
+
 use serde::{Deserialize, Serialize};
 use serde_indexed::{DeserializeIndexed, SerializeIndexed};
 use zeroize::Zeroize;
@@ -5,9 +7,11 @@ use zeroize::Zeroize;
 pub use crate::Bytes;
 use crate::{
     Error,
-    config::{MAX_KEY_MATERIAL_LENGTH, MAX_SERIALIZED_KEY_LENGTH},
+    config::{MAX_KEY_LABEL_LENGTH, MAX_KEY_MATERIAL_LENGTH, MAX_KEY_POLICY_MECHANISMS, MAX_SERIALIZED_KEY_LENGTH},
+    types::{Mechanism, StorageAttributes, Vec},
 };
 
+pub type Label = Bytes<MAX_KEY_LABEL_LENGTH>;
 pub type Material = Bytes<MAX_KEY_MATERIAL_LENGTH>;
 pub type SerializedKeyBytes = Bytes<MAX_SERIALIZED_KEY_LENGTH>;
 
@@ -24,13 +28,29 @@ pub type SerializedKeyBytes = Bytes<MAX_SERIALIZED_KEY_LENGTH>;
 pub struct Key {
    pub flags: Flags,
    pub kind: Kind,
+   #[zeroize(skip)]
+   pub policy: Policy,
+   #[zeroize(skip)]
+   pub label: Option<Label>,
+   #[zeroize(skip)]
+   pub created: Option<u64>,
    pub material: Material,
 }
 
-#[derive(Clone, Debug, /*DeserializeIndexed,*/ Eq, PartialEq, /*SerializeIndexed,*/ Zeroize)]
+/// The metadata of a key, without its material.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize, Zeroize)]
 pub struct Info {
    pub flags: Flags,
    pub kind: Kind,
+   #[zeroize(skip)]
+   pub policy: Policy,
+   /// chosen by the application, e.g. to find its keys again
+   #[zeroize(skip)]
+   pub label: Option<Label>,
+   /// seconds since the Unix epoch when the key was created, if the platform has a clock;
+   /// set by the keystore, and kept by copies
+   #[zeroize(skip)]
+   pub created: Option<u64>,
 }
 
 impl Info {
@@ -38,16 +58,156 @@ impl Info {
         self.flags |= Flags::LOCAL;
         self
     }
+
+    pub fn with_policy(mut self, policy: &Policy) -> Self {
+        self.policy = policy.clone();
+        self
+    }
+
+    pub fn with_label(mut self, label: Option<&Label>) -> Self {
+        self.label = label.cloned();
+        self
+    }
+
+    /// Applies the policy, extractability and label requested for a new key.
+    pub fn with_attributes(mut self, attributes: &StorageAttributes) -> Self {
+        self.flags.set(Flags::EXTRACTABLE, attributes.extractable);
+        self.flags.set(Flags::PRIVATE, attributes.private);
+        self.with_policy(&attributes.policy).with_label(attributes.label.as_ref())
+    }
 }
 
 impl From<Kind> for Info {
     fn from(kind: Kind) -> Self {
-        Self { flags: Default::default(), kind }
+        Self { flags: Default::default(), kind, policy: Default::default(), label: None, created: None }
+    }
+}
+
+bitflags::bitflags! {
+    #[derive(DeserializeIndexed, SerializeIndexed, Zeroize)]
+    /// Operations a key may be used for, as in PKCS#11's `CKA_SIGN` etc.
+    pub struct Usage: u16 {
+        const SIGN = 1 << 0;
+        const VERIFY = 1 << 1;
+        const ENCRYPT = 1 << 2;
+        const DECRYPT = 1 << 3;
+        const DERIVE = 1 << 4;
+        const WRAP = 1 << 5;
+        const UNWRAP = 1 << 6;
+        const AGREE = 1 << 7;
+    }
+}
+
+impl Default for Usage {
+    /// No restrictions, to keep the behaviour of keys without policy
+    fn default() -> Self {
+        Usage::all()
+    }
+}
+
+/// Usage policy of a key, persisted with it and enforced by the service.
+///
+/// The default policy permits everything.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Policy {
+    pub usage: Usage,
+    /// allowed mechanisms, as in PKCS#11's `CKA_ALLOWED_MECHANISMS`; empty means any
+    pub mechanisms: Vec<Mechanism, MAX_KEY_POLICY_MECHANISMS>,
+    /// remaining uses to sign, decrypt or agree, counted down by the service; `None` means unlimited
+    pub uses_remaining: Option<u32>,
+    /// seconds since the Unix epoch after which the key can no longer sign, decrypt or agree
+    pub not_after: Option<u64>,
+    pub touch: TouchPolicy,
+}
+
+/// Whether the service checks user presence before a key signs, decrypts or agrees,
+/// as YubiKey PIV's touch policies.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum TouchPolicy {
+    Never,
+    Always,
+    /// a touch is good for uses of the client's keys within the given number of seconds
+    Cached(u32),
+}
+
+impl Default for TouchPolicy {
+    fn default() -> Self {
+        TouchPolicy::Never
+    }
+}
+
+impl Policy {
+    pub fn new(usage: Usage) -> Self {
+        Self { usage, mechanisms: Vec::new(), uses_remaining: None, not_after: None, touch: TouchPolicy::Never }
+    }
+
+    pub fn require_touch(mut self, touch: TouchPolicy) -> Self {
+        self.touch = touch;
+        self
+    }
+
+    /// Limits the number of times the key can sign, decrypt or agree, e.g. to one for one-time keys.
+    pub fn limit_uses(mut self, uses: u32) -> Self {
+        self.uses_remaining = Some(uses);
+        self
+    }
+
+    /// Expires the key after the time, in seconds since the Unix epoch.
+    ///
+    /// Keys with an expiry are unusable on platforms without a real-time clock.
+    pub fn valid_until(mut self, not_after: u64) -> Self {
+        self.not_after = Some(not_after);
+        self
+    }
+
+    /// Restricts the policy to the given mechanism, in addition to any previously allowed ones.
+    pub fn allow_mechanism(mut self, mechanism: Mechanism) -> Self {
+        if !self.mechanisms.contains(&mechanism) {
+            // more than MAX_KEY_POLICY_MECHANISMS is a programming error
+            self.mechanisms.push(mechanism).ok();
+        }
+        self
+    }
+
+    pub fn permits(&self, mechanism: Mechanism, usage: Usage) -> bool {
+        self.usage.contains(usage)
+            && (self.mechanisms.is_empty() || self.mechanisms.contains(&mechanism))
+    }
+
+    /// The policy permitting what both policies permit.
+    pub fn intersection(&self, other: &Self) -> Self {
+        let mechanisms = match (self.mechanisms.is_empty(), other.mechanisms.is_empty()) {
+            (true, _) => other.mechanisms.clone(),
+            (_, true) => self.mechanisms.clone(),
+            _ => self.mechanisms.iter()
+                .filter(|mechanism| other.mechanisms.contains(mechanism))
+                .cloned()
+                .collect(),
+        };
+        let min = |a: Option<_>, b: Option<_>| match (a, b) {
+            (Some(a), Some(b)) => Some(core::cmp::min(a, b)),
+            (a, b) => a.or(b),
+        };
+        let mut intersection = Self {
+            usage: self.usage & other.usage,
+            mechanisms,
+            uses_remaining: min(self.uses_remaining, other.uses_remaining),
+            not_after: min(self.not_after, other.not_after),
+            touch: match (self.touch, other.touch) {
+                (TouchPolicy::Always, _) | (_, TouchPolicy::Always) => TouchPolicy::Always,
+                (TouchPolicy::Cached(a), TouchPolicy::Cached(b)) => TouchPolicy::Cached(core::cmp::min(a, b)),
+                (TouchPolicy::Never, touch) | (touch, TouchPolicy::Never) => touch,
+            },
+        };
+        if intersection.mechanisms.is_empty() && !(self.mechanisms.is_empty() && other.mechanisms.is_empty()) {
+            // disjoint mechanism lists, "empty" would mean "any"
+            intersection.usage = Usage::empty();
+        }
+        intersection
     }
 }
 
-// TODO: How to store/check?
-// TODO: Fix variant indices to keep storage stable!!
+/// Key files store the kind by its `code`, which must never change for a variant.
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Zeroize)]
 #[repr(u16)]
 pub enum Kind {
@@ -73,10 +233,18 @@ bitflags::bitflags! {
     pub struct Flags: u16 {
         const LOCAL = 1 << 0;
         const SENSITIVE = 1 << 1;
+        /// may leave the service wrapped (and unwrapped if not sensitive)
+        const EXTRACTABLE = 1 << 2;
+        /// has been sensitive since generation, set by the keystore
+        const ALWAYS_SENSITIVE = 1 << 3;
+        /// has not been extractable since generation, set by the keystore
+        const NEVER_EXTRACTABLE = 1 << 4;
+        /// may only be used after the client's PIN is verified, as PKCS#11's `CKA_PRIVATE`
+        const PRIVATE = 1 << 5;
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 /// A key can either be public, of secret.
 ///
 /// The secret case also applies to private keys for asymmetric algorithms.
@@ -86,35 +254,207 @@ pub enum Secrecy {
     Secret,
 }
 
+/// Version of the key file format written by `Key::serialize`.
+///
+/// Key files of all previous versions can be deserialized, and are upgraded by the keystore:
+/// - 0: flags (2 bytes) | kind (2 bytes) | material
+/// - 2: version (1 byte) | flags (2 bytes) | kind (2 bytes) | length of the attributes (2 bytes)
+///   | attributes | material
+///
+/// Version 0 starts with the high byte of the flags, which is 0,
+/// and version 1 was never released.
+pub const SERIALIZATION_VERSION: u8 = 2;
+
+// tags of the TLV-encoded attributes
+const USAGE_TAG: u8 = 1;
+const MECHANISMS_TAG: u8 = 2;
+const LABEL_TAG: u8 = 3;
+const USES_REMAINING_TAG: u8 = 4;
+const NOT_AFTER_TAG: u8 = 5;
+const TOUCH_TAG: u8 = 6;
+const CREATED_TAG: u8 = 7;
+
 impl Key {
+    /// Serializes in the current format, see `SERIALIZATION_VERSION`.
+    ///
+    /// The attributes are each encoded as tag (1 byte) | length (1 byte) | value.
     pub fn serialize(&self) -> SerializedKeyBytes {
+        let mut attributes = SerializedKeyBytes::new();
+        self.serialize_attributes(&mut attributes);
+
         let mut buffer = SerializedKeyBytes::new();
-        // big-endian here to ensure the first bit is enough to check compatibility
-        // on breaking format change
+        buffer.extend_from_slice(&[SERIALIZATION_VERSION]).unwrap();
         buffer.extend_from_slice(&self.flags.bits().to_be_bytes()).unwrap();
         buffer.extend_from_slice(&(self.kind.code()).to_be_bytes()).unwrap();
-        // can't fail, since MAX_SERIALIZED_KEY_LENGTH is defined as MAX_KEY_MATERIAL_LENGTH + 4
+        buffer.extend_from_slice(&(attributes.len() as u16).to_be_bytes()).unwrap();
+        buffer.extend_from_slice(&attributes).unwrap();
+        // can't fail, since MAX_SERIALIZED_KEY_LENGTH is defined as
+        // MAX_KEY_MATERIAL_LENGTH + MAX_KEY_ATTRIBUTES_LENGTH + 7
         buffer.extend_from_slice(&self.material).unwrap();
         buffer
     }
 
-    pub fn try_deserialize(bytes: &[u8]) -> Result<Self, Error> {
-        if bytes.len() < 4 {
-            return Err(Error::InvalidSerializedKey);
+    /// The format version of a serialized key.
+    pub fn format_version(bytes: &[u8]) -> Result<u8, Error> {
+        match bytes.first() {
+            Some(0x00) => Ok(0),
+            Some(&SERIALIZATION_VERSION) => Ok(SERIALIZATION_VERSION),
+            // including versions of newer firmware
+            _ => Err(Error::InvalidSerializedKey),
         }
-        let (info, material) = bytes.split_at(4);
-        let flags_bits = u16::from_be_bytes([info[0], info[1]]);
-        let flags = Flags::from_bits(flags_bits).ok_or(Error::InvalidSerializedKey)?;
+    }
 
-        let kind_bits = u16::from_be_bytes([info[2], info[3]]);
+    fn serialize_attributes(&self, buffer: &mut SerializedKeyBytes) {
+        let mut push = |tag: u8, value: &[u8]| {
+            buffer.extend_from_slice(&[tag, value.len() as u8]).unwrap();
+            buffer.extend_from_slice(value).unwrap();
+        };
+
+        if self.policy.usage != Usage::all() {
+            push(USAGE_TAG, &self.policy.usage.bits().to_be_bytes());
+        }
+        if !self.policy.mechanisms.is_empty() {
+            let mut codes = [0u8; 16];
+            for (code, mechanism) in codes.iter_mut().zip(self.policy.mechanisms.iter()) {
+                *code = mechanism.code();
+            }
+            push(MECHANISMS_TAG, &codes[..self.policy.mechanisms.len()]);
+        }
+        if let Some(label) = &self.label {
+            push(LABEL_TAG, label);
+        }
+        if let Some(uses_remaining) = self.policy.uses_remaining {
+            push(USES_REMAINING_TAG, &uses_remaining.to_be_bytes());
+        }
+        if let Some(not_after) = self.policy.not_after {
+            push(NOT_AFTER_TAG, &not_after.to_be_bytes());
+        }
+        // the cache period, or nothing for always
+        match self.policy.touch {
+            TouchPolicy::Never => {}
+            TouchPolicy::Always => push(TOUCH_TAG, &[]),
+            TouchPolicy::Cached(seconds) => push(TOUCH_TAG, &seconds.to_be_bytes()),
+        }
+        if let Some(created) = self.created {
+            push(CREATED_TAG, &created.to_be_bytes());
+        }
+    }
+
+    pub fn try_deserialize(bytes: &[u8]) -> Result<Self, Error> {
+        let (flags_bits, kind_bits, attributes, material) = match Self::format_version(bytes)? {
+            0 => {
+                if bytes.len() < 4 {
+                    return Err(Error::InvalidSerializedKey);
+                }
+                let (header, rest) = bytes.split_at(4);
+                (
+                    u16::from_be_bytes([header[0], header[1]]),
+                    u16::from_be_bytes([header[2], header[3]]),
+                    &[][..],
+                    rest,
+                )
+            }
+            _ => {
+                if bytes.len() < 5 {
+                    return Err(Error::InvalidSerializedKey);
+                }
+                let (header, rest) = bytes[1..].split_at(4);
+                let (attributes, material) = Self::split_attributes(rest)?;
+                (
+                    u16::from_be_bytes([header[0], header[1]]),
+                    u16::from_be_bytes([header[2], header[3]]),
+                    attributes,
+                    material,
+                )
+            }
+        };
+
+        let flags = Flags::from_bits(flags_bits).ok_or(Error::InvalidSerializedKey)?;
+        let (policy, label, created) = Self::deserialize_attributes(attributes)?;
         let kind = Kind::try_from(kind_bits, material.len()).map_err(|_| Error::InvalidSerializedKey)?;
 
         Ok(Key {
             flags,
             kind,
+            policy,
+            label,
+            created,
             material: Material::try_from_slice(material).map_err(|_| Error::InvalidSerializedKey)?,
         })
     }
+
+    /// Splits the length-prefixed attributes from the material.
+    fn split_attributes(bytes: &[u8]) -> Result<(&[u8], &[u8]), Error> {
+        if bytes.len() < 2 {
+            return Err(Error::InvalidSerializedKey);
+        }
+        let attributes_length = u16::from_be_bytes([bytes[0], bytes[1]]) as usize;
+        if bytes.len() < 2 + attributes_length {
+            return Err(Error::InvalidSerializedKey);
+        }
+        Ok(bytes[2..].split_at(attributes_length))
+    }
+
+    fn deserialize_attributes(mut attributes: &[u8]) -> Result<(Policy, Option<Label>, Option<u64>), Error> {
+        let mut policy = Policy::default();
+        let mut label = None;
+        let mut created = None;
+        while let [tag, length, rest @ ..] = attributes {
+            let length = *length as usize;
+            if rest.len() < length {
+                return Err(Error::InvalidSerializedKey);
+            }
+            let (value, rest) = rest.split_at(length);
+            attributes = rest;
+            match *tag {
+                USAGE_TAG => {
+                    let bits = match value {
+                        [high, low] => u16::from_be_bytes([*high, *low]),
+                        _ => return Err(Error::InvalidSerializedKey),
+                    };
+                    policy.usage = Usage::from_bits(bits).ok_or(Error::InvalidSerializedKey)?;
+                }
+                MECHANISMS_TAG => {
+                    for code in value {
+                        policy.mechanisms.push(Mechanism::try_from_code(*code)?)
+                            .map_err(|_| Error::InvalidSerializedKey)?;
+                    }
+                }
+                LABEL_TAG => {
+                    label = Some(Label::try_from_slice(value).map_err(|_| Error::InvalidSerializedKey)?);
+                }
+                USES_REMAINING_TAG => {
+                    let bytes = value.try_into().map_err(|_| Error::InvalidSerializedKey)?;
+                    policy.uses_remaining = Some(u32::from_be_bytes(bytes));
+                }
+                NOT_AFTER_TAG => {
+                    let bytes = value.try_into().map_err(|_| Error::InvalidSerializedKey)?;
+                    policy.not_after = Some(u64::from_be_bytes(bytes));
+                }
+                TOUCH_TAG => {
+                    policy.touch = match value {
+                        [] => TouchPolicy::Always,
+                        _ => TouchPolicy::Cached(u32::from_be_bytes(
+                            value.try_into().map_err(|_| Error::InvalidSerializedKey)?)),
+                    };
+                }
+                CREATED_TAG => {
+                    let bytes = value.try_into().map_err(|_| Error::InvalidSerializedKey)?;
+                    created = Some(u64::from_be_bytes(bytes));
+                }
+                // unknown attributes are an error, to not silently drop restrictions
+                _ => return Err(Error::InvalidSerializedKey),
+            }
+        }
+        if !attributes.is_empty() {
+            return Err(Error::InvalidSerializedKey);
+        }
+        Ok((policy, label, created))
+    }
+
+    pub fn info(&self) -> Info {
+        Info { flags: self.flags, kind: self.kind, policy: self.policy.clone(), label: self.label.clone(), created: self.created }
+    }
 }
 
 impl Default for Flags {
@@ -36,7 +36,9 @@ impl crate::service::Sign for HmacSha512 {}
 
 pub struct P256 {}
 pub struct P256Prehashed {}
-mod p256;
+pub struct P256Deterministic {}
+pub struct P256PrehashedDeterministic {}
+pub(crate) mod p256;
 
 pub struct Sha256 {}
 mod sha256;
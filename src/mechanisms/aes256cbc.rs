@@ -58,6 +58,9 @@ impl WrapKey for super::Aes256Cbc
     fn wrap_key(keystore: &mut impl Keystore, request: &request::WrapKey)
         -> Result<reply::WrapKey, Error>
     {
+        // no `wrap::Envelope`: CTAP's PIN protocols expect the bare ciphertext of the material,
+        // which is not meant to be unwrapped
+
         // TODO: need to check both secret and private keys
         // let path = keystore.key_path(key::Secrecy::Secret, &request.key.object_id)?;
         // let (serialized_key, _location) = keystore.load_key_unchecked(&path)?;
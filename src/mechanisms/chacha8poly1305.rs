@@ -30,11 +30,11 @@ impl GenerateKey for super::Chacha8Poly1305 {
         let key_id = keystore.store_key(
             request.attributes.persistence,
             key::Secrecy::Secret,
-            key::Kind::Symmetric32Nonce(12),
+            key::Info::from(key::Kind::Symmetric32Nonce(12)).with_local_flag().with_attributes(&request.attributes),
             &serialized,
         )?;
 
-        Ok(reply::GenerateKey { key: ObjectHandle { object_id: key_id } })
+        Ok(reply::GenerateKey { key: ObjectHandle::new(key_id) })
     }
 }
 
@@ -53,6 +53,22 @@ fn increment_nonce(nonce: &mut [u8]) -> Result<(), Error> {
     }
 }
 
+/// Loads the key, advancing its nonce, which is persisted before use.
+#[inline(never)]
+fn load_key_advancing_nonce(keystore: &mut impl Keystore, key_id: &UniqueId) -> Result<key::Material, Error> {
+    let secrecy = key::Secrecy::Secret;
+    let mut serialized_material = keystore
+        .load_key(secrecy, Some(key::Kind::Symmetric32Nonce(12)), key_id)?
+        .material;
+
+    assert!(serialized_material.len() == 44);
+    increment_nonce(&mut serialized_material[32..])?;
+
+    // also in the owner's file, if the key was granted
+    keystore.update_key(secrecy, key_id, &serialized_material)?;
+    Ok(serialized_material)
+}
+
 #[cfg(feature = "chacha8-poly1305")]
 impl Decrypt for super::Chacha8Poly1305
 {
@@ -108,34 +124,8 @@ impl Encrypt for super::Chacha8Poly1305
 
 
         // load key and nonce
-        let secrecy = key::Secrecy::Secret;
-        let key_kind = key::Kind::Symmetric32Nonce(12);
-        let key_id = &request.key.object_id;
-        let mut serialized_material = keystore
-            .load_key(secrecy, Some(key_kind), key_id)?
-            .material;
-        let serialized = serialized_material.as_mut();
-
-        assert!(serialized.len() == 44);
-
-        // no panic by above early return
-        let location = keystore.location(secrecy, key_id).unwrap();
-
-        // let key_id = request.key.object_id;
-        // let path = keystore.prepare_path_for_key(key::Secrecy::Secret, &key_id)?;
-        // let mut serialized = [0u8; 44];
-        // debug!("loading encryption key: {:?}", &path);
-
-        {
-            let nonce = &mut serialized[32..];
-            // increment nonce
-            increment_nonce(nonce)?;
-        }
-        // increment_nonce(&mut serialized[32..])?;
-
-        keystore.overwrite_key(location, secrecy, key_kind, key_id, &serialized)?;
-
-        let (symmetric_key, generated_nonce) = serialized.split_at_mut(32);
+        let mut serialized_material = load_key_advancing_nonce(keystore, &request.key.object_id)?;
+        let (symmetric_key, generated_nonce) = serialized_material.split_at_mut(32);
 
         let nonce = match request.nonce.as_ref() {
             Some(nonce) => nonce.as_ref(),
@@ -169,29 +159,62 @@ impl WrapKey for super::Chacha8Poly1305
     fn wrap_key(keystore: &mut impl Keystore, request: &request::WrapKey)
         -> Result<reply::WrapKey, Error>
     {
-        debug!("trussed: Chacha8Poly1305::WrapKey");
+        use chacha20poly1305::ChaCha8Poly1305;
+        use chacha20poly1305::aead::{AeadInPlace, NewAead};
 
-        // TODO: need to check both secret and private keys
-        let serialized_key = keystore
-            .load_key(key::Secrecy::Secret, None, &request.key.object_id)?;
+        debug!("trussed: Chacha8Poly1305::WrapKey");
 
-        let message = serialized_key.serialize().try_convert_into().unwrap();
+        // extractability is checked by the service
+        let key = keystore.load_key(key::Secrecy::Secret, None, &request.key.object_id)?;
 
-        let encryption_request = request::Encrypt {
-            mechanism: Mechanism::Chacha8Poly1305,
-            key: request.wrapping_key,
-            message,
-            associated_data: ShortData::new(),
-            nonce: None,
-        };
-        let encryption_reply = <super::Chacha8Poly1305>::encrypt(keystore, &encryption_request)?;
+        let serialized_material = load_key_advancing_nonce(keystore, &request.wrapping_key.object_id)?;
+        let (symmetric_key, nonce) = serialized_material.split_at(32);
+        let aead = ChaCha8Poly1305::new(&GenericArray::clone_from_slice(symmetric_key));
 
-        let wrapped_key = crate::postcard_serialize_bytes(&encryption_reply).map_err(|_| Error::CborError)?;
+        let wrapped_key = wrap::wrap(Mechanism::Chacha8Poly1305, &key, &request.associated_data, |material, authenticated_data| {
+            let tag = aead.encrypt_in_place_detached(
+                &GenericArray::clone_from_slice(nonce),
+                authenticated_data,
+                material,
+            ).map_err(|_| Error::AeadError)?;
+            Ok((ShortData::try_from_slice(nonce).unwrap(), ShortData::try_from_slice(&tag).unwrap()))
+        })?;
 
         Ok(reply::WrapKey { wrapped_key })
     }
 }
 
+/// A key wrapped before the envelope, as the postcard-serialized `reply::Encrypt` of the
+/// serialized key, like the credential IDs of FIDO authenticators.
+///
+/// None if it fails authentication.
+#[cfg(feature = "chacha8-poly1305")]
+fn unwrap_legacy(aead: &chacha20poly1305::ChaCha8Poly1305, wrapped_key: &[u8], associated_data: &[u8])
+    -> Result<Option<key::Key>, Error>
+{
+    use chacha20poly1305::aead::AeadInPlace;
+
+    let encrypted: reply::Encrypt = crate::postcard_deserialize(wrapped_key)
+        .map_err(|_| Error::WrappedKeyInvalid)?;
+    // the bytes must be exactly this, not an envelope of an unknown version
+    let reserialized: Message = crate::postcard_serialize_bytes(&encrypted)
+        .map_err(|_| Error::WrappedKeyInvalid)?;
+    if &reserialized[..] != wrapped_key || encrypted.nonce.len() != 12 || encrypted.tag.len() != 16 {
+        return Err(Error::WrappedKeyInvalid);
+    }
+
+    let mut serialized_key = encrypted.ciphertext;
+    if aead.decrypt_in_place_detached(
+        GenericArray::from_slice(&encrypted.nonce),
+        associated_data,
+        &mut serialized_key,
+        GenericArray::from_slice(&encrypted.tag),
+    ).is_err() {
+        return Ok(None);
+    }
+    key::Key::try_deserialize(&serialized_key).map(Some).map_err(|_| Error::WrappedKeyInvalid)
+}
+
 #[cfg(feature = "chacha8-poly1305")]
 impl UnwrapKey for super::Chacha8Poly1305
 {
@@ -199,38 +222,57 @@ impl UnwrapKey for super::Chacha8Poly1305
     fn unwrap_key(keystore: &mut impl Keystore, request: &request::UnwrapKey)
         -> Result<reply::UnwrapKey, Error>
     {
-        let reply::Encrypt { ciphertext, nonce, tag } = crate::postcard_deserialize(
-            &request.wrapped_key).map_err(|_| Error::CborError)?;
-
-        let decryption_request = request::Decrypt {
-            mechanism: Mechanism::Chacha8Poly1305,
-            key: request.wrapping_key,
-            message: ciphertext,
-            associated_data: request.associated_data.clone(),
-            nonce,
-            tag,
-        };
+        use chacha20poly1305::ChaCha8Poly1305;
+        use chacha20poly1305::aead::{AeadInPlace, NewAead};
 
-        let serialized_key = if let Some(serialized_key) =
-            <super::Chacha8Poly1305>::decrypt(keystore, &decryption_request)?.plaintext {
-            serialized_key
-        } else {
-            return Ok(reply::UnwrapKey { key: None } );
+        let envelope = match request.wrapped_key.first() {
+            Some(&wrap::ENVELOPE_VERSION) => {
+                let envelope = wrap::Envelope::parse(&request.wrapped_key)?;
+                if envelope.mechanism != Mechanism::Chacha8Poly1305 {
+                    return Err(Error::MechanismInvalid);
+                }
+                if envelope.nonce.len() != 12 || envelope.tag.len() != 16 {
+                    return Err(Error::WrappedKeyInvalid);
+                }
+                Some(envelope)
+            }
+            _ => None,
         };
 
-        // TODO: probably change this to returning Option<key> too
-        let key::Key{ flags: _, kind, material } = key::Key::try_deserialize(&serialized_key)?;
+        let serialized_material = keystore
+            .load_key(key::Secrecy::Secret, Some(key::Kind::Symmetric32Nonce(12)), &request.wrapping_key.object_id)?
+            .material;
+        let aead = ChaCha8Poly1305::new(&GenericArray::clone_from_slice(&serialized_material[..32]));
+
+        let key = match envelope {
+            Some(envelope) => {
+                let mut material = key::Material::try_from_slice(envelope.ciphertext)
+                    .map_err(|_| Error::WrappedKeyInvalid)?;
+                // wrong wrapping key or associated data, or tampered
+                aead.decrypt_in_place_detached(
+                    GenericArray::from_slice(envelope.nonce),
+                    &envelope.authenticated_data(&request.associated_data)?,
+                    &mut material,
+                    GenericArray::from_slice(envelope.tag),
+                ).map_err(|_| Error::WrappedKeyInvalid)?;
+                Some(envelope.key(&material)?)
+            }
+            // which signals failed authentication with no key, as before the envelope
+            None => unwrap_legacy(&aead, &request.wrapped_key, &request.associated_data)?,
+        };
+        let key = match key {
+            Some(key) => key,
+            None => return Ok(reply::UnwrapKey { key: None }),
+        };
 
-        // TODO: need to check both secret and private keys
         let key_id = keystore.store_key(
             request.attributes.persistence,
-            // using for signing keys... we need to know
             key::Secrecy::Secret,
-            kind,
-            &material,
+            wrap::unwrapped_info(&key, &request.attributes),
+            &key.material,
         )?;
 
-        Ok(reply::UnwrapKey { key: Some(ObjectHandle { object_id: key_id }) } )
+        Ok(reply::UnwrapKey { key: Some(ObjectHandle::new(key_id)) })
     }
 }
 
@@ -49,11 +49,12 @@ impl DeriveKey for super::Ed255
 
         let public_id = keystore.store_key(
             request.attributes.persistence,
-            key::Secrecy::Public, key::Kind::Ed255,
+            key::Secrecy::Public,
+            key::Info::from(key::Kind::Ed255).with_label(request.attributes.label.as_ref()),
             keypair.public.as_bytes())?;
 
         Ok(reply::DeriveKey {
-            key: ObjectHandle { object_id: public_id },
+            key: ObjectHandle::new(public_id),
         })
     }
 }
@@ -69,25 +70,69 @@ impl DeserializeKey for super::Ed255
           // - serialized_key: Message
           // - attributes: StorageAttributes
 
-        if request.format != KeySerialization::Raw {
-            return Err(Error::InternalError);
-        }
+        let serialized_key: [u8; 32] = match request.format {
+            KeySerialization::Der | KeySerialization::Pem => {
+                let (secrecy, material) = der::deserialize_key(
+                    der::Algorithm::Ed255, request.format, &request.serialized_key)?;
+                // private keys are imported with `ImportKey`
+                if secrecy == key::Secrecy::Secret {
+                    return Err(Error::InvalidSerializedKey);
+                }
+                material.as_ref().try_into().map_err(|_| Error::InvalidSerializedKey)?
+            }
 
-        if request.serialized_key.len() != 32 {
-            return Err(Error::InvalidSerializedKey);
-        }
+            KeySerialization::Jwk => {
+                jwk::deserialize_public_key(der::Algorithm::Ed255, &request.serialized_key)?
+                    .as_ref().try_into().unwrap()
+            }
+
+            KeySerialization::Raw => {
+                if request.serialized_key.len() != 32 {
+                    return Err(Error::InvalidSerializedKey);
+                }
+                request.serialized_key[..32].try_into().unwrap()
+            }
+
+            _ => { return Err(Error::InternalError); }
+        };
 
-        let serialized_key: [u8; 32] = request.serialized_key[..32].try_into().unwrap();
         let public_key = salty::signature::PublicKey::try_from(&serialized_key)
             .map_err(|_| Error::InvalidSerializedKey)?;
 
         let public_id = keystore.store_key(
             request.attributes.persistence,
-            key::Secrecy::Public, key::Kind::Ed255,
+            key::Secrecy::Public,
+            key::Info::from(key::Kind::Ed255).with_label(request.attributes.label.as_ref()),
             public_key.as_bytes())?;
 
         Ok(reply::DeserializeKey {
-            key: ObjectHandle { object_id: public_id },
+            key: ObjectHandle::new(public_id),
+        })
+    }
+}
+
+#[cfg(feature = "ed255")]
+impl ImportKey for super::Ed255
+{
+    #[inline(never)]
+    fn import_key(keystore: &mut impl Keystore, request: &request::ImportKey)
+        -> Result<reply::ImportKey, Error>
+    {
+        let material = der::deserialize_private_key(
+            der::Algorithm::Ed255, request.format, &request.serialized_key)?;
+        // any seed is valid
+        let seed: [u8; 32] = material.as_ref().try_into()
+            .map_err(|_| Error::InvalidSerializedKey)?;
+
+        // not LOCAL, as the key was generated elsewhere
+        let private_id = keystore.store_key(
+            request.attributes.persistence,
+            key::Secrecy::Secret,
+            key::Info::from(key::Kind::Ed255).with_attributes(&request.attributes),
+            &seed)?;
+
+        Ok(reply::ImportKey {
+            key: ObjectHandle::new(private_id),
         })
     }
 }
@@ -110,11 +155,11 @@ impl GenerateKey for super::Ed255
         let key_id = keystore.store_key(
             request.attributes.persistence,
             key::Secrecy::Secret,
-            key::Info::from(key::Kind::Ed255).with_local_flag(),
+            key::Info::from(key::Kind::Ed255).with_local_flag().with_attributes(&request.attributes),
             &seed)?;
 
         // return handle
-        Ok(reply::GenerateKey { key: ObjectHandle { object_id: key_id } })
+        Ok(reply::GenerateKey { key: ObjectHandle::new(key_id) })
     }
 }
 
@@ -145,6 +190,21 @@ impl SerializeKey for super::Ed255
                 serialized_key
             }
 
+            KeySerialization::Der | KeySerialization::Pem => {
+                der::serialize_public_key(
+                    &der::SerializedSubjectPublicKey::Ed255(*public_key.as_bytes()),
+                    request.format,
+                )?
+            }
+
+            KeySerialization::Jwk => {
+                jwk::serialize_public_key(der::Algorithm::Ed255, public_key.as_bytes())?
+            }
+
+            KeySerialization::OpenSsh => {
+                ssh::serialize_public_key(der::Algorithm::Ed255, public_key.as_bytes())?
+            }
+
             _ => { return Err(Error::InternalError); }
         };
 
@@ -178,17 +238,22 @@ impl Sign for super::Ed255
         // Ed255::Sign`, i.e. VEERY long
         // debug!("trussed::{}:{} Ed255::Sign", file!(), line!()).ok();
         // debug!("trussed: Ed255::Sign").ok();
-        // if let SignatureSerialization::Raw = request.format {
-        // } else {
-        //     return Err(Error::InvalidSerializationFormat);
-        // }
+        if let SignatureSerialization::Asn1Der = request.format {
+            return Err(Error::InvalidSerializationFormat);
+        }
 
         let key_id = request.key.object_id;
 
         let keypair = load_keypair(keystore, &key_id)?;
 
         let native_signature = keypair.sign(&request.message);
-        let our_signature = Signature::try_from_slice(&native_signature.to_bytes()).unwrap();
+        let our_signature = match request.format {
+            SignatureSerialization::OpenSsh => ssh::serialize_signature(
+                der::Algorithm::Ed255, &native_signature.to_bytes(), None)?,
+            SignatureSerialization::OpenSshSk { flags, counter } => ssh::serialize_signature(
+                der::Algorithm::Ed255, &native_signature.to_bytes(), Some(ssh::SkTrailer { flags, counter }))?,
+            _ => Signature::try_from_slice(&native_signature.to_bytes()).unwrap(),
+        };
 
         // hprintln!("Ed255 signature:").ok();
         // hprintln!("msg: {:?}", &request.message).ok();
@@ -207,24 +272,25 @@ impl Verify for super::Ed255
     fn verify(keystore: &mut impl Keystore, request: &request::Verify)
         -> Result<reply::Verify, Error>
     {
-        if let SignatureSerialization::Raw = request.format {
-        } else {
-            return Err(Error::InvalidSerializationFormat);
-        }
-
-        if request.signature.len() != salty::constants::SIGNATURE_SERIALIZED_LENGTH {
-            return Err(Error::WrongSignatureLength);
-        }
+        let (signature_array, trailer): ([u8; salty::constants::SIGNATURE_SERIALIZED_LENGTH], _) = match request.format {
+            SignatureSerialization::Asn1Der => return Err(Error::InvalidSerializationFormat),
+            SignatureSerialization::OpenSsh | SignatureSerialization::OpenSshSk { .. } => {
+                let sk = request.format != SignatureSerialization::OpenSsh;
+                ssh::deserialize_signature(der::Algorithm::Ed255, &request.signature, sk)
+                    .map_err(|_| Error::WrongSignatureLength)?
+            }
+            _ => (request.signature.as_ref().try_into()
+                .map_err(|_| Error::WrongSignatureLength)?, None),
+        };
 
         let key_id = request.key.object_id;
         let public_key = load_public_key(keystore, &key_id)?;
 
-        let mut signature_array = [0u8; salty::constants::SIGNATURE_SERIALIZED_LENGTH];
-        signature_array.copy_from_slice(request.signature.as_ref());
         let salty_signature = salty::signature::Signature::from(&signature_array);
 
         Ok(reply::Verify { valid:
-            public_key.verify(&request.message, &salty_signature).is_ok()
+            trailer == ssh::SkTrailer::of(request.format)
+                && public_key.verify(&request.message, &salty_signature).is_ok()
         })
     }
 }
@@ -234,6 +300,8 @@ impl DeriveKey for super::Ed255 {}
 #[cfg(not(feature = "ed255"))]
 impl GenerateKey for super::Ed255 {}
 #[cfg(not(feature = "ed255"))]
+impl ImportKey for super::Ed255 {}
+#[cfg(not(feature = "ed255"))]
 impl Sign for super::Ed255 {}
 #[cfg(not(feature = "ed255"))]
 impl Verify for super::Ed255 {}
@@ -27,10 +27,11 @@ impl DeriveKey for super::HmacSha1
         let derived_key: [u8; 20] = mac.finalize().into_bytes().try_into().map_err(|_| Error::InternalError)?;
         let key_id = keystore.store_key(
             request.attributes.persistence,
-            key::Secrecy::Secret, key::Kind::Symmetric(20),
+            key::Secrecy::Secret,
+            key::Info::from(key::Kind::Symmetric(20)).with_attributes(&request.attributes),
             &derived_key)?;
 
-        Ok(reply::DeriveKey { key: ObjectHandle { object_id: key_id } })
+        Ok(reply::DeriveKey { key: ObjectHandle::new(key_id) })
 
     }
 }
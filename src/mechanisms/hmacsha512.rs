@@ -26,10 +26,11 @@ impl DeriveKey for super::HmacSha512
         derived_key.copy_from_slice(&mac.finalize().into_bytes());//.try_into().map_err(|_| Error::InternalError)?;
         let key_id = keystore.store_key(
             request.attributes.persistence,
-            key::Secrecy::Secret, key::Kind::Symmetric(64),
+            key::Secrecy::Secret,
+            key::Info::from(key::Kind::Symmetric(64)).with_attributes(&request.attributes),
             &derived_key)?;
 
-        Ok(reply::DeriveKey { key: ObjectHandle { object_id: key_id } })
+        Ok(reply::DeriveKey { key: ObjectHandle::new(key_id) })
     }
 }
 
@@ -7,16 +7,22 @@ use crate::service::*;
 use crate::types::*;
 
 #[inline(never)]
-fn load_secret_key(keystore: &mut impl Keystore, key_id: &UniqueId)
-    -> Result<p256_cortex_m4::SecretKey, Error>
+fn load_secret_scalar(keystore: &mut impl Keystore, key_id: &UniqueId)
+    -> Result<[u8; 32], Error>
 {
-
     // info_now!("loading keypair");
-    let secret_scalar: [u8; 32] = keystore
+    keystore
         .load_key(key::Secrecy::Secret, Some(key::Kind::P256), &key_id)?
         .material.as_ref()
         .try_into()
-        .map_err(|_| Error::InternalError)?;
+        .map_err(|_| Error::InternalError)
+}
+
+#[inline(never)]
+fn load_secret_key(keystore: &mut impl Keystore, key_id: &UniqueId)
+    -> Result<p256_cortex_m4::SecretKey, Error>
+{
+    let secret_scalar = load_secret_scalar(keystore, key_id)?;
 
     let secret_key = p256_cortex_m4::SecretKey::from_bytes(&secret_scalar)
         .map_err(|_| Error::InternalError)?;
@@ -54,11 +60,12 @@ impl Agree for super::P256
 
         let key_id = keystore.store_key(
             request.attributes.persistence,
-            key::Secrecy::Secret, key::Kind::Shared(32),
+            key::Secrecy::Secret,
+            key::Info::from(key::Kind::Shared(32)).with_attributes(&request.attributes),
             shared_secret.as_bytes())?;
 
         // return handle
-        Ok(reply::Agree { shared_secret: ObjectHandle { object_id: key_id } })
+        Ok(reply::Agree { shared_secret: ObjectHandle::new(key_id) })
     }
 }
 
@@ -76,11 +83,12 @@ impl DeriveKey for super::P256
 
         let public_id = keystore.store_key(
             request.attributes.persistence,
-            key::Secrecy::Public, key::Kind::P256,
+            key::Secrecy::Public,
+            key::Info::from(key::Kind::P256).with_label(request.attributes.label.as_ref()),
             &public_key.to_compressed_sec1_bytes())?;
 
         Ok(reply::DeriveKey {
-            key: ObjectHandle { object_id: public_id },
+            key: ObjectHandle::new(public_id),
         })
     }
 }
@@ -129,6 +137,14 @@ impl DeserializeKey for super::P256
                     .map_err(|_| Error::InvalidSerializedKey)?
             }
 
+            KeySerialization::Jwk => {
+                let serialized_key = jwk::deserialize_public_key(
+                    der::Algorithm::P256, &request.serialized_key)?;
+
+                p256_cortex_m4::PublicKey::from_untagged_bytes(&serialized_key)
+                    .map_err(|_| Error::InvalidSerializedKey)?
+            }
+
             KeySerialization::Raw => {
                 if request.serialized_key.len() != 64 {
                     return Err(Error::InvalidSerializedKey);
@@ -141,17 +157,61 @@ impl DeserializeKey for super::P256
                     .map_err(|_| Error::InvalidSerializedKey)?
             }
 
+            KeySerialization::Der | KeySerialization::Pem => {
+                let (secrecy, material) = der::deserialize_key(
+                    der::Algorithm::P256, request.format, &request.serialized_key)?;
+
+                // private keys are imported with `ImportKey`
+                if secrecy == key::Secrecy::Secret {
+                    return Err(Error::InvalidSerializedKey);
+                }
+
+                match material.as_ref() {
+                    [0x04, untagged @ ..] => p256_cortex_m4::PublicKey::from_untagged_bytes(untagged),
+                    compressed => p256_cortex_m4::PublicKey::from_sec1_bytes(compressed),
+                }.map_err(|_| Error::InvalidSerializedKey)?
+            }
+
             _ => { return Err(Error::InternalError); }
         };
 
         let public_id = keystore.store_key(
             request.attributes.persistence,
-            key::Secrecy::Public, key::Kind::P256,
+            key::Secrecy::Public,
+            key::Info::from(key::Kind::P256).with_label(request.attributes.label.as_ref()),
             &public_key.to_compressed_sec1_bytes())?;
 
 
         Ok(reply::DeserializeKey {
-            key: ObjectHandle { object_id: public_id },
+            key: ObjectHandle::new(public_id),
+        })
+    }
+}
+
+#[cfg(feature = "p256")]
+impl ImportKey for super::P256
+{
+    #[inline(never)]
+    fn import_key(keystore: &mut impl Keystore, request: &request::ImportKey)
+        -> Result<reply::ImportKey, Error>
+    {
+        let material = der::deserialize_private_key(
+            der::Algorithm::P256, request.format, &request.serialized_key)?;
+        let secret_scalar: [u8; 32] = material.as_ref().try_into()
+            .map_err(|_| Error::InvalidSerializedKey)?;
+        // validates the scalar
+        p256_cortex_m4::SecretKey::from_bytes(&secret_scalar)
+            .map_err(|_| Error::InvalidSerializedKey)?;
+
+        // not LOCAL, as the key was generated elsewhere
+        let private_id = keystore.store_key(
+            request.attributes.persistence,
+            key::Secrecy::Secret,
+            key::Info::from(key::Kind::P256).with_attributes(&request.attributes),
+            &secret_scalar)?;
+
+        Ok(reply::ImportKey {
+            key: ObjectHandle::new(private_id),
         })
     }
 }
@@ -169,12 +229,12 @@ impl GenerateKey for super::P256
         let key_id = keystore.store_key(
             request.attributes.persistence,
             key::Secrecy::Secret,
-            key::Info::from(key::Kind::P256).with_local_flag(),
+            key::Info::from(key::Kind::P256).with_local_flag().with_attributes(&request.attributes),
             &unsafe { keypair.secret.to_bytes()  },
         )?;
 
         // return handle
-        Ok(reply::GenerateKey { key: ObjectHandle { object_id: key_id } })
+        Ok(reply::GenerateKey { key: ObjectHandle::new(key_id) })
     }
 
 }
@@ -217,6 +277,26 @@ impl SerializeKey for super::P256
                 serialized_key.extend_from_slice(&public_key.to_compressed_sec1_bytes()).map_err(|_| Error::InternalError)?;
                 serialized_key
             }
+            KeySerialization::Jwk | KeySerialization::OpenSsh => {
+                let mut serialized_key = [0u8; 64];
+                serialized_key[..32].copy_from_slice(&public_key.x());
+                serialized_key[32..].copy_from_slice(&public_key.y());
+                if request.format == KeySerialization::Jwk {
+                    jwk::serialize_public_key(der::Algorithm::P256, &serialized_key)?
+                } else {
+                    ssh::serialize_public_key(der::Algorithm::P256, &serialized_key)?
+                }
+            }
+            KeySerialization::Der | KeySerialization::Pem => {
+                // uncompressed, as RFC 5480 only mandates support for this form
+                let mut uncompressed = [0x04; 65];
+                uncompressed[1..33].copy_from_slice(&public_key.x());
+                uncompressed[33..].copy_from_slice(&public_key.y());
+                der::serialize_public_key(
+                    &der::SerializedSubjectPublicKey::P256Uncompressed(uncompressed),
+                    request.format,
+                )?
+            }
         };
 
         Ok(reply::SerializeKey { serialized_key })
@@ -236,6 +316,159 @@ impl Exists for super::P256
     }
 }
 
+/// Group order of NIST P-256, big-endian.
+const P256_ORDER: [u8; 32] = hex_literal::hex!(
+    "FFFFFFFF 00000000 FFFFFFFF FFFFFFFF BCE6FAAD A7179E84 F3B9CAC2 FC632551");
+
+type HmacSha256 = hmac::Hmac<sha2::Sha256>;
+
+fn hmac_sha256(key: &[u8; 32], parts: &[&[u8]]) -> [u8; 32] {
+    use hmac::{Mac, NewMac};
+    // HMAC accepts keys of any length
+    let mut mac = HmacSha256::new_varkey(key).unwrap();
+    for part in parts {
+        mac.update(part);
+    }
+    let mut output = [0u8; 32];
+    output.copy_from_slice(&mac.finalize().into_bytes());
+    output
+}
+
+/// Deterministic ECDSA nonces following [RFC 6979][rfc6979], for P-256 with SHA-256.
+///
+/// This is packaged as an RNG, to be passed to `p256_cortex_m4::SecretKey::sign_prehashed`:
+/// the first draw yields the candidate `k` of section 3.2, step h. Should the signer reject
+/// it (`k = 0` or `k >= n`) and draw again, the generator continues as in step h.3.
+///
+/// Passing `extra_entropy` gives the "hedged" variant of section 3.6, which no longer
+/// produces reproducible signatures, but does not rely on the quality of the entropy either.
+///
+/// [rfc6979]: https://tools.ietf.org/html/rfc6979
+pub(crate) struct Rfc6979 {
+    k: [u8; 32],
+    v: [u8; 32],
+    fresh: bool,
+}
+
+impl Rfc6979 {
+    pub fn new(secret_scalar: &[u8; 32], digest: &[u8; 32], extra_entropy: Option<&[u8]>) -> Self {
+        let h1 = Self::bits2octets(digest);
+        let extra_entropy = extra_entropy.unwrap_or(&[]);
+
+        // steps b. to g.
+        let mut k = [0u8; 32];
+        let mut v = [1u8; 32];
+        for round in [0x00u8, 0x01].iter() {
+            k = hmac_sha256(&k, &[&v[..], &[*round][..], &secret_scalar[..], &h1[..], extra_entropy]);
+            v = hmac_sha256(&k, &[&v[..]]);
+        }
+
+        Self { k, v, fresh: true }
+    }
+
+    /// As qlen = hlen = 256, this is a single conditional subtraction of the group order.
+    fn bits2octets(digest: &[u8; 32]) -> [u8; 32] {
+        let mut reduced = *digest;
+        if reduced >= P256_ORDER {
+            let mut borrow = 0i16;
+            for i in (0..32).rev() {
+                let difference = reduced[i] as i16 - P256_ORDER[i] as i16 - borrow;
+                borrow = if difference < 0 { 1 } else { 0 };
+                reduced[i] = difference as u8;
+            }
+        }
+        reduced
+    }
+}
+
+impl rand_core::RngCore for Rfc6979 {
+    fn next_u32(&mut self) -> u32 {
+        rand_core::impls::next_u32_via_fill(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        rand_core::impls::next_u64_via_fill(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(32) {
+            // step h.3., the previous candidate was not accepted
+            if !self.fresh {
+                self.k = hmac_sha256(&self.k, &[&self.v[..], &[0x00][..]]);
+                self.v = hmac_sha256(&self.k, &[&self.v[..]]);
+            }
+            self.fresh = false;
+
+            // step h.2., as qlen = hlen, a single block suffices
+            self.v = hmac_sha256(&self.k, &[&self.v[..]]);
+            let l = chunk.len();
+            chunk.copy_from_slice(&self.v[..l]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl rand_core::CryptoRng for Rfc6979 {}
+
+/// Shared implementation of the P256 signing mechanisms.
+///
+/// All of them derive the nonce via RFC 6979; unless `deterministic` is set, this is
+/// hedged with fresh output of the DRBG, so a weak TRNG can not expose the key.
+#[inline(never)]
+fn sign_with_rfc6979_nonce(keystore: &mut impl Keystore, request: &request::Sign, prehashed: bool, deterministic: bool)
+    -> Result<reply::Sign, Error>
+{
+    let key_id = request.key.object_id;
+
+    let secret_scalar = load_secret_scalar(keystore, &key_id)?;
+    let secret_key = p256_cortex_m4::SecretKey::from_bytes(&secret_scalar)
+        .map_err(|_| Error::InternalError)?;
+
+    let digest: [u8; 32] = if prehashed {
+        request.message.as_ref().try_into().map_err(|_| Error::WrongMessageLength)?
+    } else {
+        use sha2::Digest;
+        let mut digest = [0u8; 32];
+        digest.copy_from_slice(&sha2::Sha256::digest(&request.message));
+        digest
+    };
+
+    let mut extra_entropy = [0u8; 32];
+    let mut nonces = if deterministic {
+        Rfc6979::new(&secret_scalar, &digest, None)
+    } else {
+        keystore.drbg().fill_bytes(&mut extra_entropy);
+        Rfc6979::new(&secret_scalar, &digest, Some(&extra_entropy))
+    };
+    let signature = secret_key.sign_prehashed(&digest, &mut nonces);
+
+    // debug_now!("making signature");
+    let serialized_signature = match request.format {
+        SignatureSerialization::Asn1Der => {
+            let mut buffer = [0u8; 72];
+            let l = signature.to_sec1_bytes(&mut buffer);
+            Signature::try_from_slice(&buffer[..l]).unwrap()
+        }
+        SignatureSerialization::Cose | SignatureSerialization::Jose | SignatureSerialization::Raw => {
+            Signature::try_from_slice(&signature.to_untagged_bytes()).unwrap()
+        }
+        SignatureSerialization::OpenSsh => {
+            ssh::serialize_signature(der::Algorithm::P256, &signature.to_untagged_bytes(), None)?
+        }
+        SignatureSerialization::OpenSshSk { flags, counter } => {
+            ssh::serialize_signature(der::Algorithm::P256, &signature.to_untagged_bytes(),
+                Some(ssh::SkTrailer { flags, counter }))?
+        }
+    };
+
+    // return signature
+    Ok(reply::Sign { signature: serialized_signature })
+}
+
 #[cfg(feature = "p256")]
 impl Sign for super::P256
 {
@@ -243,27 +476,8 @@ impl Sign for super::P256
     fn sign(keystore: &mut impl Keystore, request: &request::Sign)
         -> Result<reply::Sign, Error>
     {
-        let key_id = request.key.object_id;
-
-        let secret_key = load_secret_key(keystore, &key_id)?;
-        let signature = secret_key.sign(&request.message, keystore.drbg());
-
-        // debug_now!("making signature");
-        let serialized_signature = match request.format {
-            SignatureSerialization::Asn1Der => {
-                let mut buffer = [0u8; 72];
-                let l = signature.to_sec1_bytes(&mut buffer);
-                Signature::try_from_slice(&buffer[..l]).unwrap()
-            }
-            SignatureSerialization::Raw => {
-                Signature::try_from_slice(&signature.to_untagged_bytes()).unwrap()
-            }
-        };
-
-        // return signature
-        Ok(reply::Sign { signature: serialized_signature })
+        sign_with_rfc6979_nonce(keystore, request, false, false)
     }
-
 }
 
 #[cfg(feature = "p256")]
@@ -273,27 +487,111 @@ impl Sign for super::P256Prehashed
     fn sign(keystore: &mut impl Keystore, request: &request::Sign)
         -> Result<reply::Sign, Error>
     {
-        let key_id = request.key.object_id;
+        sign_with_rfc6979_nonce(keystore, request, true, false)
+    }
+}
 
-        let secret_key = load_secret_key(keystore, &key_id)?;
-        let signature = secret_key.sign_prehashed(&request.message, keystore.drbg());
+#[cfg(feature = "p256")]
+impl Sign for super::P256Deterministic
+{
+    #[inline(never)]
+    fn sign(keystore: &mut impl Keystore, request: &request::Sign)
+        -> Result<reply::Sign, Error>
+    {
+        sign_with_rfc6979_nonce(keystore, request, false, true)
+    }
+}
 
-        // debug_now!("making signature");
-        let serialized_signature = match request.format {
-            SignatureSerialization::Asn1Der => {
-                let mut buffer = [0u8; 72];
-                let l = signature.to_sec1_bytes(&mut buffer);
-                Signature::try_from_slice(&buffer[..l]).unwrap()
-            }
-            SignatureSerialization::Raw => {
-                Signature::try_from_slice(&signature.to_untagged_bytes()).unwrap()
+#[cfg(feature = "p256")]
+impl Sign for super::P256PrehashedDeterministic
+{
+    #[inline(never)]
+    fn sign(keystore: &mut impl Keystore, request: &request::Sign)
+        -> Result<reply::Sign, Error>
+    {
+        sign_with_rfc6979_nonce(keystore, request, true, true)
+    }
+}
+
+/// Converts an ASN.1 DER encoded `ECDSA-Sig-Value` to the concatenation of `r` and `s`.
+///
+/// As the encoding is at most 72 bytes long, all lengths are in short form.
+/// Encodings that are BER but not DER, e.g. with non-minimal INTEGERs, are rejected.
+fn asn1_der_to_raw(der: &[u8]) -> Option<[u8; 64]> {
+    fn integer<'a>(der: &'a [u8], raw: &mut [u8]) -> Option<&'a [u8]> {
+        if der.len() < 2 || der[0] != 0x02 {
+            return None;
+        }
+        let l = der[1] as usize;
+        if l == 0 || l > 33 || der.len() < 2 + l {
+            return None;
+        }
+        let (mut value, rest) = der[2..].split_at(l);
+        // r and s are positive
+        if value[0] & 0x80 != 0 {
+            return None;
+        }
+        // a leading zero byte is only allowed, and required, to keep the INTEGER positive
+        if value[0] == 0 && l > 1 {
+            if value[1] & 0x80 == 0 {
+                return None;
             }
-        };
+            value = &value[1..];
+        }
+        if value.len() > 32 {
+            return None;
+        }
+        raw[32 - value.len()..].copy_from_slice(value);
+        Some(rest)
+    }
 
-        // return signature
-        Ok(reply::Sign { signature: serialized_signature })
+    if der.len() < 2 || der[0] != 0x30 || der[1] as usize != der.len() - 2 {
+        return None;
     }
+    let mut raw = [0u8; 64];
+    let rest = integer(&der[2..], &mut raw[..32])?;
+    let rest = integer(rest, &mut raw[32..])?;
+    if !rest.is_empty() {
+        return None;
+    }
+    Some(raw)
+}
+
+#[inline(never)]
+fn verify(keystore: &mut impl Keystore, request: &request::Verify, prehashed: bool)
+    -> Result<reply::Verify, Error>
+{
+    let key_id = request.key.object_id;
+
+    let public_key = load_public_key(keystore, &key_id)?;
 
+    let mut trailer = None;
+    let signature = match request.format {
+        SignatureSerialization::Asn1Der => {
+            let raw = asn1_der_to_raw(&request.signature)
+                .ok_or(Error::WrongSignatureLength)?;
+            p256_cortex_m4::Signature::from_untagged_bytes(&raw)
+        }
+        SignatureSerialization::Cose | SignatureSerialization::Jose | SignatureSerialization::Raw => {
+            p256_cortex_m4::Signature::from_untagged_bytes(&request.signature)
+        }
+        SignatureSerialization::OpenSsh | SignatureSerialization::OpenSshSk { .. } => {
+            let sk = request.format != SignatureSerialization::OpenSsh;
+            let (raw, sk_trailer) = ssh::deserialize_signature(der::Algorithm::P256, &request.signature, sk)
+                .map_err(|_| Error::WrongSignatureLength)?;
+            trailer = sk_trailer;
+            p256_cortex_m4::Signature::from_untagged_bytes(&raw)
+        }
+    }
+        // well... or wrong encoding, need r,s in range 1..=n-1
+        .map_err(|_| Error::WrongSignatureLength)?;
+
+    let valid = trailer == ssh::SkTrailer::of(request.format) && if prehashed {
+        public_key.verify_prehashed(&request.message, &signature)
+    } else {
+        public_key.verify(&request.message, &signature)
+    };
+    Ok(reply::Verify { valid } )
 }
 
 #[cfg(feature = "p256")]
@@ -303,22 +601,18 @@ impl Verify for super::P256
     fn verify(keystore: &mut impl Keystore, request: &request::Verify)
         -> Result<reply::Verify, Error>
     {
-        let key_id = request.key.object_id;
-
-        let public_key = load_public_key(keystore, &key_id)?;
-
-        let signature = p256_cortex_m4::Signature::from_untagged_bytes(&request.signature)
-            // well... or wrong encoding, need r,s in range 1..=n-1
-            .map_err(|_| Error::WrongSignatureLength)?;
-
-        if let SignatureSerialization::Raw = request.format {
-        } else {
-            // well more TODO
-            return Err(Error::InvalidSerializationFormat);
-        }
+        verify(keystore, request, false)
+    }
+}
 
-        let valid = public_key.verify(&request.message, &signature);
-        Ok(reply::Verify { valid } )
+#[cfg(feature = "p256")]
+impl Verify for super::P256Prehashed
+{
+    #[inline(never)]
+    fn verify(keystore: &mut impl Keystore, request: &request::Verify)
+        -> Result<reply::Verify, Error>
+    {
+        verify(keystore, request, true)
     }
 }
 
@@ -329,6 +623,51 @@ impl DeriveKey for super::P256 {}
 #[cfg(not(feature = "p256"))]
 impl GenerateKey for super::P256 {}
 #[cfg(not(feature = "p256"))]
+impl ImportKey for super::P256 {}
+#[cfg(not(feature = "p256"))]
 impl Sign for super::P256 {}
 #[cfg(not(feature = "p256"))]
+impl Sign for super::P256Prehashed {}
+#[cfg(not(feature = "p256"))]
+impl Sign for super::P256Deterministic {}
+#[cfg(not(feature = "p256"))]
+impl Sign for super::P256PrehashedDeterministic {}
+#[cfg(not(feature = "p256"))]
 impl Verify for super::P256 {}
+#[cfg(not(feature = "p256"))]
+impl Verify for super::P256Prehashed {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn asn1_der_signatures() {
+        // r = 0x80 (needs a leading zero), s = 0x7F
+        let mut raw = [0u8; 64];
+        raw[31] = 0x80;
+        raw[63] = 0x7F;
+        assert_eq!(asn1_der_to_raw(&[0x30, 7, 0x02, 2, 0x00, 0x80, 0x02, 1, 0x7F]), Some(raw));
+
+        // r and s of full length, one with a leading zero
+        let mut der = [0u8; 71];
+        der[..4].copy_from_slice(&[0x30, 69, 0x02, 33]);
+        der[4] = 0x00;
+        der[5..37].copy_from_slice(&[0xAB; 32]);
+        der[37..39].copy_from_slice(&[0x02, 32]);
+        der[39..].copy_from_slice(&[0x12; 32]);
+        let raw = asn1_der_to_raw(&der).unwrap();
+        assert_eq!(&raw[..32], &[0xAB; 32]);
+        assert_eq!(&raw[32..], &[0x12; 32]);
+
+        // non-minimal INTEGERs
+        assert_eq!(asn1_der_to_raw(&[0x30, 7, 0x02, 2, 0x00, 0x7F, 0x02, 1, 0x7F]), None);
+        assert_eq!(asn1_der_to_raw(&[0x30, 7, 0x02, 1, 0x7F, 0x02, 2, 0x00, 0x01]), None);
+        // negative INTEGERs
+        assert_eq!(asn1_der_to_raw(&[0x30, 6, 0x02, 1, 0x80, 0x02, 1, 0x7F]), None);
+        assert_eq!(asn1_der_to_raw(&[0x30, 7, 0x02, 1, 0x7F, 0x02, 2, 0xFF, 0x80]), None);
+        // 33 bytes, but the leading zero is not needed
+        der[5] = 0x2B;
+        assert_eq!(asn1_der_to_raw(&der), None);
+    }
+}
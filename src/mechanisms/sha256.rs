@@ -24,11 +24,12 @@ impl DeriveKey for super::Sha256
 
         let key_id = keystore.store_key(
             request.attributes.persistence,
-            key::Secrecy::Secret, key::Kind::Symmetric(32),
+            key::Secrecy::Secret,
+            key::Info::from(key::Kind::Symmetric(32)).with_attributes(&request.attributes),
             &symmetric_key)?;
 
         Ok(reply::DeriveKey {
-            key: ObjectHandle { object_id: key_id },
+            key: ObjectHandle::new(key_id),
         })
     }
 }
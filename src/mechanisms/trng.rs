@@ -18,10 +18,10 @@ impl GenerateKey for super::Trng
         let key_id = keystore.store_key(
             request.attributes.persistence,
             key::Secrecy::Secret,
-            key::Kind::Symmetric(32),
+            key::Info::from(key::Kind::Symmetric(32)).with_local_flag().with_attributes(&request.attributes),
             &entropy)?;
 
-        Ok(reply::GenerateKey { key: ObjectHandle { object_id: key_id } })
+        Ok(reply::GenerateKey { key: ObjectHandle::new(key_id) })
     }
 }
 
@@ -57,11 +57,12 @@ impl Agree for super::X255
 
         let key_id = keystore.store_key(
             request.attributes.persistence,
-            key::Secrecy::Secret, key::Kind::Shared(32),
+            key::Secrecy::Secret,
+            key::Info::from(key::Kind::Shared(32)).with_attributes(&request.attributes),
             &shared_secret)?;
 
         // return handle
-        Ok(reply::Agree { shared_secret: ObjectHandle { object_id: key_id } })
+        Ok(reply::Agree { shared_secret: ObjectHandle::new(key_id) })
     }
 }
 
@@ -80,11 +81,11 @@ impl GenerateKey for super::X255
         let key_id = keystore.store_key(
             request.attributes.persistence,
             key::Secrecy::Secret,
-            key::Info::from(key::Kind::X255).with_local_flag(),
+            key::Info::from(key::Kind::X255).with_local_flag().with_attributes(&request.attributes),
             &seed)?;
 
         // return handle
-        Ok(reply::GenerateKey { key: ObjectHandle { object_id: key_id } })
+        Ok(reply::GenerateKey { key: ObjectHandle::new(key_id) })
     }
 }
 
@@ -116,11 +117,12 @@ impl DeriveKey for super::X255
         let public_key_bytes = public_key.to_bytes();
         let public_id = keystore.store_key(
             request.attributes.persistence,
-            key::Secrecy::Public, key::Kind::X255,
+            key::Secrecy::Public,
+            key::Info::from(key::Kind::X255).with_label(request.attributes.label.as_ref()),
             &public_key_bytes)?;
 
         Ok(reply::DeriveKey {
-            key: ObjectHandle { object_id: public_id },
+            key: ObjectHandle::new(public_id),
         })
     }
 }
@@ -135,14 +137,29 @@ impl SerializeKey for super::X255
         let key_id = request.key.object_id;
         let public_key = load_public_key(keystore, &key_id)?;
 
-        let mut serialized_key = Message::new();
-        match request.format {
+        let serialized_key = match request.format {
             KeySerialization::Raw => {
+                let mut serialized_key = Message::new();
                 serialized_key.extend_from_slice(&public_key.to_bytes()).map_err(|_| Error::InternalError)?;
+                serialized_key
+            }
+
+            KeySerialization::Der | KeySerialization::Pem => {
+                der::serialize_public_key(
+                    &der::SerializedSubjectPublicKey::X255(public_key.to_bytes()),
+                    request.format,
+                )?
             }
 
+            KeySerialization::Jwk => {
+                jwk::serialize_public_key(der::Algorithm::X255, &public_key.to_bytes())?
+            }
+
+            // OpenSSH has no X25519 keys
+            KeySerialization::OpenSsh => { return Err(Error::InvalidSerializationFormat); }
+
             _ => { return Err(Error::InternalError); }
-        }
+        };
 
         Ok(reply::SerializeKey { serialized_key })
     }
@@ -159,25 +176,69 @@ impl DeserializeKey for super::X255
           // - serialized_key: Message
           // - attributes: StorageAttributes
 
-        if request.format != KeySerialization::Raw {
-            return Err(Error::InternalError);
-        }
+        let serialized_key: [u8; 32] = match request.format {
+            KeySerialization::Der | KeySerialization::Pem => {
+                let (secrecy, material) = der::deserialize_key(
+                    der::Algorithm::X255, request.format, &request.serialized_key)?;
+                // private keys are imported with `ImportKey`
+                if secrecy == key::Secrecy::Secret {
+                    return Err(Error::InvalidSerializedKey);
+                }
+                material.as_ref().try_into().map_err(|_| Error::InvalidSerializedKey)?
+            }
+
+            KeySerialization::Jwk => {
+                jwk::deserialize_public_key(der::Algorithm::X255, &request.serialized_key)?
+                    .as_ref().try_into().unwrap()
+            }
 
-        if request.serialized_key.len() != 32 {
-            return Err(Error::InvalidSerializedKey);
-        }
+            KeySerialization::Raw => {
+                if request.serialized_key.len() != 32 {
+                    return Err(Error::InvalidSerializedKey);
+                }
+                request.serialized_key[..32].try_into().unwrap()
+            }
+
+            _ => { return Err(Error::InternalError); }
+        };
 
-        let serialized_key: [u8; 32] = request.serialized_key[..32].try_into().unwrap();
         let public_key = salty::agreement::PublicKey::try_from(serialized_key)
             .map_err(|_| Error::InvalidSerializedKey)?;
 
         let public_id = keystore.store_key(
             request.attributes.persistence,
-            key::Secrecy::Public, key::Kind::X255,
+            key::Secrecy::Public,
+            key::Info::from(key::Kind::X255).with_label(request.attributes.label.as_ref()),
             &public_key.to_bytes())?;
 
         Ok(reply::DeserializeKey {
-            key: ObjectHandle { object_id: public_id },
+            key: ObjectHandle::new(public_id),
+        })
+    }
+}
+
+#[cfg(feature = "x255")]
+impl ImportKey for super::X255
+{
+    // #[inline(never)]
+    fn import_key(keystore: &mut impl Keystore, request: &request::ImportKey)
+        -> Result<reply::ImportKey, Error>
+    {
+        let material = der::deserialize_private_key(
+            der::Algorithm::X255, request.format, &request.serialized_key)?;
+        // any scalar is valid, it is clamped when used
+        let seed: [u8; 32] = material.as_ref().try_into()
+            .map_err(|_| Error::InvalidSerializedKey)?;
+
+        // not LOCAL, as the key was generated elsewhere
+        let private_id = keystore.store_key(
+            request.attributes.persistence,
+            key::Secrecy::Secret,
+            key::Info::from(key::Kind::X255).with_attributes(&request.attributes),
+            &seed)?;
+
+        Ok(reply::ImportKey {
+            key: ObjectHandle::new(private_id),
         })
     }
 }
@@ -195,3 +256,5 @@ impl Derive for super::X255 {}
 impl SerializeKey for super::X255 {}
 #[cfg(not(feature = "x255"))]
 impl DeserializeKey for super::X255 {}
+#[cfg(not(feature = "x255"))]
+impl ImportKey for super::X255 {}
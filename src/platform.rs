@@ -8,6 +8,8 @@
 
 // pub use rand_core::{CryptoRng, RngCore};
 pub use rand_core::{CryptoRng, RngCore};
+use littlefs2::path::Path;
+
 pub use crate::store::Store;
 pub use crate::types::{ui, reboot};
 pub use crate::types::consent;
@@ -27,10 +29,51 @@ pub trait UserInterface {
     /// Return the duration since startup.
     fn uptime(&mut self) -> core::time::Duration;
 
+    /// Return the time since the Unix epoch, if the platform has a real-time clock.
+    fn time(&mut self) -> Option<core::time::Duration> {
+        None
+    }
+
     /// Exit / reset the application
     fn reboot (&mut self, to: reboot::To) -> !;
 }
 
+/// A hardware-unique key (HUK), a device secret that never appears in the filesystem.
+///
+/// Implementations may keep the HUK inside a hardware HMAC engine,
+/// as it is only used as input keying material of HKDF.
+pub trait HardwareUniqueKey {
+    /// HKDF-Extract with SHA-256 and the HUK as input keying material.
+    fn extract(&mut self, salt: &[u8]) -> [u8; 32];
+}
+
+/// A HUK kept in memory, for host tests and platforms without one in hardware.
+pub struct SoftwareHuk([u8; 32]);
+
+impl SoftwareHuk {
+    pub fn new(huk: [u8; 32]) -> Self {
+        Self(huk)
+    }
+}
+
+impl HardwareUniqueKey for SoftwareHuk {
+    fn extract(&mut self, salt: &[u8]) -> [u8; 32] {
+        let (prk, _) = hkdf::Hkdf::<sha2::Sha256>::extract(Some(salt), &self.0);
+        let mut extracted = [0u8; 32];
+        extracted.copy_from_slice(&prk);
+        extracted
+    }
+}
+
+/// Derive a key of a client from the HUK, via HKDF with the client ID as salt.
+pub fn derive_client_key(huk: &mut dyn HardwareUniqueKey, client_id: &Path, info: &[u8]) -> [u8; 32] {
+    let prk = huk.extract(client_id.as_ref().as_bytes());
+    let mut okm = [0u8; 32];
+    // can't fail, since the output is shorter than 255 blocks
+    hkdf::Hkdf::<sha2::Sha256>::from_prk(&prk).unwrap().expand(info, &mut okm).unwrap();
+    okm
+}
+
 // This is the same trick as in "store.rs",
 // replacing generic parameters with associated types
 // and a macro.
@@ -43,6 +86,13 @@ pub unsafe trait Platform {
     fn rng(&mut self) -> &mut Self::R;
     fn store(&self) -> Self::S;
     fn user_interface(&mut self) -> &mut Self::UI;
+
+    /// The hardware-unique key, if the platform has one.
+    ///
+    /// If so, key files are encrypted at rest under per-client keys derived from it.
+    fn hardware_unique_key(&mut self) -> Option<&mut dyn HardwareUniqueKey> {
+        None
+    }
 }
 
 #[macro_export]
@@ -85,6 +135,52 @@ macro_rules! platform { (
             self.store
         }
     }
+};
+(
+    $PlatformName:ident,
+    R: $Rng:ty,
+    S: $Store:ty,
+    UI: $UserInterface:ty,
+    HUK: $Huk:ty,
+) => {
+
+    /// Platform struct implemented `trussed::Platform`, generated
+    /// by a Trussed-supplied macro at call site, using the platform-specific
+    /// implementations of its components.
+    pub struct $PlatformName {
+        rng: $Rng,
+        store: $Store,
+        user_interface: $UserInterface,
+        huk: $Huk,
+    }
+
+    impl $PlatformName {
+        pub fn new(rng: $Rng, store: $Store, user_interface: $UserInterface, huk: $Huk) -> Self {
+            Self { rng, store, user_interface, huk }
+        }
+    }
+
+    unsafe impl $crate::platform::Platform for $PlatformName {
+        type R = $Rng;
+        type S = $Store;
+        type UI = $UserInterface;
+
+        fn user_interface(&mut self) -> &mut Self::UI {
+            &mut self.user_interface
+        }
+
+        fn rng(&mut self) -> &mut Self::R {
+            &mut self.rng
+        }
+
+        fn store(&self) -> Self::S {
+            self.store
+        }
+
+        fn hardware_unique_key(&mut self) -> Option<&mut dyn $crate::platform::HardwareUniqueKey> {
+            Some(&mut self.huk)
+        }
+    }
 }}
 
 /// Trussed client will call this method when making a Trussed request.
@@ -15,13 +15,20 @@ pub use crate::store::{
     filestore::{ClientFilestore, Filestore, ReadDirState, ReadDirFilesState},
     certstore::{ClientCertstore, Certstore as _},
     counterstore::{ClientCounterstore, Counterstore as _},
-    keystore::{ClientKeystore, Keystore},
+    keystore::{ClientKeystore, Grant, KekSecret, KekState, Keks, Keystore, ListKeysState},
+    objectstore::{ClientObjectstore, Objectstore},
+    pinstore::{ClientPinstore, Pinstore},
 };
 use crate::types::*;
 pub use crate::pipe::ServiceEndpoint;
 
 
 pub mod attest;
+pub(crate) mod base64;
+pub mod der;
+pub mod jwk;
+pub mod ssh;
+pub mod wrap;
 
 // #[macro_use]
 // mod macros;
@@ -43,6 +50,7 @@ rpc_trait! {
     Exists, exists,
     GenerateKey, generate_key,
     Hash, hash,
+    ImportKey, import_key,
     SerializeKey, serialize_key,
     Sign, sign,
     UnsafeInjectKey, unsafe_inject_key,
@@ -52,6 +60,12 @@ rpc_trait! {
     WrapKey, wrap_key,
 }
 
+/// The volatile keys a client created since opening its session.
+struct Session {
+    client_id: ClientId,
+    keys: Vec<UniqueId, MAX_SESSION_KEYS>,
+}
+
 pub struct ServiceResources<P>
 where P: Platform
 {
@@ -59,9 +73,21 @@ where P: Platform
     // // Option?
     // currently_serving: ClientId,
     // TODO: how/when to clear
+    // the cursor of each client listing its keys
+    list_keys_state: Vec<(ClientId, ListKeysState), MAX_SERVICE_CLIENTS>,
     read_dir_files_state: Option<ReadDirFilesState>,
     read_dir_state: Option<ReadDirState>,
     rng_state: Option<ChaCha8Rng>,
+    handle_secret: Option<[u8; 32]>,
+    // client and uptime of the last touch, for cached touch policies
+    last_touch: Option<(ClientId, core::time::Duration)>,
+    // clients whose PIN was verified since boot, and who did not log out
+    logged_in: Vec<ClientId, MAX_SERVICE_CLIENTS>,
+    // clients allowed to import private keys
+    key_import: Vec<ClientId, MAX_SERVICE_CLIENTS>,
+    sessions: Vec<Session, MAX_SERVICE_CLIENTS>,
+    // the derived key-encryption keys of the clients served since boot
+    keks: Vec<(ClientId, Keks), MAX_SERVICE_CLIENTS>,
 }
 
 impl<P: Platform> ServiceResources<P> {
@@ -70,9 +96,16 @@ impl<P: Platform> ServiceResources<P> {
         Self {
             platform,
             // currently_serving: PathBuf::new(),
+            list_keys_state: Vec::new(),
             read_dir_files_state: None,
             read_dir_state: None,
             rng_state: None,
+            handle_secret: None,
+            last_touch: None,
+            logged_in: Vec::new(),
+            key_import: Vec::new(),
+            sessions: Vec::new(),
+            keks: Vec::new(),
         }
     }
 }
@@ -85,6 +118,151 @@ pub struct Service<P> where P: Platform {
 // need to be able to send crypto service to an interrupt handler
 unsafe impl<P: Platform> Send for Service<P> {}
 
+/// The key a request operates with, and how, to check against the key's policy.
+fn key_usage(request: &Request) -> Option<(key::Secrecy, &ObjectHandle, Mechanism, key::Usage)> {
+    use key::{Secrecy, Usage};
+
+    Some(match request {
+        Request::Agree(request) => (Secrecy::Secret, &request.private_key, request.mechanism, Usage::AGREE),
+        Request::Decrypt(request) => (Secrecy::Secret, &request.key, request.mechanism, Usage::DECRYPT),
+        Request::DeriveKey(request) => match request.mechanism {
+            // deriving the public key of a private key is always permitted
+            Mechanism::Ed255 | Mechanism::P256 | Mechanism::X255 => return None,
+            _ => (Secrecy::Secret, &request.base_key, request.mechanism, Usage::DERIVE),
+        }
+        Request::Encrypt(request) => (Secrecy::Secret, &request.key, request.mechanism, Usage::ENCRYPT),
+        Request::Sign(request) => (Secrecy::Secret, &request.key, request.mechanism, Usage::SIGN),
+        Request::UnwrapKey(request) => (Secrecy::Secret, &request.wrapping_key, request.mechanism, Usage::UNWRAP),
+        Request::Verify(request) => (Secrecy::Public, &request.key, request.mechanism, Usage::VERIFY),
+        Request::WrapKey(request) => (Secrecy::Secret, &request.wrapping_key, request.mechanism, Usage::WRAP),
+        _ => return None,
+    })
+}
+
+/// The well-known slot a request creates its key in, and whether it may replace a key there.
+fn key_slot(request: &Request) -> Option<(UniqueId, bool)> {
+    let attributes = match request {
+        Request::DeserializeKey(request) => &request.attributes,
+        Request::GenerateKey(request) => &request.attributes,
+        Request::ImportKey(request) => &request.attributes,
+        Request::UnwrapKey(request) => &request.attributes,
+        _ => return None,
+    };
+    attributes.slot.map(|slot| (UniqueId::from(slot), attributes.replace_slot))
+}
+
+/// The secrecy and header of the key with the ID, trying secret keys first.
+fn locate_key(keystore: &impl Keystore, id: &UniqueId) -> Result<(key::Secrecy, key::Info), Error> {
+    [key::Secrecy::Secret, key::Secrecy::Public].iter()
+        .find_map(|&secrecy| keystore.key_info(secrecy, id).map(|info| (secrecy, info)))
+        .ok_or(Error::NoSuchKey)
+}
+
+/// What a handle is bound to besides its client: the kind of a key, or being another object.
+fn handle_binding<P: Platform>(keystore: &ClientKeystore<P>, objectstore: &impl Objectstore, id: &UniqueId) -> Option<[u8; 2]> {
+    if let Some(grant) = keystore.grant(id) {
+        return Some(grant.kind.to_be_bytes());
+    }
+    match locate_key(keystore, id) {
+        Ok((_, info)) => Some(info.kind.code().to_be_bytes()),
+        Err(_) => objectstore.load_object(id).ok().map(|_| [0u8; 2]),
+    }
+}
+
+/// The tag of a handle: HMAC-SHA256 over object ID and binding, truncated to 16 bytes.
+fn handle_tag(handle_key: &[u8; 32], id: &UniqueId, binding: [u8; 2]) -> [u8; 16] {
+    use hmac::{Hmac, Mac, NewMac};
+    let mut mac = Hmac::<sha2::Sha256>::new_varkey(handle_key).unwrap();
+    mac.update(&id.0);
+    mac.update(&binding);
+    let mut tag = [0u8; 16];
+    tag.copy_from_slice(&mac.finalize().into_bytes()[..16]);
+    tag
+}
+
+/// The handles a request passes in, to authenticate before serving it.
+fn request_handles(request: &Request) -> [Option<&ObjectHandle>; 2] {
+    match request {
+        Request::Agree(request) => [Some(&request.private_key), Some(&request.public_key)],
+        Request::Attest(request) => [Some(&request.private_key), None],
+        Request::CopyKey(request) => [Some(&request.key), None],
+        Request::Decrypt(request) => [Some(&request.key), None],
+        Request::Delete(request) => [Some(&request.key), None],
+        Request::DeriveKey(request) => [Some(&request.base_key), None],
+        Request::Encrypt(request) => [Some(&request.key), None],
+        Request::Exists(request) => [Some(&request.key), None],
+        Request::GetAttributes(request) => [Some(&request.object), None],
+        Request::GrantKey(request) => [Some(&request.key), None],
+        Request::MoveKey(request) => [Some(&request.key), None],
+        Request::RevokeGrant(request) => [Some(&request.key), None],
+        Request::SerializeKey(request) => [Some(&request.key), None],
+        Request::Sign(request) => [Some(&request.key), None],
+        Request::UnwrapKey(request) => [Some(&request.wrapping_key), None],
+        Request::Verify(request) => [Some(&request.key), None],
+        Request::WrapKey(request) => [Some(&request.wrapping_key), Some(&request.key)],
+        _ => [None, None],
+    }
+}
+
+/// Calls `f` on each handle a reply hands out, to tag them.
+fn for_each_reply_handle(reply: &mut Reply, mut f: impl FnMut(&mut ObjectHandle)) {
+    match reply {
+        Reply::Agree(reply) => f(&mut reply.shared_secret),
+        Reply::CopyKey(reply) => f(&mut reply.key),
+        Reply::CreateObject(reply) => f(&mut reply.object),
+        Reply::DeriveDeviceKey(reply) => f(&mut reply.key),
+        Reply::DeriveKey(reply) => f(&mut reply.key),
+        Reply::DeserializeKey(reply) => f(&mut reply.key),
+        Reply::FindObjects(reply) => reply.objects.iter_mut().for_each(f),
+        Reply::GenerateKey(reply) => f(&mut reply.key),
+        Reply::GenerateSecretKey(reply) => f(&mut reply.key),
+        Reply::ImportKey(reply) => f(&mut reply.key),
+        Reply::ListGrantedKeys(reply) => reply.keys.iter_mut().for_each(f),
+        Reply::ListKeysFirst(reply) => reply.entry.iter_mut().for_each(|entry| f(&mut entry.key)),
+        Reply::ListKeysNext(reply) => reply.entry.iter_mut().for_each(|entry| f(&mut entry.key)),
+        Reply::SlotKey(reply) => reply.key.iter_mut().for_each(f),
+        Reply::UnsafeInjectKey(reply) => f(&mut reply.key),
+        Reply::UnsafeInjectSharedKey(reply) => f(&mut reply.key),
+        Reply::UnwrapKey(reply) => reply.key.iter_mut().for_each(f),
+        Reply::UpgradeHandle(reply) => f(&mut reply.handle),
+        _ => {}
+    }
+}
+
+/// The key a reply hands out that the request created.
+fn created_key(reply: &Reply) -> Option<&ObjectHandle> {
+    match reply {
+        Reply::Agree(reply) => Some(&reply.shared_secret),
+        Reply::CopyKey(reply) => Some(&reply.key),
+        Reply::DeriveDeviceKey(reply) => Some(&reply.key),
+        Reply::DeriveKey(reply) => Some(&reply.key),
+        Reply::DeserializeKey(reply) => Some(&reply.key),
+        Reply::GenerateKey(reply) => Some(&reply.key),
+        Reply::GenerateSecretKey(reply) => Some(&reply.key),
+        Reply::ImportKey(reply) => Some(&reply.key),
+        Reply::UnsafeInjectKey(reply) => Some(&reply.key),
+        Reply::UnsafeInjectSharedKey(reply) => Some(&reply.key),
+        Reply::UnwrapKey(reply) => reply.key.as_ref(),
+        _ => None,
+    }
+}
+
+/// Secret keys that are not extractable stay on the device: they may be moved,
+/// but neither copied nor moved to external flash. Keys with limited uses are
+/// never copied, as each copy would count its uses anew.
+fn check_relocation(secrecy: key::Secrecy, info: &key::Info, location: Location, copy: bool) -> Result<(), Error> {
+    if secrecy == key::Secrecy::Secret
+        && !info.flags.contains(key::Flags::EXTRACTABLE)
+        && (copy || location == Location::External)
+    {
+        return Err(Error::KeyUnextractable);
+    }
+    if copy && info.policy.uses_remaining.is_some() {
+        return Err(Error::KeyUnextractable);
+    }
+    Ok(())
+}
+
 impl<P: Platform> ServiceResources<P> {
 
     pub fn reply_to(&mut self, client_id: PathBuf, request: &Request) -> Result<Reply, Error> {
@@ -92,13 +270,28 @@ impl<P: Platform> ServiceResources<P> {
         // Is there a nicer way to do this?
 
         let full_store = self.platform.store();
+        let keks = self.keks(&client_id)?;
+
+        // only the key a request operates with may be granted by another client,
+        // and is read with the KEKs of its owner
+        let granted = key_usage(request).and_then(|(_, key, _, usage)| {
+            Grant::load(full_store, &client_id, &key.object_id).map(|grant| (key.object_id, usage, grant.owner))
+        });
+        let grantor_keks = match &granted {
+            Some((_, _, owner)) => self.keks(owner)?,
+            None => None,
+        };
+        let granted = granted.map(|(id, usage, _)| (id, usage));
+
+        let pin_key = self.pin_key(&client_id)?;
 
         // prepare keystore, bound to client_id, for cryptographic calls
+        let now = self.platform.user_interface().time().map(|time| time.as_secs());
         let mut keystore: ClientKeystore<P> = ClientKeystore::new(
             client_id.clone(),
             self.drbg().map_err(|_| Error::EntropyMalfunction)?,
             full_store,
-        );
+        ).with_keks(keks).with_grant(granted, grantor_keks).with_slot(key_slot(request)).with_time(now);
         let keystore = &mut keystore;
 
         // prepare certstore, bound to client_id, for cert calls
@@ -115,14 +308,87 @@ impl<P: Platform> ServiceResources<P> {
         );
         let counterstore = &mut counterstore;
 
+        // prepare objectstore, bound to client_id, for data objects and certificates
+        let mut objectstore: ClientObjectstore<P::S> = ClientObjectstore::new(
+            client_id.clone(),
+            full_store,
+        );
+        let objectstore = &mut objectstore;
+
         // prepare filestore, bound to client_id, for storage calls
         let mut filestore: ClientFilestore<P::S> = ClientFilestore::new(
-            client_id,
+            client_id.clone(),
             full_store,
         );
         let filestore = &mut filestore;
 
-        match request {
+        // prepare pinstore, bound to client_id, for PIN calls
+        let mut pinstore: ClientPinstore<P::S> = ClientPinstore::new(
+            client_id.clone(),
+            full_store,
+            pin_key,
+        );
+        let pinstore = &mut pinstore;
+
+        // handles must have been issued to this client, for an existing object of the same kind
+        let handle_key = self.handle_key(&client_id)?;
+        let logged_in = self.logged_in.contains(&client_id);
+        for handle in request_handles(request).iter().flatten() {
+            let binding = handle_binding(keystore, objectstore, &handle.object_id)
+                .ok_or(Error::ObjectHandleInvalid)?;
+            let tag = handle_tag(&handle_key, &handle.object_id, binding);
+            // compare in constant time
+            if tag.iter().zip(handle.tag.iter()).fold(0, |acc, (a, b)| acc | (a ^ b)) != 0 {
+                return Err(Error::ObjectHandleInvalid);
+            }
+
+            // private keys are usable only after the PIN is verified
+            if !logged_in {
+                if let Ok((_, info)) = locate_key(keystore, &handle.object_id) {
+                    if info.flags.contains(key::Flags::PRIVATE) {
+                        return Err(Error::UserNotLoggedIn);
+                    }
+                }
+            }
+        }
+
+        // enforce key policies centrally, mechanisms report missing keys
+        if let Some((secrecy, key, mechanism, usage)) = key_usage(request) {
+            if let Some(info) = keystore.key_info(secrecy, &key.object_id) {
+                if !info.policy.permits(mechanism, usage) {
+                    return Err(Error::KeyFunctionNotPermitted);
+                }
+                if usage.intersects(key::Usage::SIGN | key::Usage::DECRYPT | key::Usage::AGREE) {
+                    // before counting the use, so the user declining does not use up the key
+                    self.check_touch(&client_id, info.policy.touch)?;
+
+                    // keys with limits count their uses, and expire
+                    if info.policy.uses_remaining.is_some() || info.policy.not_after.is_some() {
+                        keystore.use_key(secrecy, &key.object_id, now)?;
+                    }
+                }
+            }
+        }
+
+        // secret keys leave the service only if extractable, and wrapped if sensitive,
+        // and never with limited uses, which would start over once imported or unwrapped
+        let exported_key = match request {
+            Request::SerializeKey(request) => Some((&request.key, false)),
+            Request::WrapKey(request) => Some((&request.key, true)),
+            _ => None,
+        };
+        if let Some((key, wrapped)) = exported_key {
+            if let Some(info) = keystore.key_info(key::Secrecy::Secret, &key.object_id) {
+                if !info.flags.contains(key::Flags::EXTRACTABLE)
+                    || (!wrapped && info.flags.contains(key::Flags::SENSITIVE))
+                    || info.policy.uses_remaining.is_some()
+                {
+                    return Err(Error::KeyUnextractable);
+                }
+            }
+        }
+
+        let reply = match request {
             Request::DummyRequest => {
                 Ok(Reply::DummyReply)
             },
@@ -138,12 +404,14 @@ impl<P: Platform> ServiceResources<P> {
             },
 
             Request::Attest(request) => {
+                let attn = PathBuf::from("attn");
+                let attn_keks = self.keks(&attn)?;
                 let mut attn_keystore: ClientKeystore<P> = ClientKeystore::new(
-                    PathBuf::from("attn"),
+                    attn,
                     self.drbg().map_err(|_| Error::EntropyMalfunction)?,
                     full_store,
-                );
-                attest::try_attest(&mut attn_keystore, certstore, counterstore, keystore, request).map(Reply::Attest)
+                ).with_keks(attn_keks);
+                attest::try_attest(&mut attn_keystore, certstore, keystore, request).map(Reply::Attest)
             }
 
             Request::Decrypt(request) => {
@@ -157,6 +425,23 @@ impl<P: Platform> ServiceResources<P> {
                 }.map(Reply::Decrypt)
             },
 
+            Request::DeriveDeviceKey(request) => {
+                let huk = self.platform.hardware_unique_key().ok_or(Error::RequestNotAvailable)?;
+                // separated from the keystore's KEK and other internal uses of the HUK
+                let mut info = Bytes::<MAX_MEDIUM_DATA_LENGTH>::new();
+                info.extend_from_slice(b"trussed device key ").unwrap();
+                info.extend_from_slice(&request.info).unwrap();
+                let derived = derive_client_key(huk, &client_id, &info);
+
+                let key_id = keystore.store_key(
+                    request.attributes.persistence,
+                    key::Secrecy::Secret,
+                    key::Info::from(key::Kind::Symmetric(32)).with_local_flag().with_attributes(&request.attributes),
+                    &derived,
+                )?;
+                Ok(Reply::DeriveDeviceKey(reply::DeriveDeviceKey { key: ObjectHandle::new(key_id) }))
+            }
+
             Request::DeriveKey(request) => {
                 match request.mechanism {
 
@@ -194,8 +479,62 @@ impl<P: Platform> ServiceResources<P> {
                 }.map(Reply::Encrypt)
             },
 
+            Request::CopyKey(request) => {
+                let id = &request.key.object_id;
+                let (secrecy, info) = locate_key(keystore, id)?;
+                check_relocation(secrecy, &info, request.location, true)?;
+                let copy = keystore.copy_key(secrecy, id, request.location)?;
+                Ok(Reply::CopyKey(reply::CopyKey { key: ObjectHandle::new(copy) }))
+            },
+
+            Request::CreateObject(request) => {
+                match &request.attributes {
+                    Attributes::Key(attributes) => {
+                        let algorithm = match attributes.mechanism {
+                            Mechanism::Ed255 => der::Algorithm::Ed255,
+                            Mechanism::P256 => der::Algorithm::P256,
+                            Mechanism::X255 => der::Algorithm::X255,
+                            _ => return Err(Error::MechanismNotAvailable),
+                        };
+                        // only these formats can contain private keys
+                        if let KeySerialization::Der | KeySerialization::Pem = attributes.format {
+                            let (secrecy, _) = der::deserialize_key(algorithm, attributes.format, &attributes.public_key)?;
+                            if secrecy == key::Secrecy::Secret {
+                                return Err(Error::InvalidSerializedKey);
+                            }
+                        }
+
+                        let mut storage_attributes = StorageAttributes::new().set_persistence(request.location);
+                        storage_attributes.label = attributes.label.clone();
+                        let request = request::DeserializeKey {
+                            mechanism: attributes.mechanism,
+                            serialized_key: attributes.public_key.clone(),
+                            format: attributes.format,
+                            attributes: storage_attributes,
+                        };
+                        match request.mechanism {
+                            Mechanism::Ed255 => mechanisms::Ed255::deserialize_key(keystore, &request),
+                            Mechanism::P256 => mechanisms::P256::deserialize_key(keystore, &request),
+                            Mechanism::X255 => mechanisms::X255::deserialize_key(keystore, &request),
+                            _ => Err(Error::MechanismNotAvailable),
+                        }.map(|reply| reply.key)
+                    }
+
+                    Attributes::Certificate(_) | Attributes::Data(_) => {
+                        let id = keystore.generate_key_id();
+                        objectstore.store_object(request.location, &id, &request.attributes)?;
+                        Ok(ObjectHandle::new(id))
+                    }
+
+                    // use `CreateCounter`
+                    Attributes::Counter => Err(Error::RequestNotAvailable),
+
+                }.map(|object| Reply::CreateObject(reply::CreateObject { object }))
+            },
+
             Request::Delete(request) => {
-                let success = keystore.delete_key(&request.key.object_id);
+                let success = keystore.delete_key(&request.key.object_id)
+                    || objectstore.delete_object(&request.key.object_id);
                 Ok(Reply::Delete(reply::Delete { success } ))
             },
 
@@ -216,6 +555,21 @@ impl<P: Platform> ServiceResources<P> {
                 }.map(Reply::Exists)
             },
 
+            Request::FindObjects(request) => {
+                // keys come first, then the other objects
+                let (mut ids, num_keys) = keystore.find_keys(&request.template, request.offset)?;
+                let (object_ids, num_other_objects) = objectstore.find_objects(
+                    &request.template, request.offset.saturating_sub(num_keys))?;
+                for id in object_ids {
+                    if ids.push(id).is_err() {
+                        break;
+                    }
+                }
+                let objects = ids.into_iter().map(ObjectHandle::new).collect();
+                let num_objects = num_keys + num_other_objects;
+                Ok(Reply::FindObjects(reply::FindObjects { objects, num_objects } ))
+            },
+
             Request::GenerateKey(request) => {
                 match request.mechanism {
                     Mechanism::Chacha8Poly1305 => mechanisms::Chacha8Poly1305::generate_key(keystore, request),
@@ -234,28 +588,53 @@ impl<P: Platform> ServiceResources<P> {
                 let key_id = keystore.store_key(
                     request.attributes.persistence,
                     key::Secrecy::Secret,
-                    key::Kind::Symmetric(size),
+                    key::Info::from(key::Kind::Symmetric(size)).with_local_flag().with_attributes(&request.attributes),
                     &secret_key[..size],
                 )?;
-                Ok(Reply::GenerateSecretKey(reply::GenerateSecretKey { key: ObjectHandle { object_id: key_id } }))
+                Ok(Reply::GenerateSecretKey(reply::GenerateSecretKey { key: ObjectHandle::new(key_id) }))
             },
 
-            // deprecated
+            // deprecated, replaced by `ImportKey`
             Request::UnsafeInjectKey(_request) => {
                 Err(Error::MechanismNotAvailable)
             },
 
             Request::UnsafeInjectSharedKey(request) => {
-                let key = ObjectHandle { object_id: keystore.store_key(
+                let key = ObjectHandle::new(keystore.store_key(
                     request.location,
                     key::Secrecy::Secret,
                     key::Kind::Shared(request.raw_key.len()),
                     &request.raw_key,
-                )? };
+                )?);
 
                 Ok(Reply::UnsafeInjectSharedKey(reply::UnsafeInjectSharedKey { key } ))
             },
 
+            Request::GetAttributes(request) => {
+                let id = &request.object.object_id;
+                let key = [key::Secrecy::Secret, key::Secrecy::Public].iter().find_map(|&secrecy| {
+                    Some((secrecy, keystore.key_info(secrecy, id)?, keystore.location(secrecy, id)?))
+                });
+                let reply = match key {
+                    Some((secrecy, info, location)) => reply::GetAttributes {
+                        object_type: ObjectType::Key(secrecy),
+                        location,
+                        key: Some(info),
+                        attributes: None,
+                    },
+                    None => {
+                        let (attributes, location) = objectstore.load_object(id)?;
+                        reply::GetAttributes {
+                            object_type: attributes.object_type(),
+                            location,
+                            key: None,
+                            attributes: Some(attributes),
+                        }
+                    }
+                };
+                Ok(Reply::GetAttributes(reply))
+            },
+
             Request::Hash(request) => {
                 match request.mechanism {
 
@@ -265,6 +644,52 @@ impl<P: Platform> ServiceResources<P> {
                 }.map(Reply::Hash)
             },
 
+            Request::ImportKey(request) => {
+                if !self.key_import.contains(&client_id) {
+                    return Err(Error::ImportNotAllowed);
+                }
+                match request.mechanism {
+
+                    Mechanism::Ed255 => mechanisms::Ed255::import_key(keystore, request),
+                    Mechanism::P256 => mechanisms::P256::import_key(keystore, request),
+                    Mechanism::X255 => mechanisms::X255::import_key(keystore, request),
+                    _ => Err(Error::MechanismNotAvailable),
+
+                }.map(Reply::ImportKey)
+            },
+
+            Request::ListKeysFirst(request) => {
+                self.take_list_keys_state(&client_id);
+                let entry = keystore.next_key(request.kind, request.secrecy, None)?;
+                if let Some(entry) = &entry {
+                    // there are no more clients than endpoints
+                    self.list_keys_state.push((client_id.clone(), ListKeysState {
+                        kind: request.kind,
+                        secrecy: request.secrecy,
+                        last: entry.clone(),
+                    })).ok();
+                }
+                Ok(Reply::ListKeysFirst(reply::ListKeysFirst { entry } ))
+            }
+
+            Request::ListKeysNext(_request) => {
+                // ensure next call has nothing to work with, unless we store state again
+                let entry = match self.take_list_keys_state(&client_id) {
+                    None => None,
+                    Some(state) => {
+                        let entry = keystore.next_key(state.kind, state.secrecy, Some(&state.last))?;
+                        if let Some(entry) = &entry {
+                            self.list_keys_state.push((client_id.clone(), ListKeysState {
+                                last: entry.clone(),
+                                ..state
+                            })).ok();
+                        }
+                        entry
+                    }
+                };
+                Ok(Reply::ListKeysNext(reply::ListKeysNext { entry } ))
+            }
+
             Request::LocateFile(request) => {
                 let path = filestore.locate_file(request.location, request.dir.clone(), request.filename.clone())?;
 
@@ -308,6 +733,14 @@ impl<P: Platform> ServiceResources<P> {
 
             }
 
+            Request::MoveKey(request) => {
+                let id = &request.key.object_id;
+                let (secrecy, info) = locate_key(keystore, id)?;
+                check_relocation(secrecy, &info, request.location, false)?;
+                keystore.move_key(secrecy, id, request.location)?;
+                Ok(Reply::MoveKey(reply::MoveKey {}))
+            }
+
             Request::ReadDirFirst(request) => {
                 let maybe_entry = match filestore.read_dir_first(&request.dir, request.location, request.not_before_filename.as_ref())? {
                     Some((entry, read_dir_state)) => {
@@ -413,6 +846,40 @@ impl<P: Platform> ServiceResources<P> {
                 }
             }
 
+            Request::RewrapKeys(request) => {
+                // not for clients whose keys stay in plaintext
+                self.keks(&client_id)?.ok_or(Error::RequestNotAvailable)?;
+                let mut state = KekState::load(full_store, &client_id)?;
+                // otherwise, resume the rotation interrupted by power loss, with its secret
+                if !state.rotating() {
+                    // fresh randomness, so the new KEK is secret even if the given input is not
+                    use sha2::digest::Digest;
+                    let mut random = [0u8; 32];
+                    self.drbg()?.fill_bytes(&mut random);
+                    let mut hash = sha2::Sha256::new();
+                    hash.update(&random);
+                    hash.update(&request.secret);
+                    state.start_rotation(KekSecret::try_from_slice(&hash.finalize()).unwrap())?;
+                    state.save(full_store, &client_id)?;
+                    self.forget_keks(&client_id);
+                }
+                let huk = self.platform.hardware_unique_key().ok_or(Error::RequestNotAvailable)?;
+                let keks = Keks::derive(huk, &client_id, &state);
+
+                let mut keystore: ClientKeystore<P> = ClientKeystore::new(
+                    client_id.clone(),
+                    self.drbg().map_err(|_| Error::EntropyMalfunction)?,
+                    full_store,
+                ).with_keks(Some(keks));
+                // a failure leaves the rotation in progress, to be resumed
+                let count = keystore.rewrap_keys()?;
+
+                state.finish_rotation();
+                state.save(full_store, &client_id)?;
+                self.forget_keks(&client_id);
+                Ok(Reply::RewrapKeys(reply::RewrapKeys { version: state.current, count }))
+            }
+
             Request::SerializeKey(request) => {
                 match request.mechanism {
 
@@ -433,12 +900,20 @@ impl<P: Platform> ServiceResources<P> {
                     Mechanism::HmacSha512 => mechanisms::HmacSha512::sign(keystore, request),
                     Mechanism::P256 => mechanisms::P256::sign(keystore, request),
                     Mechanism::P256Prehashed => mechanisms::P256Prehashed::sign(keystore, request),
+                    Mechanism::P256Deterministic => mechanisms::P256Deterministic::sign(keystore, request),
+                    Mechanism::P256PrehashedDeterministic => mechanisms::P256PrehashedDeterministic::sign(keystore, request),
                     Mechanism::Totp => mechanisms::Totp::sign(keystore, request),
                     _ => Err(Error::MechanismNotAvailable),
 
                 }.map(Reply::Sign)
             },
 
+            Request::SlotKey(request) => {
+                let id = UniqueId::from(request.slot);
+                let key = locate_key(keystore, &id).ok().map(|_| ObjectHandle::new(id));
+                Ok(Reply::SlotKey(reply::SlotKey { key }))
+            }
+
             Request::WriteFile(request) => {
                 filestore.write(&request.path, request.location, &request.data)?;
                 Ok(Reply::WriteFile(reply::WriteFile {} ))
@@ -453,11 +928,23 @@ impl<P: Platform> ServiceResources<P> {
                 }.map(Reply::UnwrapKey)
             }
 
+            Request::UpgradeHandle(request) => {
+                // untagged handles only ever referred to keys, and only those of the client
+                let id = request.handle.object_id;
+                let own_key = [key::Secrecy::Secret, key::Secrecy::Public].iter()
+                    .any(|&secrecy| keystore.location(secrecy, &id).is_some());
+                if !request.handle.is_legacy() || !own_key {
+                    return Err(Error::ObjectHandleInvalid);
+                }
+                Ok(Reply::UpgradeHandle(reply::UpgradeHandle { handle: ObjectHandle::new(id) }))
+            }
+
             Request::Verify(request) => {
                 match request.mechanism {
 
                     Mechanism::Ed255 => mechanisms::Ed255::verify(keystore, request),
-                    Mechanism::P256 => mechanisms::P256::verify(keystore, request),
+                    Mechanism::P256 | Mechanism::P256Deterministic => mechanisms::P256::verify(keystore, request),
+                    Mechanism::P256Prehashed | Mechanism::P256PrehashedDeterministic => mechanisms::P256Prehashed::verify(keystore, request),
                     _ => Err(Error::MechanismNotAvailable),
 
                 }.map(Reply::Verify)
@@ -476,40 +963,10 @@ impl<P: Platform> ServiceResources<P> {
             Request::RequestUserConsent(request) => {
                 assert_eq!(request.level, consent::Level::Normal);
 
-                let starttime = self.platform.user_interface().uptime();
-                let timeout = core::time::Duration::from_millis(request.timeout_milliseconds as u64);
-
-                self.platform.user_interface().set_status(ui::Status::WaitingForUserPresence);
-                loop {
-                    let nowtime = self.platform.user_interface().uptime();
-                    if (nowtime - starttime) > timeout {
-                        let result = Err(consent::Error::TimedOut);
-                        return Ok(Reply::RequestUserConsent(reply::RequestUserConsent { result } ));
-                    }
-                    let up = self.platform.user_interface().check_user_presence();
-                    match request.level {
-                        // If Normal level consent is request, then both Strong and Normal
-                        // indications will result in success.
-                        consent::Level::Normal => {
-                            if up == consent::Level::Normal ||
-                                up == consent::Level::Strong {
-                                    break;
-                                }
-                        },
-                        // Otherwise, only strong level indication will work.
-                        consent::Level::Strong => {
-                            if up == consent::Level::Strong {
-                                break;
-                            }
-                        }
-                        _ => {
-                            break;
-                        }
-                    }
+                let result = self.user_consent(request.level, request.timeout_milliseconds);
+                if result.is_ok() {
+                    self.last_touch = Some((client_id.clone(), self.platform.user_interface().uptime()));
                 }
-                self.platform.user_interface().set_status(ui::Status::Idle);
-
-                let result = Ok(());
                 Ok(Reply::RequestUserConsent(reply::RequestUserConsent { result } ))
             }
 
@@ -544,15 +1001,295 @@ impl<P: Platform> ServiceResources<P> {
             }
 
             Request::WriteCertificate(request) => {
-                certstore.write_certificate(request.location, &request.der, counterstore)
+                certstore.write_certificate(request.location, &request.der, keystore.generate_key_id())
                     .map(|id| Reply::WriteCertificate(reply::WriteCertificate { id } ))
             }
 
+            Request::GrantKey(request) => {
+                // only the owner of a secret key can grant it
+                let id = &request.key.object_id;
+                keystore.location(key::Secrecy::Secret, id).ok_or(Error::NoSuchKey)?;
+                // a slot's key may be replaced, and the grant must not carry over
+                if id.is_special() {
+                    return Err(Error::KeyFunctionNotPermitted);
+                }
+                let info = keystore.key_info(key::Secrecy::Secret, id).ok_or(Error::NoSuchKey)?;
+                // the grantee would use it behind the owner's PIN
+                if info.flags.contains(key::Flags::PRIVATE) {
+                    return Err(Error::KeyFunctionNotPermitted);
+                }
+                let grant = Grant { owner: client_id.clone(), kind: info.kind.code(), usage: request.usage };
+                grant.save(full_store, &request.grantee, id)?;
+                Ok(Reply::GrantKey(reply::GrantKey {}))
+            }
+
+            Request::ListGrantedKeys(request) => {
+                let keys = Grant::find(full_store, &client_id, &request.owner)?
+                    .into_iter().map(ObjectHandle::new).collect();
+                Ok(Reply::ListGrantedKeys(reply::ListGrantedKeys { keys }))
+            }
+
+            Request::RevokeGrant(request) => {
+                let id = &request.key.object_id;
+                let revoked = Grant::load(full_store, &request.grantee, id)
+                    .map_or(false, |grant| grant.owner == client_id)
+                    && Grant::revoke(full_store, &request.grantee, id);
+                Ok(Reply::RevokeGrant(reply::RevokeGrant { revoked }))
+            }
+
+            Request::CloseSession(_request) => {
+                let count = self.close_session(&client_id)?;
+                Ok(Reply::CloseSession(reply::CloseSession { count }))
+            }
+
+            Request::OpenSession(_request) => {
+                if self.sessions.iter().any(|session| session.client_id == client_id) {
+                    return Err(Error::SessionExists);
+                }
+                // there are no more clients than endpoints
+                self.sessions.push(Session { client_id: client_id.clone(), keys: Vec::new() }).ok();
+                Ok(Reply::OpenSession(reply::OpenSession {}))
+            }
+
+            Request::ChangePin(request) => {
+                let mut salt = [0u8; 16];
+                keystore.drbg().fill_bytes(&mut salt);
+                pinstore.change_pin(&request.old_pin, &request.new_pin, salt)?;
+                Ok(Reply::ChangePin(reply::ChangePin {}))
+            }
+
+            Request::Logout(_request) => {
+                if let Some(index) = self.logged_in.iter().position(|logged_in| logged_in == &client_id) {
+                    self.logged_in.swap_remove(index);
+                }
+                Ok(Reply::Logout(reply::Logout {}))
+            }
+
+            Request::PinRetries(_request) => {
+                let retries = pinstore.pin_retries()?;
+                Ok(Reply::PinRetries(reply::PinRetries { retries }))
+            }
+
+            Request::SetPin(request) => {
+                let mut salt = [0u8; 16];
+                keystore.drbg().fill_bytes(&mut salt);
+                pinstore.set_pin(&request.pin, request.max_retries, salt)?;
+                Ok(Reply::SetPin(reply::SetPin {}))
+            }
+
+            Request::VerifyPin(request) => {
+                pinstore.verify_pin(&request.pin)?;
+                if !self.logged_in.contains(&client_id) {
+                    // there are no more clients than endpoints
+                    self.logged_in.push(client_id.clone()).ok();
+                }
+                Ok(Reply::VerifyPin(reply::VerifyPin {}))
+            }
+
             _ => {
                 // #[cfg(test)]
                 // println!("todo: {:?} request!", &request);
                 Err(Error::RequestNotAvailable)
             },
+        };
+
+        let mut reply = reply?;
+
+        // volatile keys created in a session are deleted with it
+        let session = self.sessions.iter_mut().find(|session| session.client_id == client_id);
+        if let Some(key) = created_key(&reply) {
+            let id = key.object_id;
+            let volatile = [key::Secrecy::Secret, key::Secrecy::Public].iter()
+                .any(|&secrecy| keystore.location(secrecy, &id) == Some(Location::Volatile));
+            if volatile && keystore.count_keys(Location::Volatile)? > MAX_VOLATILE_KEYS {
+                keystore.delete_key(&id);
+                return Err(Error::DeviceMemory);
+            }
+            if let Some(session) = session {
+                if volatile && session.keys.push(id).is_err() {
+                    keystore.delete_key(&id);
+                    return Err(Error::SessionFull);
+                }
+            }
+        } else if let Some(session) = session {
+            // keys deleted or moved out of volatile storage leave the session
+            let gone = match request {
+                Request::Delete(request) => Some(&request.key.object_id),
+                Request::MoveKey(request) if request.location != Location::Volatile => Some(&request.key.object_id),
+                _ => None,
+            };
+            if let Request::DeleteAllKeys(request::DeleteAllKeys { location: Location::Volatile }) = request {
+                session.keys.clear();
+            }
+            if let Some(index) = gone.and_then(|id| session.keys.iter().position(|key| key == id)) {
+                session.keys.swap_remove(index);
+            }
+        }
+        for_each_reply_handle(&mut reply, |handle| {
+            let binding = handle_binding(keystore, objectstore, &handle.object_id).unwrap_or([0xff; 2]);
+            handle.tag = handle_tag(&handle_key, &handle.object_id, binding);
+        });
+        Ok(reply)
+    }
+
+    /// Remove the cursor of the client's listing of its keys, if it has one.
+    fn take_list_keys_state(&mut self, client_id: &ClientId) -> Option<ListKeysState> {
+        let index = self.list_keys_state.iter().position(|(listing, _)| listing == client_id)?;
+        Some(self.list_keys_state.swap_remove(index).1)
+    }
+
+    /// Close the client's session, if it has one, deleting the volatile keys created in it.
+    pub fn close_session(&mut self, client_id: &ClientId) -> Result<usize, Error> {
+        let session = match self.sessions.iter().position(|session| &session.client_id == client_id) {
+            Some(index) => self.sessions.swap_remove(index),
+            None => return Ok(0),
+        };
+        let store = self.platform.store();
+        let keystore: ClientKeystore<P> = ClientKeystore::new(client_id.clone(), self.drbg()?, store);
+        // keys moved out of volatile storage since are kept
+        Ok(session.keys.iter().filter(|id| {
+            [key::Secrecy::Secret, key::Secrecy::Public].iter().any(|&secrecy| {
+                crate::store::delete(store, Location::Volatile, &keystore.key_path(secrecy, id))
+            })
+        }).count())
+    }
+
+    /// Wait for the user to indicate presence of at least the level.
+    fn user_consent(&mut self, level: consent::Level, timeout_milliseconds: u32) -> consent::Result {
+        let starttime = self.platform.user_interface().uptime();
+        let timeout = core::time::Duration::from_millis(timeout_milliseconds as u64);
+
+        self.platform.user_interface().set_status(ui::Status::WaitingForUserPresence);
+        loop {
+            let nowtime = self.platform.user_interface().uptime();
+            if (nowtime - starttime) > timeout {
+                self.platform.user_interface().set_status(ui::Status::Idle);
+                return Err(consent::Error::TimedOut);
+            }
+            let up = self.platform.user_interface().check_user_presence();
+            match level {
+                // If Normal level consent is request, then both Strong and Normal
+                // indications will result in success.
+                consent::Level::Normal => {
+                    if up == consent::Level::Normal ||
+                        up == consent::Level::Strong {
+                            break;
+                        }
+                },
+                // Otherwise, only strong level indication will work.
+                consent::Level::Strong => {
+                    if up == consent::Level::Strong {
+                        break;
+                    }
+                }
+                _ => {
+                    break;
+                }
+            }
+        }
+        self.platform.user_interface().set_status(ui::Status::Idle);
+        Ok(())
+    }
+
+    /// Require a touch for a key with the touch policy, unless the client's last one is recent enough.
+    fn check_touch(&mut self, client_id: &ClientId, touch: key::TouchPolicy) -> Result<(), Error> {
+        let now = self.platform.user_interface().uptime();
+        let cached = match (touch, &self.last_touch) {
+            (key::TouchPolicy::Never, _) => return Ok(()),
+            (key::TouchPolicy::Cached(seconds), Some((last_client_id, last_touch))) =>
+                last_client_id == client_id
+                    && now - *last_touch <= core::time::Duration::from_secs(seconds as u64),
+            _ => false,
+        };
+        if !cached {
+            self.user_consent(consent::Level::Normal, KEY_TOUCH_TIMEOUT_MILLISECONDS)
+                .map_err(|_| Error::UserNotPresent)?;
+            self.last_touch = Some((client_id.clone(), self.platform.user_interface().uptime()));
+        }
+        Ok(())
+    }
+
+    /// The key authenticating the handles of a client.
+    ///
+    /// Derived from a random device secret if one was stored, else from the hardware-unique key
+    /// if there is one, else from a new random device secret.
+    fn handle_key(&mut self, client_id: &PathBuf) -> Result<[u8; 32], Error> {
+        let secret = match self.handle_secret {
+            Some(secret) => secret,
+            None => {
+                let mut filestore: ClientFilestore<P::S> = ClientFilestore::new(
+                    PathBuf::from("trussed"),
+                    self.platform.store(),
+                );
+                let path = PathBuf::from("handle-key.bin");
+                let mut secret = [0u8; 32];
+                // a platform that gained a HUK in an update keeps the secret of its handles
+                if filestore.exists(&path, Location::Internal) {
+                    let bytes: Bytes<consts::U32> = filestore.read(&path, Location::Internal)?;
+                    secret.copy_from_slice(&bytes);
+                } else if let Some(huk) = self.platform.hardware_unique_key() {
+                    return Ok(derive_client_key(huk, client_id, b"trussed handles"));
+                } else {
+                    self.drbg()?.fill_bytes(&mut secret);
+                    filestore.write(&path, Location::Internal, &secret)?;
+                }
+                self.handle_secret = Some(secret);
+                secret
+            }
+        };
+
+        use hmac::{Hmac, Mac, NewMac};
+        let mut mac = Hmac::<sha2::Sha256>::new_varkey(&secret).unwrap();
+        mac.update(client_id.as_ref().as_bytes());
+        let mut handle_key = [0u8; 32];
+        handle_key.copy_from_slice(&mac.finalize().into_bytes());
+        Ok(handle_key)
+    }
+
+    /// The key under which the PIN of a client is hashed, so its file alone does not
+    /// allow guessing it.
+    ///
+    /// Derived from the handle key, which stays the same when a platform gains a HUK.
+    fn pin_key(&mut self, client_id: &PathBuf) -> Result<[u8; 32], Error> {
+        use hmac::{Hmac, Mac, NewMac};
+        let mut mac = Hmac::<sha2::Sha256>::new_varkey(&self.handle_key(client_id)?).unwrap();
+        mac.update(b"trussed pin");
+        let mut pin_key = [0u8; 32];
+        pin_key.copy_from_slice(&mac.finalize().into_bytes());
+        Ok(pin_key)
+    }
+
+    /// The key-encryption keys of the client, if the platform has a hardware-unique key.
+    ///
+    /// Key files written before are sealed when the KEKs are first used.
+    /// The KEKs are derived once per boot, until the client rotates them.
+    fn keks(&mut self, client_id: &PathBuf) -> Result<Option<Keks>, Error> {
+        if let Some((_, keks)) = self.keks.iter().find(|(cached, _)| cached == client_id) {
+            return Ok(Some(keks.clone()));
+        }
+        let store = self.platform.store();
+        let state = KekState::load(store, client_id)?;
+        let keks = match self.platform.hardware_unique_key() {
+            Some(huk) => Keks::derive(huk, client_id, &state),
+            None => return Ok(None),
+        };
+        if !KekState::exists(store, client_id) {
+            let mut keystore: ClientKeystore<P> = ClientKeystore::new(
+                client_id.clone(),
+                self.drbg().map_err(|_| Error::EntropyMalfunction)?,
+                store,
+            ).with_keks(Some(keks.clone()));
+            keystore.seal_plaintext_keys()?;
+            state.save(store, client_id)?;
+        }
+        // beyond capacity, e.g. for the owners of granted keys, they are derived each time
+        self.keks.push((client_id.clone(), keks.clone())).ok();
+        Ok(Some(keks))
+    }
+
+    fn forget_keks(&mut self, client_id: &PathBuf) {
+        if let Some(index) = self.keks.iter().position(|(cached, _)| cached == client_id) {
+            self.keks.swap_remove(index);
         }
     }
 
@@ -675,9 +1412,22 @@ impl<P: Platform> Service<P> {
         if client_id == PathBuf::from("trussed") {
             panic!("trussed is a reserved client ID");
         }
+        // the client was reset, its session is over
+        self.resources.close_session(&client_id).ok();
         self.eps.push(ServiceEndpoint { interchange, client_id })
     }
 
+    /// Allow or forbid the client to import private keys generated elsewhere.
+    #[allow(clippy::result_unit_err)]
+    pub fn set_key_import(&mut self, client_id: ClientId, allowed: bool) -> Result<(), ()> {
+        let key_import = &mut self.resources.key_import;
+        match (key_import.iter().position(|id| id == &client_id), allowed) {
+            (None, true) => key_import.push(client_id).map_err(|_| ()),
+            (Some(index), false) => { key_import.swap_remove(index); Ok(()) }
+            _ => Ok(()),
+        }
+    }
+
     pub fn set_seed_if_uninitialized(&mut self, seed: &[u8; 32]) {
 
         let mut filestore: ClientFilestore<P::S> = ClientFilestore::new(
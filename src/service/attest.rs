@@ -16,7 +16,6 @@ use crate::{
     mechanisms,
     service::{DeriveKey, SerializeKey, Sign},
     store::certstore::Certstore,
-    store::counterstore::Counterstore,
     store::keystore::Keystore,
     types::{KeySerialization, Location, Mechanism, Message, ObjectHandle, SignatureSerialization, StorageAttributes, UniqueId},
 };
@@ -34,7 +33,6 @@ pub const P256_ATTN_KEY: UniqueId = UniqueId([0xc8,0xd6,0x77,0xa3,0x93,0x46,0xc9
 pub fn try_attest(
     attn_keystore: &mut impl Keystore,
     certstore: &mut impl Certstore,
-    counterstore: &mut impl Counterstore,
     keystore: &mut impl Keystore,
     request: &AttestRequest,
 )
@@ -77,7 +75,7 @@ pub fn try_attest(
                     mechanism: Mechanism::Ed255,
                     base_key: request.private_key,
                     additional_data: None,
-                    attributes: StorageAttributes { persistence: Location::Volatile },
+                    attributes: StorageAttributes::new().set_persistence(Location::Volatile),
                 },
             )?.key;
             let serialized_key = mechanisms::Ed255::serialize_key(
@@ -102,7 +100,7 @@ pub fn try_attest(
                     mechanism: Mechanism::P256,
                     base_key: request.private_key,
                     additional_data: None,
-                    attributes: StorageAttributes { persistence: Location::Volatile },
+                    attributes: StorageAttributes::new().set_persistence(Location::Volatile),
                 },
             )?.key;
             let serialized_key = mechanisms::P256::serialize_key(
@@ -144,7 +142,7 @@ pub fn try_attest(
                 attn_keystore,
                 &request::Sign {
                     mechanism: Mechanism::Ed255,
-                    key: ObjectHandle { object_id: ED255_ATTN_KEY },
+                    key: ObjectHandle::new(ED255_ATTN_KEY),
                     message,
                     format: SignatureSerialization::Raw,
                 },
@@ -156,7 +154,7 @@ pub fn try_attest(
                 attn_keystore,
                 &request::Sign {
                     mechanism: Mechanism::P256,
-                    key: ObjectHandle { object_id: P256_ATTN_KEY },
+                    key: ObjectHandle::new(P256_ATTN_KEY),
                     message,
                     format: SignatureSerialization::Asn1Der,
                 },
@@ -177,7 +175,9 @@ pub fn try_attest(
         .to_heapless_vec()
         .map_err(|_| Error::ImplementationError)?);
 
-    let id = certstore.write_certificate(Location::Internal, &certificate, counterstore)?;
+    let mut id = [0u8; 16];
+    keystore.drbg().fill_bytes(&mut id);
+    let id = certstore.write_certificate(Location::Internal, &certificate, UniqueId(id))?;
 
     Ok(AttestReply { certificate: id })
 }
@@ -349,9 +349,12 @@ impl TryFrom<Mechanism> for SignatureAlgorithm {
 
 // 1.2.840.10045.4.3.2 ecdsaWithSHA256 (ANSI X9.62 ECDSA algorithm with SHA256))
 const P256_OID_ENCODING: &[u8] = &hex!("06 08  2A 86 48 CE 3D 04 03 02");
-const P256_PUB_ENCODING: &[u8] = &hex!("06 07 2A 86 48 CE 3D 02 01   06 08 2A 86 48 CE  3D 03 01 07");
+// 1.2.840.10045.2.1 ecPublicKey, with parameter 1.2.840.10045.3.1.7 prime256v1
+pub(crate) const P256_PUB_ENCODING: &[u8] = &hex!("06 07 2A 86 48 CE 3D 02 01   06 08 2A 86 48 CE  3D 03 01 07");
 // 1.3.101.112 curveEd25519 (EdDSA 25519 signature algorithm)
-const ED255_OID_ENCODING: &[u8] = &hex!("06 03  2B 65 70");
+pub(crate) const ED255_OID_ENCODING: &[u8] = &hex!("06 03  2B 65 70");
+// 1.3.101.110 curveX25519 (ECDH 25519 key agreement algorithm)
+pub(crate) const X255_OID_ENCODING: &[u8] = &hex!("06 03  2B 65 6E");
 
 impl Encodable for SignatureAlgorithm {
 
@@ -544,8 +547,31 @@ impl Encodable for Validity<'_> {
 #[derive(Clone, Copy, Eq, PartialEq)]
 pub enum SerializedSubjectPublicKey {
     Ed255([u8; 32]),
-    // This is the DER version with leading '04'
+    // This is the DER version with leading '02' or '03'
     P256([u8; 33]),
+    // This is the DER version with leading '04'
+    P256Uncompressed([u8; 65]),
+    X255([u8; 32]),
+}
+
+impl SerializedSubjectPublicKey {
+    fn algorithm(&self) -> &'static [u8] {
+        match self {
+            SerializedSubjectPublicKey::Ed255(_) => ED255_OID_ENCODING,
+            SerializedSubjectPublicKey::P256(_) => P256_PUB_ENCODING,
+            SerializedSubjectPublicKey::P256Uncompressed(_) => P256_PUB_ENCODING,
+            SerializedSubjectPublicKey::X255(_) => X255_OID_ENCODING,
+        }
+    }
+
+    fn public_key(&self) -> &[u8] {
+        match self {
+            SerializedSubjectPublicKey::Ed255(pub_key) => pub_key,
+            SerializedSubjectPublicKey::P256(pub_key) => pub_key,
+            SerializedSubjectPublicKey::P256Uncompressed(pub_key) => pub_key,
+            SerializedSubjectPublicKey::X255(pub_key) => pub_key,
+        }
+    }
 }
 
 impl Encodable for SerializedSubjectPublicKey {
@@ -553,40 +579,25 @@ impl Encodable for SerializedSubjectPublicKey {
         Ok((match self {
             SerializedSubjectPublicKey::Ed255(_) => 0x2A,
             SerializedSubjectPublicKey::P256(_) => 0x39,
+            SerializedSubjectPublicKey::P256Uncompressed(_) => 0x59,
+            SerializedSubjectPublicKey::X255(_) => 0x2A,
         } as u8).into())
     }
 
 
     fn encode(&self, encoder: &mut Encoder<'_>) -> BerResult<()> {
+        encoder.encode(&TaggedSlice::from(
+            Tag::SEQUENCE,
+            self.algorithm(),
+        )?)?;
         // NB: BIT-STRING needs to have number of "unused bits" in first byte (we have none)
-        match self {
-            SerializedSubjectPublicKey::Ed255(pub_key) => {
-                encoder.encode(&TaggedSlice::from(
-                    Tag::SEQUENCE,
-                    ED255_OID_ENCODING,
-                )?)?;
-                let mut leading_zero = [0u8; 33];
-                leading_zero[1..].copy_from_slice(pub_key.as_ref());
-                encoder.encode(&TaggedSlice::from(
-                    Tag::BIT_STRING,
-                    &leading_zero,
-                )?)
-
-                // encoder.encode(&flexiber
-            }
-            SerializedSubjectPublicKey::P256(pub_key) => {
-                encoder.encode(&TaggedSlice::from(
-                    Tag::SEQUENCE,
-                    P256_PUB_ENCODING,
-                )?)?;
-                let mut leading_zero = [0u8; 34];
-                leading_zero[1..].copy_from_slice(pub_key.as_ref());
-                encoder.encode(&TaggedSlice::from(
-                    Tag::BIT_STRING,
-                    &leading_zero,
-                )?)
-            }
-        }
+        let public_key = self.public_key();
+        let mut leading_zero = [0u8; 66];
+        leading_zero[1..][..public_key.len()].copy_from_slice(public_key);
+        encoder.encode(&TaggedSlice::from(
+            Tag::BIT_STRING,
+            &leading_zero[..1 + public_key.len()],
+        )?)
     }
 }
 
@@ -0,0 +1,127 @@
+//! Base64 (RFC 4648), as needed for PEM, JWK and OpenSSH encodings.
+
+use crate::{
+    error::Error,
+    types::Message,
+};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Alphabet {
+    /// Section 4, padded.
+    Standard,
+    /// Section 5 ("base64url"), unpadded.
+    UrlSafe,
+}
+
+impl Alphabet {
+    fn characters(&self) -> &'static [u8; 64] {
+        match self {
+            Alphabet::Standard => b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/",
+            Alphabet::UrlSafe => b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_",
+        }
+    }
+
+    fn value(&self, character: u8) -> Option<u8> {
+        match (character, self) {
+            (b'A'..=b'Z', _) => Some(character - b'A'),
+            (b'a'..=b'z', _) => Some(character - b'a' + 26),
+            (b'0'..=b'9', _) => Some(character - b'0' + 52),
+            (b'+', Alphabet::Standard) | (b'-', Alphabet::UrlSafe) => Some(62),
+            (b'/', Alphabet::Standard) | (b'_', Alphabet::UrlSafe) => Some(63),
+            _ => None,
+        }
+    }
+}
+
+/// Appends the encoding of `data` to `output`.
+pub fn encode(output: &mut Message, data: &[u8], alphabet: Alphabet) -> Result<(), Error> {
+    let characters = alphabet.characters();
+    for chunk in data.chunks(3) {
+        let mut block = [0u8; 3];
+        block[..chunk.len()].copy_from_slice(chunk);
+        let bits = u32::from_be_bytes([0, block[0], block[1], block[2]]);
+        let mut encoded = [b'='; 4];
+        for (i, character) in encoded.iter_mut().enumerate().take(chunk.len() + 1) {
+            *character = characters[((bits >> (18 - 6 * i)) & 0x3F) as usize];
+        }
+        let length = match alphabet {
+            Alphabet::Standard => 4,
+            Alphabet::UrlSafe => chunk.len() + 1,
+        };
+        output.extend_from_slice(&encoded[..length]).map_err(|_| Error::InternalError)?;
+    }
+    Ok(())
+}
+
+/// Appends the decoding of `text` to `output`, skipping whitespace.
+///
+/// Padding is optional in either alphabet, unused bits of the last character must be zero.
+pub fn decode(output: &mut Message, text: &[u8], alphabet: Alphabet) -> Result<(), Error> {
+    let mut bits = 0u32;
+    let mut count = 0usize;
+    let mut padded = false;
+    for &character in text {
+        match character {
+            b' ' | b'\t' | b'\r' | b'\n' => continue,
+            b'=' if alphabet == Alphabet::Standard && count >= 2 => { padded = true; continue }
+            _ if padded => return Err(Error::InvalidSerializedKey),
+            _ => {}
+        }
+        let value = alphabet.value(character).ok_or(Error::InvalidSerializedKey)?;
+        bits = (bits << 6) | value as u32;
+        count += 1;
+        if count == 4 {
+            output.extend_from_slice(&bits.to_be_bytes()[1..]).map_err(|_| Error::InvalidSerializedKey)?;
+            bits = 0;
+            count = 0;
+        }
+    }
+
+    // trailing partial block
+    match count {
+        0 => {}
+        2 | 3 => {
+            // the unused bits must be zero, so each encoding is canonical
+            if bits & ((1 << (2 * (4 - count))) - 1) != 0 {
+                return Err(Error::InvalidSerializedKey);
+            }
+            let bits = bits << (6 * (4 - count));
+            output.extend_from_slice(&bits.to_be_bytes()[1..count])
+                .map_err(|_| Error::InvalidSerializedKey)?;
+        }
+        _ => return Err(Error::InvalidSerializedKey),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let data = [0xFBu8, 0xEF, 0xBE, 0x12, 0x34];
+        for length in 0..=data.len() {
+            for alphabet in [Alphabet::Standard, Alphabet::UrlSafe].iter() {
+                let mut encoded = Message::new();
+                encode(&mut encoded, &data[..length], *alphabet).unwrap();
+                let mut decoded = Message::new();
+                decode(&mut decoded, &encoded, *alphabet).unwrap();
+                assert_eq!(&decoded[..], &data[..length]);
+            }
+        }
+    }
+
+    #[test]
+    fn non_canonical() {
+        let mut decoded = Message::new();
+        decode(&mut decoded, b"/w==", Alphabet::Standard).unwrap();
+        assert_eq!(&decoded[..], &[0xFF]);
+        decode(&mut decoded, b"__8", Alphabet::UrlSafe).unwrap();
+        assert_eq!(&decoded[1..], &[0xFF, 0xFF]);
+
+        // the same bytes, with unused bits set
+        assert_eq!(decode(&mut Message::new(), b"/x==", Alphabet::Standard), Err(Error::InvalidSerializedKey));
+        assert_eq!(decode(&mut Message::new(), b"__9", Alphabet::UrlSafe), Err(Error::InvalidSerializedKey));
+    }
+}
@@ -0,0 +1,187 @@
+//! JSON Web Key (RFC 7517) encodings of public keys.
+//!
+//! P256 keys are of type "EC" (RFC 7518, section 6.2), Ed255 and X255 keys
+//! of type "OKP" (RFC 8037, section 2).
+
+use crate::{
+    error::Error,
+    key,
+    types::Message,
+};
+
+use super::base64;
+use super::der::Algorithm;
+
+impl Algorithm {
+    fn jwk_type_and_curve(&self) -> (&'static str, &'static str) {
+        match self {
+            Algorithm::Ed255 => ("OKP", "Ed25519"),
+            Algorithm::P256 => ("EC", "P-256"),
+            Algorithm::X255 => ("OKP", "X25519"),
+        }
+    }
+}
+
+/// Serializes a public key, given in `KeySerialization::Raw` format.
+///
+/// The members are in lexicographic order without whitespace, so the SHA-256 digest
+/// of the output is the key's JWK thumbprint (RFC 7638).
+pub fn serialize_public_key(algorithm: Algorithm, raw: &[u8]) -> Result<Message, Error> {
+    fn push(jwk: &mut Message, data: &[u8]) -> Result<(), Error> {
+        jwk.extend_from_slice(data).map_err(|_| Error::InternalError)
+    }
+
+    let (kty, crv) = algorithm.jwk_type_and_curve();
+    let mut jwk = Message::new();
+    push(&mut jwk, b"{\"crv\":\"")?;
+    push(&mut jwk, crv.as_bytes())?;
+    push(&mut jwk, b"\",\"kty\":\"")?;
+    push(&mut jwk, kty.as_bytes())?;
+    push(&mut jwk, b"\",\"x\":\"")?;
+    match algorithm {
+        Algorithm::P256 => {
+            if raw.len() != 64 {
+                return Err(Error::InternalError);
+            }
+            base64::encode(&mut jwk, &raw[..32], base64::Alphabet::UrlSafe)?;
+            push(&mut jwk, b"\",\"y\":\"")?;
+            base64::encode(&mut jwk, &raw[32..], base64::Alphabet::UrlSafe)?;
+        }
+        Algorithm::Ed255 | Algorithm::X255 => {
+            base64::encode(&mut jwk, raw, base64::Alphabet::UrlSafe)?;
+        }
+    }
+    push(&mut jwk, b"\"}")?;
+    Ok(jwk)
+}
+
+/// Deserializes a public key, returning it in `KeySerialization::Raw` format.
+///
+/// Members other than "kty", "crv", "x" and "y" are ignored, in particular a private "d".
+/// String values with escapes are not supported, nor are nested objects.
+pub fn deserialize_public_key(algorithm: Algorithm, jwk: &[u8]) -> Result<key::Material, Error> {
+    let (expected_kty, expected_crv) = algorithm.jwk_type_and_curve();
+    let (mut kty, mut crv, mut x, mut y) = (None, None, None, None);
+
+    let mut parser = Parser(jwk);
+    parser.expect(b'{')?;
+    if !parser.consume(b'}') {
+        loop {
+            let name = parser.string()?;
+            parser.expect(b':')?;
+            let value = parser.value()?;
+            match name {
+                b"kty" => kty = value,
+                b"crv" => crv = value,
+                b"x" => x = value,
+                b"y" => y = value,
+                _ => {}
+            }
+            if parser.consume(b'}') {
+                break;
+            }
+            parser.expect(b',')?;
+        }
+    }
+    parser.finish()?;
+
+    if kty != Some(expected_kty.as_bytes()) || crv != Some(expected_crv.as_bytes()) {
+        return Err(Error::InvalidSerializedKey);
+    }
+
+    let mut raw = Message::new();
+    base64::decode(&mut raw, x.ok_or(Error::InvalidSerializedKey)?, base64::Alphabet::UrlSafe)?;
+    if raw.len() != 32 {
+        return Err(Error::InvalidSerializedKey);
+    }
+    if algorithm == Algorithm::P256 {
+        base64::decode(&mut raw, y.ok_or(Error::InvalidSerializedKey)?, base64::Alphabet::UrlSafe)?;
+        if raw.len() != 64 {
+            return Err(Error::InvalidSerializedKey);
+        }
+    }
+
+    key::Material::try_from_slice(&raw).map_err(|_| Error::InvalidSerializedKey)
+}
+
+/// Minimal JSON parser, sufficient for flat JWK objects.
+struct Parser<'a>(&'a [u8]);
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        while let Some((b' ' | b'\t' | b'\r' | b'\n', rest)) = self.0.split_first() {
+            self.0 = rest;
+        }
+    }
+
+    fn consume(&mut self, character: u8) -> bool {
+        self.skip_whitespace();
+        match self.0.split_first() {
+            Some((&actual, rest)) if actual == character => {
+                self.0 = rest;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, character: u8) -> Result<(), Error> {
+        if self.consume(character) {
+            Ok(())
+        } else {
+            Err(Error::InvalidSerializedKey)
+        }
+    }
+
+    fn string(&mut self) -> Result<&'a [u8], Error> {
+        self.expect(b'"')?;
+        let length = self.0.iter().position(|&c| c == b'"').ok_or(Error::InvalidSerializedKey)?;
+        let (string, rest) = self.0.split_at(length);
+        if string.iter().any(|&c| c == b'\\' || c < 0x20) {
+            return Err(Error::InvalidSerializedKey);
+        }
+        self.0 = &rest[1..];
+        Ok(string)
+    }
+
+    /// Parses a value, returning it if it is a string.
+    fn value(&mut self) -> Result<Option<&'a [u8]>, Error> {
+        self.skip_whitespace();
+        match self.0.first() {
+            Some(b'"') => return self.string().map(Some),
+            // e.g. "key_ops": ["verify"]
+            Some(b'[') => {
+                self.expect(b'[')?;
+                if !self.consume(b']') {
+                    loop {
+                        self.value()?;
+                        if self.consume(b']') {
+                            break;
+                        }
+                        self.expect(b',')?;
+                    }
+                }
+            }
+            // e.g. "ext": true
+            _ => {
+                let length = self.0.iter()
+                    .position(|c| !(c.is_ascii_alphanumeric() || b"+-.".contains(c)))
+                    .unwrap_or(self.0.len());
+                if length == 0 {
+                    return Err(Error::InvalidSerializedKey);
+                }
+                self.0 = &self.0[length..];
+            }
+        }
+        Ok(None)
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.skip_whitespace();
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::InvalidSerializedKey)
+        }
+    }
+}
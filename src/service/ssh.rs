@@ -0,0 +1,262 @@
+//! OpenSSH encodings of public keys (RFC 4253, section 6.6; RFC 5656, section 3.1; RFC 8709)
+//! and signatures (RFC 5656, section 3.1.2; RFC 8709, section 6), including those of
+//! FIDO security keys ("sk-" key types, see `PROTOCOL.u2f` in OpenSSH).
+
+use crate::{
+    ArrayLength,
+    Bytes,
+    error::Error,
+    types::{Message, Signature, SignatureSerialization},
+};
+
+use core::convert::TryInto;
+
+use super::base64;
+use super::der::Algorithm;
+
+const SSH_ED25519: &[u8] = b"ssh-ed25519";
+const ECDSA_SHA2_NISTP256: &[u8] = b"ecdsa-sha2-nistp256";
+const NISTP256: &[u8] = b"nistp256";
+const SK_SSH_ED25519: &[u8] = b"sk-ssh-ed25519@openssh.com";
+const SK_ECDSA_SHA2_NISTP256: &[u8] = b"sk-ecdsa-sha2-nistp256@openssh.com";
+
+/// SSH wire encoding, as in RFC 4251, section 5.
+struct Writer<'a, N: ArrayLength<u8>>(&'a mut Bytes<N>);
+
+impl<N: ArrayLength<u8>> Writer<'_, N> {
+    fn raw(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.0.extend_from_slice(data).map_err(|_| Error::InternalError)
+    }
+
+    fn string(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.raw(&(data.len() as u32).to_be_bytes())?;
+        self.raw(data)
+    }
+
+    /// Non-negative `mpint`, given in big-endian.
+    fn mpint(&mut self, data: &[u8]) -> Result<(), Error> {
+        let first_nonzero = data.iter().position(|&byte| byte != 0).unwrap_or(data.len());
+        let data = &data[first_nonzero..];
+        let sign_byte = matches!(data.first(), Some(&byte) if byte >= 0x80);
+        self.raw(&(data.len() as u32 + sign_byte as u32).to_be_bytes())?;
+        if sign_byte {
+            self.raw(&[0])?;
+        }
+        self.raw(data)
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn raw(&mut self, length: usize) -> Result<&'a [u8], Error> {
+        if self.0.len() < length {
+            return Err(Error::InvalidSerializationFormat);
+        }
+        let (data, rest) = self.0.split_at(length);
+        self.0 = rest;
+        Ok(data)
+    }
+
+    fn string(&mut self) -> Result<&'a [u8], Error> {
+        let length = u32::from_be_bytes(self.raw(4)?.try_into().unwrap());
+        self.raw(length as usize)
+    }
+
+    /// Non-negative `mpint` of at most 32 bytes, left-padded to 32 bytes.
+    ///
+    /// Only the canonical encoding is accepted: zero is the empty string, and a leading
+    /// zero byte is present exactly if the next byte has its high bit set.
+    fn mpint(&mut self) -> Result<[u8; 32], Error> {
+        let data = match self.string()? {
+            [] => &[][..],
+            [byte, ..] if byte & 0x80 != 0 => return Err(Error::InvalidSerializationFormat),
+            [0, rest @ ..] => match rest.first() {
+                Some(byte) if byte & 0x80 != 0 => rest,
+                _ => return Err(Error::InvalidSerializationFormat),
+            },
+            data => data,
+        };
+        if data.len() > 32 {
+            return Err(Error::InvalidSerializationFormat);
+        }
+        let mut padded = [0u8; 32];
+        padded[32 - data.len()..].copy_from_slice(data);
+        Ok(padded)
+    }
+
+    fn finish(&self) -> Result<(), Error> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::InvalidSerializationFormat)
+        }
+    }
+}
+
+/// The trailer of signatures by FIDO security keys, from the authenticator data.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SkTrailer {
+    pub flags: u8,
+    pub counter: u32,
+}
+
+impl SkTrailer {
+    /// The trailer of the signature format, if it is `OpenSshSk`.
+    pub fn of(format: SignatureSerialization) -> Option<Self> {
+        match format {
+            SignatureSerialization::OpenSshSk { flags, counter } => Some(Self { flags, counter }),
+            _ => None,
+        }
+    }
+}
+
+/// Serializes a public key, given in `KeySerialization::Raw` format, as `authorized_keys` line
+/// (without comment or trailing newline).
+///
+/// There is no SSH key type for X255.
+pub fn serialize_public_key(algorithm: Algorithm, raw: &[u8]) -> Result<Message, Error> {
+    let mut blob = Message::new();
+    let mut writer = Writer(&mut blob);
+    let key_type = match algorithm {
+        Algorithm::Ed255 => {
+            writer.string(SSH_ED25519)?;
+            writer.string(raw)?;
+            SSH_ED25519
+        }
+        Algorithm::P256 => {
+            writer.string(ECDSA_SHA2_NISTP256)?;
+            writer.string(NISTP256)?;
+            // uncompressed SEC1 point
+            writer.raw(&(1 + raw.len() as u32).to_be_bytes())?;
+            writer.raw(&[0x04])?;
+            writer.raw(raw)?;
+            ECDSA_SHA2_NISTP256
+        }
+        Algorithm::X255 => return Err(Error::InvalidSerializationFormat),
+    };
+
+    let mut line = Message::new();
+    line.extend_from_slice(key_type).map_err(|_| Error::InternalError)?;
+    line.extend_from_slice(b" ").map_err(|_| Error::InternalError)?;
+    base64::encode(&mut line, &blob, base64::Alphabet::Standard)?;
+    Ok(line)
+}
+
+/// Serializes a signature, given in `SignatureSerialization::Raw` format, as signature blob.
+///
+/// For "sk-" keys, the signed message must be the data constructed by the authenticator.
+pub fn serialize_signature(algorithm: Algorithm, raw: &[u8], sk: Option<SkTrailer>) -> Result<Signature, Error> {
+    if raw.len() != 64 {
+        return Err(Error::InternalError);
+    }
+
+    let mut blob = Signature::new();
+    let mut writer = Writer(&mut blob);
+    match (algorithm, sk) {
+        (Algorithm::Ed255, None) => writer.string(SSH_ED25519)?,
+        (Algorithm::Ed255, Some(_)) => writer.string(SK_SSH_ED25519)?,
+        (Algorithm::P256, None) => writer.string(ECDSA_SHA2_NISTP256)?,
+        (Algorithm::P256, Some(_)) => writer.string(SK_ECDSA_SHA2_NISTP256)?,
+        (Algorithm::X255, _) => return Err(Error::InvalidSerializationFormat),
+    }
+    match algorithm {
+        Algorithm::P256 => {
+            let mut rs = Bytes::<crate::consts::U74>::new();
+            let mut rs_writer = Writer(&mut rs);
+            rs_writer.mpint(&raw[..32])?;
+            rs_writer.mpint(&raw[32..])?;
+            writer.string(&rs)?;
+        }
+        _ => writer.string(raw)?,
+    }
+    if let Some(SkTrailer { flags, counter }) = sk {
+        writer.raw(&[flags])?;
+        writer.raw(&counter.to_be_bytes())?;
+    }
+    Ok(blob)
+}
+
+/// Deserializes a signature blob, returning it in `SignatureSerialization::Raw` format,
+/// and for "sk-" signatures the trailer.
+///
+/// The trailer is part of the signed data, but callers still need to compare it
+/// with the expected flags and counter.
+pub fn deserialize_signature(algorithm: Algorithm, blob: &[u8], sk: bool) -> Result<([u8; 64], Option<SkTrailer>), Error> {
+    let mut reader = Reader(blob);
+    let expected_type = match (algorithm, sk) {
+        (Algorithm::Ed255, false) => SSH_ED25519,
+        (Algorithm::Ed255, true) => SK_SSH_ED25519,
+        (Algorithm::P256, false) => ECDSA_SHA2_NISTP256,
+        (Algorithm::P256, true) => SK_ECDSA_SHA2_NISTP256,
+        (Algorithm::X255, _) => return Err(Error::InvalidSerializationFormat),
+    };
+    if reader.string()? != expected_type {
+        return Err(Error::InvalidSerializationFormat);
+    }
+
+    let mut raw = [0u8; 64];
+    let signature = reader.string()?;
+    match algorithm {
+        Algorithm::P256 => {
+            let mut rs = Reader(signature);
+            raw[..32].copy_from_slice(&rs.mpint()?);
+            raw[32..].copy_from_slice(&rs.mpint()?);
+            rs.finish()?;
+        }
+        _ => {
+            if signature.len() != 64 {
+                return Err(Error::InvalidSerializationFormat);
+            }
+            raw.copy_from_slice(signature);
+        }
+    }
+    let trailer = if sk {
+        let flags = reader.raw(1)?[0];
+        let counter = u32::from_be_bytes(reader.raw(4)?.try_into().unwrap());
+        Some(SkTrailer { flags, counter })
+    } else {
+        None
+    };
+    reader.finish()?;
+    Ok((raw, trailer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn p256_blob(r: &[u8], s: &[u8]) -> Message {
+        let mut rs = Message::new();
+        let mut writer = Writer(&mut rs);
+        writer.string(r).unwrap();
+        writer.string(s).unwrap();
+        let mut blob = Message::new();
+        let mut writer = Writer(&mut blob);
+        writer.string(ECDSA_SHA2_NISTP256).unwrap();
+        writer.string(&rs).unwrap();
+        blob
+    }
+
+    #[test]
+    fn canonical_mpints() {
+        let (raw, trailer) = deserialize_signature(Algorithm::P256, &p256_blob(&[0x00, 0x80], &[0x7F]), false).unwrap();
+        assert_eq!((raw[31], raw[63], trailer), (0x80, 0x7F, None));
+        // zero
+        assert!(deserialize_signature(Algorithm::P256, &p256_blob(&[], &[0x7F]), false).is_ok());
+
+        for (r, s) in [
+            // superfluous leading zero, also for zero
+            (&[0x00, 0x7F][..], &[0x7F][..]),
+            (&[0x7F][..], &[0x00][..]),
+            // negative
+            (&[0x80][..], &[0x7F][..]),
+            (&[0x7F][..], &[0xFF, 0x80][..]),
+        ].iter() {
+            assert_eq!(
+                deserialize_signature(Algorithm::P256, &p256_blob(r, s), false),
+                Err(Error::InvalidSerializationFormat),
+            );
+        }
+    }
+}
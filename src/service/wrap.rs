@@ -0,0 +1,132 @@
+//! The envelope of wrapped keys, produced by all `WrapKey` mechanisms that can be unwrapped.
+//!
+//! ```text
+//! version (1) | mechanism (1) | header length (2) | header | nonce length (1) | nonce | tag length (1) | tag | encrypted material
+//! ```
+//!
+//! The header is the serialized key without its material, i.e. its format version, flags, kind,
+//! usage policy, label and creation time (see `key::Key::serialize`), the header length is big-endian, and the
+//! mechanism is encoded as in key policies (`Mechanism::code`).
+//!
+//! Everything up to the end of the header, followed by the associated data of the request,
+//! is authenticated by the wrapping mechanism.
+
+use crate::{
+    Bytes,
+    consts,
+    error::Error,
+    key,
+    types::{Mechanism, Message, ShortData, StorageAttributes},
+};
+
+pub const ENVELOPE_VERSION: u8 = 1;
+
+/// Envelope header and associated data of the request.
+pub type AuthenticatedData = Bytes<consts::U2048>;
+
+/// A wrapped key, borrowed from its envelope.
+pub struct Envelope<'a> {
+    pub mechanism: Mechanism,
+    pub nonce: &'a [u8],
+    pub tag: &'a [u8],
+    pub ciphertext: &'a [u8],
+    authenticated: &'a [u8],
+    header: &'a [u8],
+}
+
+/// Wraps the key with `encrypt`, which encrypts the material in place, authenticating
+/// the given data, and returns the nonce and tag.
+pub fn wrap(
+    mechanism: Mechanism,
+    key: &key::Key,
+    associated_data: &[u8],
+    encrypt: impl FnOnce(&mut [u8], &[u8]) -> Result<(ShortData, ShortData), Error>,
+) -> Result<Message, Error> {
+    let serialized = key.serialize();
+    let header = &serialized[..serialized.len() - key.material.len()];
+
+    let mut wrapped = Message::new();
+    wrapped.extend_from_slice(&[ENVELOPE_VERSION, mechanism.code()]).map_err(|_| Error::InternalError)?;
+    wrapped.extend_from_slice(&(header.len() as u16).to_be_bytes()).map_err(|_| Error::InternalError)?;
+    wrapped.extend_from_slice(header).map_err(|_| Error::InternalError)?;
+
+    let mut material = key.material.clone();
+    let (nonce, tag) = encrypt(&mut material[..], &authenticated_data(&wrapped, associated_data)?[..])?;
+
+    for field in [&nonce[..], &tag[..]].iter() {
+        wrapped.push(field.len() as u8).map_err(|_| Error::InternalError)?;
+        wrapped.extend_from_slice(field).map_err(|_| Error::InternalError)?;
+    }
+    wrapped.extend_from_slice(&material).map_err(|_| Error::InternalError)?;
+    Ok(wrapped)
+}
+
+fn authenticated_data(header: &[u8], associated_data: &[u8]) -> Result<AuthenticatedData, Error> {
+    let mut data = AuthenticatedData::try_from_slice(header).map_err(|_| Error::InternalError)?;
+    data.extend_from_slice(associated_data).map_err(|_| Error::InternalError)?;
+    Ok(data)
+}
+
+/// Splits off a field with a big-endian length prefix of the given size.
+fn split_field(bytes: &[u8], prefix_size: usize) -> Result<(&[u8], &[u8]), Error> {
+    if bytes.len() < prefix_size {
+        return Err(Error::WrappedKeyInvalid);
+    }
+    let (length, rest) = bytes.split_at(prefix_size);
+    let length = length.iter().fold(0, |length, byte| (length << 8) | *byte as usize);
+    if rest.len() < length {
+        return Err(Error::WrappedKeyInvalid);
+    }
+    Ok(rest.split_at(length))
+}
+
+impl<'a> Envelope<'a> {
+    pub fn parse(wrapped: &'a [u8]) -> Result<Self, Error> {
+        let (version, mechanism, rest) = match wrapped {
+            [version, mechanism, rest @ ..] => (*version, *mechanism, rest),
+            _ => return Err(Error::WrappedKeyInvalid),
+        };
+        if version != ENVELOPE_VERSION {
+            return Err(Error::WrappedKeyInvalid);
+        }
+        let mechanism = Mechanism::try_from_code(mechanism).map_err(|_| Error::WrappedKeyInvalid)?;
+        let (header, rest) = split_field(rest, 2)?;
+        let (nonce, rest) = split_field(rest, 1)?;
+        let (tag, ciphertext) = split_field(rest, 1)?;
+
+        Ok(Self {
+            mechanism,
+            nonce,
+            tag,
+            ciphertext,
+            authenticated: &wrapped[..4 + header.len()],
+            header,
+        })
+    }
+
+    /// The data the mechanism must authenticate, given the associated data of the request.
+    pub fn authenticated_data(&self, associated_data: &[u8]) -> Result<AuthenticatedData, Error> {
+        authenticated_data(self.authenticated, associated_data)
+    }
+
+    /// The wrapped key, given its decrypted material.
+    pub fn key(&self, material: &[u8]) -> Result<key::Key, Error> {
+        let mut serialized = key::SerializedKeyBytes::try_from_slice(self.header)
+            .map_err(|_| Error::WrappedKeyInvalid)?;
+        serialized.extend_from_slice(material).map_err(|_| Error::WrappedKeyInvalid)?;
+        key::Key::try_deserialize(&serialized).map_err(|_| Error::WrappedKeyInvalid)
+    }
+}
+
+/// The header of an unwrapped key: the attributes of the request may restrict those
+/// of the wrapped key, but not lift its restrictions.
+///
+/// As the key was outside the service, it is not LOCAL.
+pub fn unwrapped_info(key: &key::Key, attributes: &StorageAttributes) -> key::Info {
+    let mut info = key::Info::from(key.kind)
+        .with_policy(&key.policy.intersection(&attributes.policy))
+        .with_label(attributes.label.as_ref().or_else(|| key.label.as_ref()));
+    info.flags.set(key::Flags::EXTRACTABLE, key.flags.contains(key::Flags::EXTRACTABLE) && attributes.extractable);
+    info.flags.set(key::Flags::PRIVATE, key.flags.contains(key::Flags::PRIVATE) || attributes.private);
+    info
+}
@@ -78,6 +78,8 @@ pub mod certstore;
 pub mod counterstore;
 pub mod filestore;
 pub mod keystore;
+pub mod objectstore;
+pub mod pinstore;
 
 // pub type FileContents = Bytes<MAX_FILE_SIZE>;
 
@@ -523,6 +525,37 @@ where
     outcome.map_err(|_| Error::FilesystemWriteFailure)
 }
 
+/// Calls `f` with each file in the directory at path in location of store.
+///
+/// A missing directory contains no files.
+pub fn for_each_file<F>(store: impl Store, location: Location, path: &Path, mut f: F) -> Result<(), Error>
+where
+    F: FnMut(&DirEntry),
+{
+    debug_now!("listing {}", &path);
+    match location {
+        Location::Internal => for_each_file_in(store.ifs(), path, &mut f),
+        Location::External => for_each_file_in(store.efs(), path, &mut f),
+        Location::Volatile => for_each_file_in(store.vfs(), path, &mut f),
+    }
+}
+
+fn for_each_file_in<S: LfsStorage>(fs: &Filesystem<'_, S>, path: &Path, f: &mut impl FnMut(&DirEntry)) -> Result<(), Error> {
+    if !path.exists(fs) {
+        return Ok(());
+    }
+    fs.read_dir_and_then(path, |dir| {
+        for entry in dir {
+            let entry = entry?;
+            // skips over directories, including `.` and `..`
+            if entry.file_type().is_file() {
+                f(&entry);
+            }
+        }
+        Ok(())
+    }).map_err(|_| Error::FilesystemReadFailure)
+}
+
 // pub fn delete_volatile(store: impl Store, handle: &ObjectHandle) -> bool {
 //     let secrecies = [
 //         Secrecy::Secret,
@@ -7,33 +7,39 @@ use crate::{
     consts,
     error::{Error, Result},
     store::{self, Store},
-    types::{ClientId, Id, Location, Message},
+    types::{Attributes, CertificateAttributes, CertificateType, ClientId, Id, Location, Message},
 };
 
-use super::counterstore::Counterstore;
+use super::objectstore::{ClientObjectstore, ObjectId, Objectstore};
 
 
+/// The certificates of a client, which are objects like those of `CreateObject`,
+/// so either request finds them.
 pub struct ClientCertstore<S>
 where
     S: Store,
 {
     client_id: ClientId,
     store: S,
+    objectstore: ClientObjectstore<S>,
 }
 
 pub trait Certstore {
     fn delete_certificate(&mut self, id: Id) -> Result<()>;
     fn read_certificate(&mut self, id: Id) -> Result<Message>;
-    /// TODO: feels a bit heavy-weight to pass in the ClientCounterstore here
-    /// just to ensure the next global counter ("counter zero") is used, and
-    /// not something random.
-    fn write_certificate(&mut self, location: Location, der: &Message, counterstore: &mut impl Counterstore) -> Result<Id>;
+    /// Store the certificate as an object with the given new ID.
+    fn write_certificate(&mut self, location: Location, der: &Message, id: ObjectId) -> Result<Id>;
 }
 
 impl<S: Store> Certstore for ClientCertstore<S> {
 
     fn delete_certificate(&mut self, id: Id) -> Result<()> {
-        let path = self.cert_path(id);
+        if let Ok((Attributes::Certificate(_), _)) = self.objectstore.load_object(&ObjectId::from(id)) {
+            self.objectstore.delete_object(&ObjectId::from(id));
+            return Ok(());
+        }
+
+        let path = self.legacy_cert_path(id);
         let locations = [
             Location::Internal,
             Location::External,
@@ -45,7 +51,13 @@ impl<S: Store> Certstore for ClientCertstore<S> {
     }
 
     fn read_certificate(&mut self, id: Id) -> Result<Message> {
-        let path = self.cert_path(id);
+        match self.objectstore.load_object(&ObjectId::from(id)) {
+            Ok((Attributes::Certificate(attributes), _)) => return Ok(attributes.der),
+            Ok(_) => return Err(Error::NoSuchCertificate),
+            Err(_) => {}
+        }
+
+        let path = self.legacy_cert_path(id);
         let locations = [
             Location::Internal,
             Location::External,
@@ -56,39 +68,32 @@ impl<S: Store> Certstore for ClientCertstore<S> {
         }).ok_or(Error::NoSuchCertificate)
     }
 
-    fn write_certificate(&mut self, location: Location, der: &Message, counterstore: &mut impl Counterstore) -> Result<Id> {
-        let id = Id(counterstore.increment_counter_zero());
-        let path = self.cert_path(id);
-        store::store(self.store, location, &path, &der.as_slice())?;
-        Ok(id)
+    fn write_certificate(&mut self, location: Location, der: &Message, id: ObjectId) -> Result<Id> {
+        let attributes = Attributes::Certificate(CertificateAttributes {
+            certificate_type: CertificateType::PublicKey,
+            label: None,
+            der: der.clone(),
+        });
+        self.objectstore.store_object(location, &id, &attributes)?;
+        Ok(Id::from(id))
     }
 }
 
 impl<S: Store> ClientCertstore<S> {
     pub fn new(client_id: ClientId, store: S) -> Self {
-        Self { client_id, store }
+        let objectstore = ClientObjectstore::new(client_id.clone(), store);
+        Self { client_id, store, objectstore }
     }
 
-    fn cert_path(&self, id: Id) -> PathBuf {
+    /// The path of certificates written before they were objects, named by a counter.
+    fn legacy_cert_path(&self, id: Id) -> PathBuf {
         let mut path = PathBuf::new();
         path.push(&self.client_id);
         path.push(&PathBuf::from("x5c"));
-        let mut buf = Bytes::<consts::U32>::new();
+        let mut buf = Bytes::<consts::U40>::new();
         write!(&mut buf, "{}", id.0).ok();
         path.push(&PathBuf::from(buf.as_slice()));
         path
     }
 
-    // fn read_cert(&mut self, location: Location, id: u128) -> Result<Message> {
-    //     let path = self.cert_path(id);
-    //     let mut data: Message = store::read(self.store, location, &path)?;
-    //     Ok(data)
-    // }
-
-    // fn write_cert(&mut self, location: Location, id: u128, data: Message) -> Result<()> {
-    //     let path = self.cert_path(id);
-    //     store::store(self.store, location, &path, &data)
-    // }
-
 }
-
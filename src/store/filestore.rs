@@ -38,12 +38,17 @@ impl<S: Store> ClientFilestore<S> {
     }
 
     /// Client files are store below `/<client_id>/dat/`.
-    pub fn actual_path(&self, client_path: &PathBuf) -> PathBuf {
+    ///
+    /// Paths with `..` components are rejected, as they could reach other clients' files.
+    pub fn actual_path(&self, client_path: &PathBuf) -> Result<PathBuf> {
+        if client_path.as_ref().as_bytes().split(|&byte| byte == b'/').any(|component| component == b"..") {
+            return Err(Error::InvalidPath);
+        }
         let mut path = PathBuf::new();
         path.push(&self.client_id);
         path.push(&PathBuf::from("dat"));
         path.push(client_path);
-        path
+        Ok(path)
     }
 
     // TODO: this is waaay too fiddly, need an approach
@@ -107,23 +112,25 @@ pub trait Filestore {
 
 impl<S: Store> Filestore for ClientFilestore<S> {
     fn read<N: ArrayLength<u8>>(&mut self, path: &PathBuf, location: Location) -> Result<Bytes<N>> {
-        let path = self.actual_path(path);
+        let path = self.actual_path(path)?;
 
         store::read(self.store, location, &path)
     }
 
     fn write(&mut self, path: &PathBuf, location: Location, data: &[u8]) -> Result<()> {
-        let path = self.actual_path(path);
+        let path = self.actual_path(path)?;
         store::store(self.store, location, &path, data)
     }
 
     fn exists(&mut self, path: &PathBuf, location: Location) -> bool {
-        let path = self.actual_path(path);
-        store::exists(self.store, location, &path)
+        match self.actual_path(path) {
+            Ok(path) => store::exists(self.store, location, &path),
+            Err(_) => false,
+        }
     }
 
     fn remove_file(&mut self, path: &PathBuf, location: Location) -> Result<()> {
-        let path = self.actual_path(path);
+        let path = self.actual_path(path)?;
 
         match store::delete(self.store, location, &path) {
             true => Ok(()),
@@ -132,7 +139,7 @@ impl<S: Store> Filestore for ClientFilestore<S> {
     }
 
     fn remove_dir(&mut self, path: &PathBuf, location: Location) -> Result<()> {
-        let path = self.actual_path(path);
+        let path = self.actual_path(path)?;
 
         match store::delete(self.store, location, &path) {
             true => Ok(()),
@@ -141,7 +148,7 @@ impl<S: Store> Filestore for ClientFilestore<S> {
     }
 
     fn remove_dir_all(&mut self, path: &PathBuf, location: Location) -> Result<usize> {
-        let path = self.actual_path(path);
+        let path = self.actual_path(path)?;
 
         store::remove_dir_all_where(self.store, location, &path, |_| true)
             .map_err(|_| Error::InternalError)
@@ -153,7 +160,7 @@ impl<S: Store> Filestore for ClientFilestore<S> {
         }
         let fs = self.store.ifs();
 
-        let dir = self.actual_path(clients_dir);
+        let dir = self.actual_path(clients_dir)?;
 
         Ok(fs.read_dir_and_then(&dir, |it| {
 
@@ -237,7 +244,7 @@ impl<S: Store> Filestore for ClientFilestore<S> {
         }
         let fs = self.store.ifs();
 
-        let dir = self.actual_path(clients_dir);
+        let dir = self.actual_path(clients_dir)?;
 
         Ok(fs.read_dir_and_then(&dir, |it| {
 
@@ -334,7 +341,7 @@ impl<S: Store> Filestore for ClientFilestore<S> {
         }
 
         let clients_dir = underneath.unwrap_or_else(|| PathBuf::from("/"));
-        let dir = self.actual_path(&clients_dir);
+        let dir = self.actual_path(&clients_dir)?;
         let fs = self.store.ifs();
 
         info_now!("base dir {:?}", &dir);
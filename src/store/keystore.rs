@@ -1,20 +1,24 @@
 use chacha20::ChaCha8Rng;
 pub use heapless::consts;
-use littlefs2::path::PathBuf;
+use littlefs2::path::{Path, PathBuf};
 use rand_core::RngCore as _;
 
 use crate::{
-    Bytes,
+    config::{MAX_OBJECT_HANDLES, MAX_STORED_KEY_LENGTH},
     error::{Error, Result},
     key,
+    platform::{self, HardwareUniqueKey},
     Platform,
     store::{self, Store as _},
-    types::Location as Location,
+    types::{Bytes, GenericArray, KeyEntry, Location, ObjectHandle, ObjectTemplate, Vec},
 };
 
 
 pub type ClientId = littlefs2::path::PathBuf;
 pub type KeyId = crate::types::UniqueId;
+/// Per-client key-encryption key, for key files encrypted at rest.
+pub type Kek = [u8; 32];
+pub type StoredKeyBytes = Bytes<MAX_STORED_KEY_LENGTH>;
 
 pub struct ClientKeystore<P>
 where
@@ -23,15 +27,418 @@ where
     client_id: ClientId,
     drbg: ChaCha8Rng,
     store: P::S,
+    keks: Option<Keks>,
+    granted: Option<(KeyId, key::Usage)>,
+    grantor_keks: Option<Keks>,
+    slot: Option<(KeyId, bool)>,
+    now: Option<u64>,
 }
 
 impl<'a, P: Platform> ClientKeystore<P> {
     pub fn new(client_id: ClientId, drbg: ChaCha8Rng, store: P::S) -> Self {
-        Self { client_id, drbg, store }
+        Self { client_id, drbg, store, keks: None, granted: None, grantor_keks: None, slot: None, now: None }
     }
+
+    /// The next stored key gets the well-known ID, replacing a key with it only if allowed.
+    pub fn with_slot(mut self, slot: Option<(KeyId, bool)>) -> Self {
+        self.slot = slot;
+        self
+    }
+
+    /// Stored keys are stamped with the time since the Unix epoch, if known, as their creation time.
+    pub fn with_time(mut self, now: Option<u64>) -> Self {
+        self.now = now;
+        self
+    }
+
+    /// With KEKs, key files are encrypted at rest, and plaintext key files are rejected.
+    pub fn with_keks(mut self, keks: Option<Keks>) -> Self {
+        self.keks = keks;
+        self
+    }
+
+    /// The key a request operates with may be granted by another client, for the request's
+    /// usage, and is read with the KEKs of its owner. No other key of the request may be granted.
+    pub fn with_grant(mut self, granted: Option<(KeyId, key::Usage)>, grantor_keks: Option<Keks>) -> Self {
+        self.granted = granted;
+        self.grantor_keks = grantor_keks;
+        self
+    }
+}
+
+/// Permission of another client, the grantee, to use a secret key without exporting it.
+///
+/// Persisted in the trussed namespace at `/trussed/grants/<grantee>/<key id>`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Grant {
+    pub owner: ClientId,
+    /// The code of the key's kind, to authenticate the grantee's handles without loading the key.
+    pub kind: u16,
+    pub usage: key::Usage,
+}
+
+impl Grant {
+    fn directory(grantee: &ClientId) -> PathBuf {
+        let mut path = PathBuf::from("trussed");
+        path.push(&PathBuf::from("grants"));
+        path.push(grantee);
+        path
+    }
+
+    fn path(grantee: &ClientId, id: &KeyId) -> PathBuf {
+        let mut path = Self::directory(grantee);
+        path.push(&PathBuf::from(id.hex().as_ref()));
+        path
+    }
+
+    /// The grant of the key to the grantee, if any.
+    pub fn load(store: impl store::Store, grantee: &ClientId, id: &KeyId) -> Option<Self> {
+        let path = Self::path(grantee, id);
+        if !path.exists(&store.ifs()) {
+            return None;
+        }
+        // usage, kind and an owner of at most 255 bytes
+        let bytes: Bytes<consts::U259> = store::read(store, Location::Internal, &path).ok()?;
+        if bytes.len() <= 4 {
+            return None;
+        }
+        Some(Self {
+            owner: PathBuf::from(&bytes[4..]),
+            kind: u16::from_be_bytes([bytes[2], bytes[3]]),
+            usage: key::Usage::from_bits_truncate(u16::from_be_bytes([bytes[0], bytes[1]])),
+        })
+    }
+
+    pub fn save(&self, store: impl store::Store, grantee: &ClientId, id: &KeyId) -> Result<()> {
+        let mut bytes = Bytes::<consts::U259>::new();
+        bytes.extend_from_slice(&self.usage.bits().to_be_bytes()).unwrap();
+        bytes.extend_from_slice(&self.kind.to_be_bytes()).unwrap();
+        bytes.extend_from_slice(self.owner.as_ref().as_bytes()).unwrap();
+        store::store(store, Location::Internal, &Self::path(grantee, id), &bytes)
+    }
+
+    pub fn revoke(store: impl store::Store, grantee: &ClientId, id: &KeyId) -> bool {
+        store::delete(store, Location::Internal, &Self::path(grantee, id))
+    }
+
+    /// The keys the owner granted to the grantee, and did not delete since.
+    pub fn find(store: impl store::Store, grantee: &ClientId, owner: &ClientId) -> Result<Vec<KeyId, MAX_OBJECT_HANDLES>> {
+        let mut ids = Vec::new();
+        store::for_each_file(store, Location::Internal, &Self::directory(grantee), |entry| {
+            let id = match KeyId::try_from_hex(entry.file_name().as_ref().as_bytes()) {
+                Ok(id) => id,
+                Err(()) => return,
+            };
+            let granted = Self::load(store, grantee, &id).map_or(false, |grant| {
+                &grant.owner == owner
+                    && key_file(store, owner, key::Secrecy::Secret, &id).is_some()
+            });
+            if granted {
+                // more than MAX_OBJECT_HANDLES grants are not listed
+                ids.push(id).ok();
+            }
+        })?;
+        Ok(ids)
+    }
+}
+
+fn key_directory(client_id: &ClientId, secrecy: key::Secrecy) -> PathBuf {
+    let mut path = PathBuf::new();
+    path.push(client_id);
+    path.push(&match secrecy {
+        key::Secrecy::Secret => PathBuf::from("sec"),
+        key::Secrecy::Public => PathBuf::from("pub"),
+    });
+    path
+}
+
+fn key_path(client_id: &ClientId, secrecy: key::Secrecy, id: &KeyId) -> PathBuf {
+    let mut path = key_directory(client_id, secrecy);
+    path.push(&PathBuf::from(id.hex().as_ref()));
+    path
+}
+
+/// The path key files had before their names were the full hex of the ID,
+/// if it differs, i.e. if the ID contains zero bytes.
+///
+/// Such files are renamed when the key is next loaded.
+fn legacy_key_path(client_id: &ClientId, secrecy: key::Secrecy, id: &KeyId) -> Option<PathBuf> {
+    if !id.0.contains(&0) {
+        return None;
+    }
+    let mut path = key_directory(client_id, secrecy);
+    path.push(&PathBuf::from(id.legacy_hex().as_ref()));
+    Some(path)
+}
+
+/// The location and path of the key file, volatile first, and under its legacy name last.
+fn key_file(store: impl store::Store, client_id: &ClientId, secrecy: key::Secrecy, id: &KeyId) -> Option<(Location, PathBuf)> {
+    let path = key_path(client_id, secrecy, id);
+    if let Some(location) = key_location(store, &path) {
+        return Some((location, path));
+    }
+    let path = legacy_key_path(client_id, secrecy, id)?;
+    key_location(store, &path).map(|location| (location, path))
+}
+
+/// The ID of a key file, named by the hex of the ID.
+///
+/// Of the legacy names, only those of special IDs are recognized, as the positions of the
+/// skipped zero bytes are unknown for other IDs; their keys are listed once they are loaded.
+fn key_id(file_name: &[u8]) -> Option<KeyId> {
+    match file_name.len() {
+        2 | 32 => KeyId::try_from_hex(file_name).ok(),
+        _ => None,
+    }
+}
+
+/// Remove the file of the key under its legacy name, after it was written under its current one.
+fn remove_legacy_key_file(store: impl store::Store, location: Location, client_id: &ClientId, secrecy: key::Secrecy, id: &KeyId) {
+    if let Some(path) = legacy_key_path(client_id, secrecy, id) {
+        store::delete(store, location, &path);
+    }
+}
+
+/// The location of the key file, volatile first.
+fn key_location(store: impl store::Store, path: &Path) -> Option<Location> {
+    if path.exists(&store.vfs()) {
+        return Some(Location::Volatile);
+    }
+
+    if path.exists(&store.ifs()) {
+        return Some(Location::Internal);
+    }
+
+    if path.exists(&store.efs()) {
+        return Some(Location::External);
+    }
+
+    None
+}
+
+/// A key file of a client, whatever its name.
+struct KeyFile {
+    location: Location,
+    secrecy: key::Secrecy,
+    name: PathBuf,
+    path: PathBuf,
+}
+
+/// Secret input of a KEK version, chosen by the rotation to it; empty for the initial version.
+pub type KekSecret = Bytes<consts::U32>;
+
+/// Versions of the key-encryption keys of a client, and their secret inputs.
+///
+/// Persisted before and after rewrapping the keys, so a rotation
+/// interrupted by power loss can be resumed.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct KekState {
+    /// Version all key files were encrypted under.
+    pub current: u8,
+    /// Version of a rotation in progress, or the current version.
+    pub target: u8,
+    pub current_secret: KekSecret,
+    pub target_secret: KekSecret,
 }
 
-pub const SERIALIZATION_VERSION: u8 = 0;
+impl KekState {
+    fn path(client_id: &ClientId) -> PathBuf {
+        let mut path = PathBuf::new();
+        path.push(client_id);
+        path.push(&PathBuf::from("kek"));
+        path
+    }
+
+    /// The state is stored as current | target | length | current secret | length | target secret.
+    pub fn load(store: impl store::Store, client_id: &ClientId) -> Result<Self> {
+        let path = Self::path(client_id);
+        if !path.exists(&store.ifs()) {
+            return Ok(Self::default());
+        }
+        fn secret(bytes: &[u8]) -> Result<(KekSecret, &[u8])> {
+            match bytes {
+                [length, rest @ ..] if rest.len() >= *length as usize => {
+                    let (secret, rest) = rest.split_at(*length as usize);
+                    let secret = KekSecret::try_from_slice(secret).map_err(|_| Error::InvalidSerializationFormat)?;
+                    Ok((secret, rest))
+                }
+                _ => Err(Error::InvalidSerializationFormat),
+            }
+        }
+
+        let bytes: Bytes<consts::U68> = store::read(store, Location::Internal, &path)?;
+        match &bytes[..] {
+            [current, target, rest @ ..] => {
+                let (current_secret, rest) = secret(rest)?;
+                let (target_secret, rest) = secret(rest)?;
+                if !rest.is_empty() {
+                    return Err(Error::InvalidSerializationFormat);
+                }
+                Ok(Self { current: *current, target: *target, current_secret, target_secret })
+            }
+            _ => Err(Error::InvalidSerializationFormat),
+        }
+    }
+
+    /// Whether the state was saved, i.e. the client's key files were sealed under its KEKs.
+    pub fn exists(store: impl store::Store, client_id: &ClientId) -> bool {
+        Self::path(client_id).exists(&store.ifs())
+    }
+
+    pub fn save(&self, store: impl store::Store, client_id: &ClientId) -> Result<()> {
+        let mut bytes = Bytes::<consts::U68>::new();
+        bytes.extend_from_slice(&[self.current, self.target, self.current_secret.len() as u8]).unwrap();
+        bytes.extend_from_slice(&self.current_secret).unwrap();
+        bytes.push(self.target_secret.len() as u8).unwrap();
+        bytes.extend_from_slice(&self.target_secret).unwrap();
+        store::store(store, Location::Internal, &Self::path(client_id), &bytes)
+    }
+
+    pub fn rotating(&self) -> bool {
+        self.current != self.target
+    }
+
+    /// Start the rotation to the next version, whose KEK is derived with the secret.
+    ///
+    /// Versions are never reused, so after the last one, the KEK can no longer be rotated.
+    pub fn start_rotation(&mut self, secret: KekSecret) -> Result<()> {
+        self.target = self.current.checked_add(1).ok_or(Error::KekVersionsExhausted)?;
+        self.target_secret = secret;
+        Ok(())
+    }
+
+    /// Finish the rotation, once all key files are encrypted under the target version.
+    pub fn finish_rotation(&mut self) {
+        self.current = self.target;
+        self.current_secret = self.target_secret.clone();
+    }
+}
+
+/// The key-encryption keys of a client, derived from the hardware-unique key.
+#[derive(Clone)]
+pub struct Keks {
+    /// The KEK key files are written with, and its version.
+    pub current: (u8, Kek),
+    /// During a rotation, the KEK of key files not yet rewrapped, and its version.
+    pub previous: Option<(u8, Kek)>,
+}
+
+impl Keks {
+    pub fn derive(huk: &mut dyn HardwareUniqueKey, client_id: &ClientId, state: &KekState) -> Self {
+        let current = (state.target, derive_kek(huk, client_id, state.target, &state.target_secret));
+        let previous = if state.rotating() {
+            Some((state.current, derive_kek(huk, client_id, state.current, &state.current_secret)))
+        } else {
+            None
+        };
+        Self { current, previous }
+    }
+
+    fn get(&self, version: u8) -> Option<&Kek> {
+        core::iter::once(&self.current).chain(self.previous.as_ref())
+            .find(|(v, _)| *v == version)
+            .map(|(_, kek)| kek)
+    }
+}
+
+/// Derive a version of the key-encryption key of a client from the hardware-unique key
+/// and the secret input of the version.
+pub fn derive_kek(huk: &mut dyn HardwareUniqueKey, client_id: &ClientId, version: u8, secret: &KekSecret) -> Kek {
+    const LABEL: &[u8] = b"trussed keystore ";
+    let mut info = [0u8; LABEL.len() + 1 + 32];
+    info[..LABEL.len()].copy_from_slice(LABEL);
+    info[LABEL.len()] = version;
+    info[LABEL.len() + 1..][..secret.len()].copy_from_slice(secret);
+    platform::derive_client_key(huk, client_id, &info[..LABEL.len() + 1 + secret.len()])
+}
+
+/// Write a key file, encrypted under the current KEK if there are KEKs.
+fn write_key_file(store: impl store::Store, drbg: &mut ChaCha8Rng, keks: Option<&Keks>, location: Location, path: &Path, key: &key::Key) -> Result<()> {
+    let serialized = key.serialize();
+    match keks {
+        Some(keks) => {
+            let (version, kek) = keks.current;
+            let mut nonce = [0u8; NONCE_LENGTH];
+            drbg.fill_bytes(&mut nonce);
+            let sealed = seal(&kek, version, &nonce, path.as_ref().as_bytes(), &serialized);
+            store::store(store, location, path, &sealed)
+        }
+        None => store::store(store, location, path, &serialized),
+    }
+}
+
+/// First byte of encrypted key files.
+///
+/// Plaintext key files start with the high byte of their flags, which is either 0 or 0x80.
+const ENCRYPTED_KEY: u8 = 0x40;
+const NONCE_LENGTH: usize = 12;
+const TAG_LENGTH: usize = 16;
+// marker and KEK version
+const HEADER_LENGTH: usize = 2;
+
+/// Encrypt a serialized key, binding it to its path.
+///
+/// The format is: `ENCRYPTED_KEY` | KEK version (1 byte) | nonce (12 bytes) | ciphertext | tag (16 bytes).
+pub(crate) fn seal(kek: &Kek, version: u8, nonce: &[u8; NONCE_LENGTH], path: &[u8], serialized: &[u8]) -> StoredKeyBytes {
+    use chacha20poly1305::ChaCha8Poly1305;
+    use chacha20poly1305::aead::{AeadInPlace, NewAead};
+
+    let mut sealed = StoredKeyBytes::new();
+    sealed.extend_from_slice(&[ENCRYPTED_KEY, version]).unwrap();
+    sealed.extend_from_slice(nonce).unwrap();
+    // can't fail, since MAX_STORED_KEY_LENGTH is defined as MAX_SERIALIZED_KEY_LENGTH + 30
+    sealed.extend_from_slice(serialized).unwrap();
+
+    let aead = ChaCha8Poly1305::new(GenericArray::from_slice(kek));
+    let tag = aead.encrypt_in_place_detached(
+        GenericArray::from_slice(nonce),
+        path,
+        &mut sealed[HEADER_LENGTH + NONCE_LENGTH..],
+    ).unwrap();
+    sealed.extend_from_slice(&tag).unwrap();
+    sealed
+}
+
+/// The KEK version of an encrypted key file.
+fn sealed_version(sealed: &[u8]) -> Option<u8> {
+    match sealed {
+        [ENCRYPTED_KEY, version, ..] => Some(*version),
+        _ => None,
+    }
+}
+
+/// Decrypt an encrypted key file, checking it belongs to the path.
+pub(crate) fn open(kek: &Kek, path: &[u8], sealed: &[u8]) -> Result<key::SerializedKeyBytes> {
+    use chacha20poly1305::ChaCha8Poly1305;
+    use chacha20poly1305::aead::{AeadInPlace, NewAead};
+
+    if sealed.len() < HEADER_LENGTH + NONCE_LENGTH + TAG_LENGTH || sealed[0] != ENCRYPTED_KEY {
+        return Err(Error::InvalidSerializedKey);
+    }
+    let (nonce, rest) = sealed[HEADER_LENGTH..].split_at(NONCE_LENGTH);
+    let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LENGTH);
+
+    let mut serialized = key::SerializedKeyBytes::try_from_slice(ciphertext)
+        .map_err(|_| Error::InvalidSerializedKey)?;
+    let aead = ChaCha8Poly1305::new(GenericArray::from_slice(kek));
+    aead.decrypt_in_place_detached(
+        GenericArray::from_slice(nonce),
+        path,
+        &mut serialized,
+        GenericArray::from_slice(tag),
+    ).map_err(|_| Error::TamperedKey)?;
+    Ok(serialized)
+}
+
+pub use crate::key::SERIALIZATION_VERSION;
+
+/// State of a key listing, to continue after the last returned key.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ListKeysState {
+    pub kind: Option<key::Kind>,
+    pub secrecy: Option<key::Secrecy>,
+    pub last: KeyEntry,
+}
 
 /// Trait intended for use by mechanism implementations.
 pub trait Keystore {
@@ -44,14 +451,39 @@ pub trait Keystore {
     fn key_info(&self, secrecy: key::Secrecy, id: &KeyId) -> Option<key::Info>;
     fn delete_key(&self, id: &KeyId) -> bool;
     fn delete_all(&self, location: Location) -> Result<usize>;
-    fn load_key(&self, secrecy: key::Secrecy, kind: Option<key::Kind>, id: &KeyId) -> Result<key::Key>;
-    fn overwrite_key(&self, location: Location, secrecy: key::Secrecy, kind: key::Kind, id: &KeyId, material: &[u8]) -> Result<()>;
+    /// Upgrades key files of older formats.
+    fn load_key(&mut self, secrecy: key::Secrecy, kind: Option<key::Kind>, id: &KeyId) -> Result<key::Key>;
+    /// Store a copy of the key with a new ID in the location.
+    fn copy_key(&mut self, secrecy: key::Secrecy, id: &KeyId, location: Location) -> Result<KeyId>;
+    /// Move the key to the location, keeping its ID.
+    fn move_key(&mut self, secrecy: key::Secrecy, id: &KeyId, location: Location) -> Result<()>;
+    /// Replace the material of a loaded key in place, keeping its header,
+    /// in the owner's file for keys granted to the client.
+    fn update_key(&mut self, secrecy: key::Secrecy, id: &KeyId, material: &[u8]) -> Result<()>;
+    /// Replace the material of an existing key, keeping the given header.
+    fn overwrite_key(&mut self, location: Location, secrecy: key::Secrecy, info: impl Into<key::Info>, id: &KeyId, material: &[u8]) -> Result<()>;
     fn drbg(&mut self) -> &mut ChaCha8Rng;
     fn location(&self, secrecy: key::Secrecy, id: &KeyId) -> Option<Location>;
+    /// Return the keys matching the template after skipping `offset` matches,
+    /// and the total number of matches.
+    fn find_keys(&self, template: &ObjectTemplate, offset: usize) -> Result<(Vec<KeyId, MAX_OBJECT_HANDLES>, usize)>;
+    /// Return the first key after `after` with the given kind and secrecy, if any.
+    ///
+    /// Keys are ordered by location, secrecy and ID, so keys may be deleted during a listing.
+    fn next_key(&self, kind: Option<key::Kind>, secrecy: Option<key::Secrecy>, after: Option<&KeyEntry>) -> Result<Option<KeyEntry>>;
 }
 
 impl<P: Platform> ClientKeystore<P> {
 
+    /// The number of key files of the client in the location.
+    pub fn count_keys(&self, location: Location) -> Result<usize> {
+        let mut count = 0;
+        for secrecy in [key::Secrecy::Secret, key::Secrecy::Public].iter() {
+            store::for_each_file(self.store, location, &self.key_directory(*secrecy), |_| count += 1)?;
+        }
+        Ok(count)
+    }
+
     pub fn generate_key_id(&mut self) -> KeyId {
         let mut id = [0u8; 16];
 
@@ -60,19 +492,221 @@ impl<P: Platform> ClientKeystore<P> {
     }
 
     pub fn key_directory(&self, secrecy: key::Secrecy) -> PathBuf {
-        let mut path = PathBuf::new();
-        path.push(&self.client_id);
-        path.push(&match secrecy {
-            key::Secrecy::Secret => PathBuf::from("sec"),
-            key::Secrecy::Public => PathBuf::from("pub"),
-        });
-        path
+        key_directory(&self.client_id, secrecy)
     }
 
     pub fn key_path(&self, secrecy: key::Secrecy, id: &KeyId) -> PathBuf {
-        let mut path = self.key_directory(secrecy);
-        path.push(&PathBuf::from(id.hex().as_ref()));
-        path
+        key_path(&self.client_id, secrecy, id)
+    }
+
+    /// The location and path of the key file, which may still have its legacy name.
+    fn key_file(&self, secrecy: key::Secrecy, id: &KeyId) -> Option<(Location, PathBuf)> {
+        key_file(self.store, &self.client_id, secrecy, id)
+    }
+
+    /// The grant of another client's key to this one, unless revoked or the key was deleted.
+    pub fn grant(&self, id: &KeyId) -> Option<Grant> {
+        Grant::load(self.store, &self.client_id, id)
+            .filter(|grant| key_file(self.store, &grant.owner, key::Secrecy::Secret, id).is_some())
+    }
+
+    /// Write a key file, encrypted under the current KEK if there is one.
+    fn write_key(&mut self, location: Location, path: &Path, key: &key::Key) -> Result<()> {
+        write_key_file(self.store, &mut self.drbg, self.keks.as_ref(), location, path, key)
+    }
+
+    /// Read a key file, decrypting it if there are KEKs.
+    fn read_key(&self, location: Location, path: &Path) -> Result<key::Key> {
+        self.read_key_file(self.keks.as_ref(), location, path).map(|(key, _)| key)
+    }
+
+    /// Read a key file, and whether it is in an older format that needs to be upgraded.
+    fn read_key_file(&self, keks: Option<&Keks>, location: Location, path: &Path) -> Result<(key::Key, bool)> {
+        let bytes: StoredKeyBytes = store::read(self.store, location, path)?;
+        let serialized = match (keks, sealed_version(&bytes)) {
+            (Some(keks), Some(version)) => {
+                // a file of a version that is neither current nor being rotated from is stale
+                let kek = keks.get(version).ok_or(Error::TamperedKey)?;
+                open(kek, path.as_ref().as_bytes(), &bytes)?
+            }
+            (None, None) => key::SerializedKeyBytes::try_from_slice(&bytes)
+                .map_err(|_| Error::InvalidSerializedKey)?,
+            // a plaintext file was planted where keys are encrypted, or the KEKs are gone
+            _ => return Err(Error::TamperedKey),
+        };
+        let outdated = key::Key::format_version(&serialized)? < SERIALIZATION_VERSION;
+        Ok((key::Key::try_deserialize(&serialized)?, outdated))
+    }
+
+    /// Load a key without upgrading or renaming its file, falling back to keys granted to the client.
+    fn load(&self, secrecy: key::Secrecy, kind: Option<key::Kind>, id: &KeyId) -> Result<(key::Key, Location, bool)> {
+        let (key, location, outdated) = match self.key_file(secrecy, id) {
+            Some((location, path)) => {
+                let (key, outdated) = self.read_key_file(self.keks.as_ref(), location, &path)?;
+                (key, location, outdated || path != self.key_path(secrecy, id))
+            }
+            None => {
+                let grant = match secrecy {
+                    key::Secrecy::Secret => self.grant(id),
+                    key::Secrecy::Public => None,
+                }.ok_or(Error::NoSuchKey)?;
+                let (location, path) = key_file(self.store, &grant.owner, secrecy, id).ok_or(Error::NoSuchKey)?;
+                // the owner upgrades the file
+                let (key, _) = self.read_key_file(self.grantor_keks.as_ref(), location, &path)?;
+                (key, location, false)
+            }
+        };
+
+        if let Some(kind) = kind {
+            if key.kind != kind {
+                return Err(Error::WrongKeyKind);
+            }
+        }
+        Ok((key, location, outdated))
+    }
+
+    /// Count a use of the key to sign, decrypt or agree, failing if its uses are exhausted
+    /// or it is past its not-after time, given in seconds since the Unix epoch.
+    ///
+    /// The count is persisted before the key is used, so power loss cannot yield extra uses.
+    pub fn use_key(&mut self, secrecy: key::Secrecy, id: &KeyId, now: Option<u64>) -> Result<()> {
+        let mut key = self.load_key(secrecy, None, id)?;
+        if let Some(not_after) = key.policy.not_after {
+            // without a clock, expiring keys are unusable
+            if now.map_or(true, |now| now > not_after) {
+                return Err(Error::KeyExpired);
+            }
+        }
+
+        let uses_remaining = match key.policy.uses_remaining {
+            Some(0) => return Err(Error::KeyExpired),
+            Some(uses_remaining) => uses_remaining - 1,
+            None => return Ok(()),
+        };
+        key.policy.uses_remaining = Some(uses_remaining);
+
+        // uses of granted keys are counted in the owner's key file
+        self.rewrite_key(secrecy, id, &key)
+    }
+
+    /// Write the key to its file, which is the owner's for keys granted to the client,
+    /// encrypted under the owner's KEKs.
+    fn rewrite_key(&mut self, secrecy: key::Secrecy, id: &KeyId, key: &key::Key) -> Result<()> {
+        let (owner, keks) = match self.location(secrecy, id) {
+            Some(_) => (self.client_id.clone(), self.keks.as_ref()),
+            None => {
+                let grant = self.grant(id).ok_or(Error::NoSuchKey)?;
+                (grant.owner, self.grantor_keks.as_ref())
+            }
+        };
+        let (location, _) = key_file(self.store, &owner, secrecy, id).ok_or(Error::NoSuchKey)?;
+        write_key_file(self.store, &mut self.drbg, keks, location, &key_path(&owner, secrecy, id), key)?;
+        remove_legacy_key_file(self.store, location, &owner, secrecy, id);
+        Ok(())
+    }
+
+    /// Seal the key files written before the platform had a hardware-unique key,
+    /// returning their number.
+    ///
+    /// Only done before the client's `KekState` is first saved, as plaintext key files
+    /// are rejected afterwards.
+    pub fn seal_plaintext_keys(&mut self) -> Result<usize> {
+        if self.keks.is_none() {
+            return Err(Error::RequestNotAvailable);
+        }
+
+        let mut count = 0;
+        let mut after = None;
+        while let Some(file) = self.next_key_file(after.as_ref())? {
+            let bytes: StoredKeyBytes = store::read(self.store, file.location, &file.path)?;
+            if sealed_version(&bytes).is_none() {
+                let (key, _) = self.read_key_file(None, file.location, &file.path)?;
+                self.write_key(file.location, &file.path, &key)?;
+                count += 1;
+            }
+            after = Some(file);
+        }
+        Ok(count)
+    }
+
+    /// The key file after `after`, whatever its name, ordered by location, secrecy and name,
+    /// so the files can be rewritten while walking them.
+    fn next_key_file(&self, after: Option<&KeyFile>) -> Result<Option<KeyFile>> {
+        let locations = [
+            Location::Internal,
+            Location::External,
+            Location::Volatile,
+        ];
+        let secrecies = [
+            key::Secrecy::Secret,
+            key::Secrecy::Public,
+        ];
+        let position = |location, secrecy| (
+            locations.iter().position(|l| *l == location),
+            secrecies.iter().position(|s| *s == secrecy),
+        );
+
+        for location in locations.iter() {
+            for secrecy in secrecies.iter() {
+                let after_name = match after {
+                    Some(after) => {
+                        let last = position(after.location, after.secrecy);
+                        let current = position(*location, *secrecy);
+                        if current < last {
+                            continue;
+                        }
+                        if current == last { Some(&after.name) } else { None }
+                    }
+                    None => None,
+                };
+
+                // the smallest name after the last one, without assuming an order of the entries
+                let dir = self.key_directory(*secrecy);
+                let mut next: Option<PathBuf> = None;
+                store::for_each_file(self.store, *location, &dir, |entry| {
+                    let name = entry.file_name().as_ref().as_bytes();
+                    if after_name.map_or(false, |after_name| name <= after_name.as_ref().as_bytes())
+                        || next.as_ref().map_or(false, |next| next.as_ref().as_bytes() <= name)
+                    {
+                        return;
+                    }
+                    next = Some(PathBuf::from(entry.file_name()));
+                })?;
+
+                if let Some(name) = next {
+                    let mut path = dir;
+                    path.push(&name);
+                    return Ok(Some(KeyFile { location: *location, secrecy: *secrecy, name, path }));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Rewrap all keys not encrypted under the current KEK, returning their number.
+    ///
+    /// Walks all key files, whatever their name, and fails if any cannot be rewrapped,
+    /// so the rotation is only finished once no file needs the previous KEK.
+    /// Each key file is replaced atomically, so this can be repeated after power loss.
+    pub fn rewrap_keys(&mut self) -> Result<usize> {
+        let version = match &self.keks {
+            Some(keks) => keks.current.0,
+            None => return Err(Error::RequestNotAvailable),
+        };
+
+        let mut count = 0;
+        let mut after = None;
+        while let Some(file) = self.next_key_file(after.as_ref())? {
+            let bytes: StoredKeyBytes = store::read(self.store, file.location, &file.path)?;
+            if sealed_version(&bytes) != Some(version) {
+                let key = self.read_key(file.location, &file.path)?;
+                self.write_key(file.location, &file.path, &key)?;
+                count += 1;
+            }
+            after = Some(file);
+        }
+        Ok(count)
     }
 
 }
@@ -88,28 +722,68 @@ impl<P: Platform> Keystore for ClientKeystore<P> {
         // info_now!("storing {:?} -> {:?}", &key_kind, location);
 
         let mut info: key::Info = info.into();
-        if secrecy == key::Secrecy::Secret {
+        // the provenance flags are only ever set here
+        info.flags.remove(key::Flags::ALWAYS_SENSITIVE | key::Flags::NEVER_EXTRACTABLE);
+        if secrecy == key::Secrecy::Public {
+            info.flags.remove(key::Flags::SENSITIVE);
+        } else {
             info.flags |= key::Flags::SENSITIVE;
+            if info.flags.contains(key::Flags::LOCAL) {
+                info.flags |= key::Flags::ALWAYS_SENSITIVE;
+                if !info.flags.contains(key::Flags::EXTRACTABLE) {
+                    info.flags |= key::Flags::NEVER_EXTRACTABLE;
+                }
+            }
+        }
+        // copies keep the creation time of the original
+        if info.created.is_none() {
+            info.created = self.now;
         }
         let key = key::Key {
             flags: info.flags,
             kind: info.kind,
+            policy: info.policy,
+            label: info.label,
+            created: info.created,
             material: key::Material::try_from_slice(material).unwrap(),
         };
 
-        let id = self.generate_key_id();
+        let slot = self.slot.take();
+        let id = match slot {
+            Some((id, replace)) => {
+                let occupied = [key::Secrecy::Secret, key::Secrecy::Public].iter()
+                    .any(|&secrecy| self.location(secrecy, &id).is_some());
+                if occupied && !replace {
+                    return Err(Error::SlotOccupied);
+                }
+                id
+            }
+            None => self.generate_key_id(),
+        };
         let path = self.key_path(secrecy, &id);
-        store::store(self.store, location, &path, &key.serialize())?;
+        self.write_key(location, &path, &key)?;
+
+        if slot.is_some() {
+            // remove the replaced key only now, in case writing failed
+            for &other_secrecy in [key::Secrecy::Secret, key::Secrecy::Public].iter() {
+                for &other_location in [Location::Internal, Location::External, Location::Volatile].iter() {
+                    if (other_secrecy, other_location) != (secrecy, location) {
+                        store::delete(self.store, other_location, &self.key_path(other_secrecy, &id));
+                    }
+                    remove_legacy_key_file(self.store, other_location, &self.client_id, other_secrecy, &id);
+                }
+            }
+        }
 
         Ok(id)
     }
 
     fn exists_key(&self, secrecy: key::Secrecy, kind: Option<key::Kind>, id: &KeyId) -> bool {
-        self.load_key(secrecy, kind, id).is_ok()
+        self.load(secrecy, kind, id).is_ok()
     }
 
     fn key_info(&self, secrecy: key::Secrecy, id: &KeyId) -> Option<key::Info> {
-        self.load_key(secrecy, None, id).map(|key| key::Info { flags: key.flags, kind: key.kind }).ok()
+        self.load(secrecy, None, id).map(|(key, _, _)| key.info()).ok()
     }
 
     // TODO: is this an Oracle?
@@ -126,10 +800,11 @@ impl<P: Platform> Keystore for ClientKeystore<P> {
         ];
 
         secrecies.iter().any(|secrecy| {
-            let path = self.key_path(*secrecy, &id);
-            locations.iter().any(|location| {
+            let paths = core::iter::once(self.key_path(*secrecy, &id))
+                .chain(legacy_key_path(&self.client_id, *secrecy, &id));
+            paths.map(|path| locations.iter().any(|location| {
                 store::delete(self.store, *location, &path)
-            })
+            })).fold(false, |deleted, deleted_path| deleted || deleted_path)
         })
     }
 
@@ -146,58 +821,245 @@ impl<P: Platform> Keystore for ClientKeystore<P> {
         })
     }
 
-    fn load_key(&self, secrecy: key::Secrecy, kind: Option<key::Kind>, id: &KeyId) -> Result<key::Key> {
+    fn load_key(&mut self, secrecy: key::Secrecy, kind: Option<key::Kind>, id: &KeyId) -> Result<key::Key> {
         // info_now!("loading  {:?}", &key_kind);
-        let path = self.key_path(secrecy, id);
-
-        let location = self.location(secrecy, id).ok_or(Error::NoSuchKey)?;
-
-        let bytes: Bytes<consts::U128> = store::read(self.store, location, &path)?;
-
-        let key = key::Key::try_deserialize(&bytes)?;
-
-        if let Some(kind) = kind {
-            if key.kind != kind {
-                return Err(Error::WrongKeyKind);
+        if self.location(secrecy, id).is_none() {
+            // granted keys are used only as permitted, and never exported
+            let grant = self.grant(id).ok_or(Error::NoSuchKey)?;
+            let permitted = self.granted.map_or(false, |(granted, usage)| {
+                &granted == id && !usage.is_empty() && grant.usage.contains(usage)
+            });
+            if !permitted {
+                return Err(Error::KeyFunctionNotPermitted);
+            }
+        }
+        let (key, location, outdated) = self.load(secrecy, kind, id)?;
+        if outdated {
+            // the key stays usable if this fails, and is upgraded or renamed on a later load
+            let path = self.key_path(secrecy, id);
+            if self.write_key(location, &path, &key).is_ok() {
+                remove_legacy_key_file(self.store, location, &self.client_id, secrecy, id);
             }
         }
         Ok(key)
     }
 
-    fn overwrite_key(&self, location: Location, secrecy: key::Secrecy, kind: key::Kind, id: &KeyId, material: &[u8]) -> Result<()> {
-        let mut flags = key::Flags::default();
-        if secrecy == key::Secrecy::Secret {
-            flags |= key::Flags::SENSITIVE;
+    fn copy_key(&mut self, secrecy: key::Secrecy, id: &KeyId, location: Location) -> Result<KeyId> {
+        let key = self.load_key(secrecy, None, id)?;
+        self.store_key(location, secrecy, key.info(), &key.material)
+    }
+
+    fn move_key(&mut self, secrecy: key::Secrecy, id: &KeyId, location: Location) -> Result<()> {
+        let from = self.location(secrecy, id).ok_or(Error::NoSuchKey)?;
+        if from == location {
+            return Ok(());
         }
+        let key = self.load_key(secrecy, None, id)?;
+        // loading may have renamed the file
+        let (from, from_path) = self.key_file(secrecy, id).ok_or(Error::NoSuchKey)?;
+        // write first, after power loss the old file is an identical copy
+        self.overwrite_key(location, secrecy, key.info(), id, &key.material)?;
+        store::delete(self.store, from, &from_path);
+        Ok(())
+    }
+
+    fn update_key(&mut self, secrecy: key::Secrecy, id: &KeyId, material: &[u8]) -> Result<()> {
+        let (mut key, _, _) = self.load(secrecy, None, id)?;
+        key.material = key::Material::try_from_slice(material).map_err(|_| Error::InvalidSerializedKey)?;
+        self.rewrite_key(secrecy, id, &key)
+    }
+
+    fn overwrite_key(&mut self, location: Location, secrecy: key::Secrecy, info: impl Into<key::Info>, id: &KeyId, material: &[u8]) -> Result<()> {
+        let info: key::Info = info.into();
         let key = key::Key {
-            flags: Default::default(),
-            kind,
+            flags: info.flags,
+            kind: info.kind,
+            policy: info.policy,
+            label: info.label,
+            created: info.created,
             material: key::Material::try_from_slice(material).unwrap(),
         };
 
         let path = self.key_path(secrecy, id);
-        store::store(self.store, location, &path, &key.serialize())?;
+        self.write_key(location, &path, &key)?;
+        remove_legacy_key_file(self.store, location, &self.client_id, secrecy, id);
 
         Ok(())
     }
 
 
     fn location(&self, secrecy: key::Secrecy, id: &KeyId) -> Option<Location> {
-        let path = self.key_path(secrecy, id);
+        self.key_file(secrecy, id).map(|(location, _)| location)
+    }
 
-        if path.exists(&self.store.vfs()) {
-            return Some(Location::Volatile);
-        }
+    fn find_keys(&self, template: &ObjectTemplate, offset: usize) -> Result<(Vec<KeyId, MAX_OBJECT_HANDLES>, usize)> {
+        let secrecies = [
+            key::Secrecy::Secret,
+            key::Secrecy::Public,
+        ];
 
-        if path.exists(&self.store.ifs()) {
-            return Some(Location::Internal);
+        let locations = [
+            Location::Internal,
+            Location::External,
+            Location::Volatile,
+        ];
+
+        let mut found = Vec::new();
+        let mut count = 0;
+        for location in locations.iter().filter(|location| template.location.map_or(true, |l| l == **location)) {
+            for secrecy in secrecies.iter() {
+                let dir = self.key_directory(*secrecy);
+                store::for_each_file(self.store, *location, &dir, |entry| {
+                    let id = match key_id(entry.file_name().as_ref().as_bytes()) {
+                        Some(id) => id,
+                        None => return,
+                    };
+                    let mut path = dir.clone();
+                    path.push(entry.file_name());
+                    let info = self.read_key(*location, &path).ok()
+                        .map(|key| key.info());
+                    if let Some(info) = info {
+                        if template.matches_key(&info, *secrecy, *location) {
+                            if count >= offset {
+                                // matches beyond the capacity are only counted
+                                found.push(id).ok();
+                            }
+                            count += 1;
+                        }
+                    }
+                })?;
+            }
         }
 
-        if path.exists(&self.store.efs()) {
-            return Some(Location::External);
+        Ok((found, count))
+    }
+
+    fn next_key(&self, kind: Option<key::Kind>, secrecy: Option<key::Secrecy>, after: Option<&KeyEntry>) -> Result<Option<KeyEntry>> {
+        let secrecies = [
+            key::Secrecy::Secret,
+            key::Secrecy::Public,
+        ];
+
+        let locations = [
+            Location::Internal,
+            Location::External,
+            Location::Volatile,
+        ];
+
+        for location in locations.iter() {
+            for current_secrecy in secrecies.iter() {
+                if secrecy.map_or(false, |secrecy| secrecy != *current_secrecy) {
+                    continue;
+                }
+                let position = |location, secrecy| (
+                    locations.iter().position(|l| *l == location),
+                    secrecies.iter().position(|s| *s == secrecy),
+                );
+                let after_id = match after {
+                    Some(after) => {
+                        let last = position(after.location, after.secrecy);
+                        let current = position(*location, *current_secrecy);
+                        if current < last {
+                            continue;
+                        }
+                        if current == last { Some(after.key.object_id) } else { None }
+                    }
+                    None => None,
+                };
+
+                // the smallest ID after the last one, without assuming an order of the entries
+                let dir = self.key_directory(*current_secrecy);
+                let mut next: Option<(KeyId, key::Info)> = None;
+                store::for_each_file(self.store, *location, &dir, |entry| {
+                    let id = match key_id(entry.file_name().as_ref().as_bytes()) {
+                        Some(id) => id,
+                        None => return,
+                    };
+                    if after_id.map_or(false, |after_id| id.0 <= after_id.0)
+                        || next.as_ref().map_or(false, |(next_id, _)| next_id.0 <= id.0)
+                    {
+                        return;
+                    }
+                    let mut path = dir.clone();
+                    path.push(entry.file_name());
+                    let info = self.read_key(*location, &path).ok()
+                        .map(|key| key.info());
+                    if let Some(info) = info {
+                        if kind.map_or(true, |kind| kind == info.kind) {
+                            next = Some((id, info));
+                        }
+                    }
+                })?;
+
+                if let Some((id, info)) = next {
+                    return Ok(Some(KeyEntry {
+                        key: ObjectHandle::new(id),
+                        secrecy: *current_secrecy,
+                        location: *location,
+                        info,
+                    }));
+                }
+            }
         }
 
-        None
+        Ok(None)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seal_open() {
+        let kek = [7u8; 32];
+        let path = b"test/sec/00112233445566778899aabbccddeeff";
+        let serialized = [1u8, 2, 3, 4, 5];
+        let sealed = seal(&kek, 3, &[9u8; NONCE_LENGTH], path, &serialized);
+        assert_eq!(sealed.len(), HEADER_LENGTH + NONCE_LENGTH + serialized.len() + TAG_LENGTH);
+        assert_eq!(sealed_version(&sealed), Some(3));
+        assert_eq!(&open(&kek, path, &sealed).unwrap()[..], &serialized[..]);
+
+        // another KEK, another file, or modified contents
+        assert_eq!(open(&[8u8; 32], path, &sealed).err(), Some(Error::TamperedKey));
+        assert_eq!(open(&kek, b"test/sec/ffeeddccbbaa99887766554433221100", &sealed).err(), Some(Error::TamperedKey));
+        let mut modified = sealed.clone();
+        modified[HEADER_LENGTH + NONCE_LENGTH] ^= 1;
+        assert_eq!(open(&kek, path, &modified).err(), Some(Error::TamperedKey));
+        assert_eq!(open(&kek, path, &sealed[..TAG_LENGTH]).err(), Some(Error::InvalidSerializedKey));
     }
 
+    #[test]
+    fn plaintext_keys_are_not_sealed() {
+        let key = key::Key {
+            flags: key::Flags::LOCAL | key::Flags::SENSITIVE,
+            kind: key::Kind::Ed255,
+            policy: Default::default(),
+            label: None,
+            created: None,
+            material: key::Material::try_from_slice(&[1u8; 32]).unwrap(),
+        };
+        assert_eq!(sealed_version(&key.serialize()), None);
+    }
+
+    #[test]
+    fn kek_versions_are_not_reused() {
+        let mut state = KekState { current: 254, target: 254, ..Default::default() };
+        state.start_rotation(KekSecret::try_from_slice(&[1; 32]).unwrap()).unwrap();
+        assert!(state.rotating());
+        state.finish_rotation();
+        assert_eq!((state.current, state.target), (255, 255));
+        assert_eq!(&state.current_secret[..], &[1; 32][..]);
+        assert_eq!(state.start_rotation(KekSecret::new()), Err(Error::KekVersionsExhausted));
+    }
+
+    #[test]
+    fn kek_secrets() {
+        let mut huk = platform::SoftwareHuk::new([37; 32]);
+        let client_id = PathBuf::from("test");
+        let kek = derive_kek(&mut huk, &client_id, 1, &KekSecret::new());
+        let other = derive_kek(&mut huk, &client_id, 1, &KekSecret::try_from_slice(b"new PIN").unwrap());
+        assert_ne!(kek, other);
+    }
 }
@@ -0,0 +1,120 @@
+use littlefs2::path::PathBuf;
+
+use crate::{
+    config::{MAX_OBJECT_HANDLES, MAX_SERIALIZED_OBJECT_LENGTH},
+    error::{Error, Result},
+    store::{self, Store},
+    types::{Attributes, ClientId, Location, ObjectTemplate, UniqueId, Vec},
+};
+
+pub type ObjectId = UniqueId;
+
+/// Storage of the objects created via `CreateObject` that are not keys,
+/// i.e. data objects and certificates.
+pub struct ClientObjectstore<S>
+where
+    S: Store,
+{
+    client_id: ClientId,
+    store: S,
+}
+
+pub trait Objectstore {
+    fn store_object(&mut self, location: Location, id: &ObjectId, attributes: &Attributes) -> Result<()>;
+    fn load_object(&self, id: &ObjectId) -> Result<(Attributes, Location)>;
+    fn delete_object(&mut self, id: &ObjectId) -> bool;
+    /// Return the objects matching the template after skipping `offset` matches,
+    /// and the total number of matches.
+    fn find_objects(&self, template: &ObjectTemplate, offset: usize) -> Result<(Vec<ObjectId, MAX_OBJECT_HANDLES>, usize)>;
+}
+
+impl<S: Store> Objectstore for ClientObjectstore<S> {
+
+    fn store_object(&mut self, location: Location, id: &ObjectId, attributes: &Attributes) -> Result<()> {
+        let serialized: crate::Bytes<MAX_SERIALIZED_OBJECT_LENGTH> = crate::postcard_serialize_bytes(attributes)
+            .map_err(|_| Error::InternalError)?;
+        store::store(self.store, location, &self.object_path(id), &serialized)
+    }
+
+    fn load_object(&self, id: &ObjectId) -> Result<(Attributes, Location)> {
+        let path = self.object_path(id);
+        let locations = [
+            Location::Internal,
+            Location::External,
+            Location::Volatile,
+        ];
+        let (serialized, location) = locations.iter().find_map(|&location| {
+            store::read::<MAX_SERIALIZED_OBJECT_LENGTH>(self.store, location, &path).ok()
+                .map(|serialized| (serialized, location))
+        }).ok_or(Error::ObjectHandleInvalid)?;
+        let attributes = crate::postcard_deserialize(&serialized).map_err(|_| Error::InternalError)?;
+        Ok((attributes, location))
+    }
+
+    fn delete_object(&mut self, id: &ObjectId) -> bool {
+        let path = self.object_path(id);
+        let locations = [
+            Location::Internal,
+            Location::External,
+            Location::Volatile,
+        ];
+        locations.iter().any(|&location| {
+            store::delete(self.store, location, &path)
+        })
+    }
+
+    fn find_objects(&self, template: &ObjectTemplate, offset: usize) -> Result<(Vec<ObjectId, MAX_OBJECT_HANDLES>, usize)> {
+        let locations = [
+            Location::Internal,
+            Location::External,
+            Location::Volatile,
+        ];
+
+        let dir = self.object_directory();
+        let mut found = Vec::new();
+        let mut count = 0;
+        for &location in locations.iter() {
+            store::for_each_file(self.store, location, &dir, |entry| {
+                let file_name = entry.file_name().as_ref().as_bytes();
+                // object files are named by the hex of their ID
+                if file_name.len() != 32 || !file_name.iter().all(u8::is_ascii_hexdigit) {
+                    return;
+                }
+                let mut path = dir.clone();
+                path.push(entry.file_name());
+                let attributes: Option<Attributes> = store::read::<MAX_SERIALIZED_OBJECT_LENGTH>(self.store, location, &path).ok()
+                    .and_then(|serialized| crate::postcard_deserialize(&serialized).ok());
+                if let Some(attributes) = attributes {
+                    if template.matches_object(&attributes, location) {
+                        if count >= offset {
+                            // matches beyond the capacity are only counted
+                            found.push(ObjectId::try_from_hex(file_name).unwrap()).ok();
+                        }
+                        count += 1;
+                    }
+                }
+            })?;
+        }
+
+        Ok((found, count))
+    }
+}
+
+impl<S: Store> ClientObjectstore<S> {
+    pub fn new(client_id: ClientId, store: S) -> Self {
+        Self { client_id, store }
+    }
+
+    fn object_directory(&self) -> PathBuf {
+        let mut path = PathBuf::new();
+        path.push(&self.client_id);
+        path.push(&PathBuf::from("obj"));
+        path
+    }
+
+    fn object_path(&self, id: &ObjectId) -> PathBuf {
+        let mut path = self.object_directory();
+        path.push(&PathBuf::from(id.hex().as_ref()));
+        path
+    }
+}
@@ -0,0 +1,158 @@
+use core::convert::TryInto;
+
+use littlefs2::path::PathBuf;
+
+use crate::{
+    Bytes,
+    consts,
+    error::{Error, Result},
+    store::{self, Store},
+    types::{ClientId, Location},
+};
+
+
+/// The PIN of a client, stored in `/<client>/pin` as its PBKDF2-HMAC-SHA256 under a random salt,
+/// MACed with a key derived from the hardware-unique key.
+pub struct ClientPinstore<S>
+where
+    S: Store,
+{
+    client_id: ClientId,
+    store: S,
+    key: [u8; 32],
+}
+
+pub type Salt = [u8; 16];
+
+// retries left | maximum retries | salt | hash
+const PIN_FILE_LENGTH: usize = 50;
+
+struct PinFile {
+    retries_left: u8,
+    max_retries: u8,
+    salt: Salt,
+    hash: [u8; 32],
+}
+
+// slows down guessing, while keeping a PIN check within a fraction of a second on a device
+const PIN_KDF_ITERATIONS: u32 = 1000;
+
+fn hmac(key: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+    use hmac::{Hmac, Mac, NewMac};
+    let mut mac = Hmac::<sha2::Sha256>::new_varkey(key).unwrap();
+    for part in parts {
+        mac.update(part);
+    }
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&mac.finalize().into_bytes());
+    hash
+}
+
+/// PBKDF2-HMAC-SHA256 of the PIN, MACed with the key, so guessing it needs the device.
+fn hash(key: &[u8; 32], salt: &Salt, pin: &[u8]) -> [u8; 32] {
+    // the first and only block of PBKDF2
+    let mut u = hmac(pin, &[salt, &1u32.to_be_bytes()]);
+    let mut stretched = u;
+    for _ in 1..PIN_KDF_ITERATIONS {
+        u = hmac(pin, &[&u]);
+        stretched.iter_mut().zip(u.iter()).for_each(|(t, u)| *t ^= u);
+    }
+    hmac(key, &[&stretched])
+}
+
+impl<S: Store> ClientPinstore<S> {
+    pub fn new(client_id: ClientId, store: S, key: [u8; 32]) -> Self {
+        Self { client_id, store, key }
+    }
+
+    fn pin_path(&self) -> PathBuf {
+        let mut path = PathBuf::new();
+        path.push(&self.client_id);
+        path.push(&PathBuf::from("pin"));
+        path
+    }
+
+    fn read_pin(&self) -> Result<Option<PinFile>> {
+        let path = self.pin_path();
+        if !store::exists(self.store, Location::Internal, &path) {
+            return Ok(None);
+        }
+        let bytes: Bytes<consts::U50> = store::read(self.store, Location::Internal, &path)?;
+        if bytes.len() != PIN_FILE_LENGTH {
+            return Err(Error::InvalidSerializationFormat);
+        }
+        Ok(Some(PinFile {
+            retries_left: bytes[0],
+            max_retries: bytes[1],
+            salt: bytes[2..18].try_into().unwrap(),
+            hash: bytes[18..].try_into().unwrap(),
+        }))
+    }
+
+    fn write_pin(&mut self, file: &PinFile) -> Result<()> {
+        let mut bytes = [0u8; PIN_FILE_LENGTH];
+        bytes[0] = file.retries_left;
+        bytes[1] = file.max_retries;
+        bytes[2..18].copy_from_slice(&file.salt);
+        bytes[18..].copy_from_slice(&file.hash);
+        store::store(self.store, Location::Internal, &self.pin_path(), &bytes)
+    }
+
+    fn store_pin(&mut self, pin: &[u8], max_retries: u8, salt: Salt) -> Result<()> {
+        if max_retries == 0 {
+            return Err(Error::ArgumentsBad);
+        }
+        if pin.is_empty() {
+            return Err(Error::PinLenRange);
+        }
+        let hash = hash(&self.key, &salt, pin);
+        self.write_pin(&PinFile { retries_left: max_retries, max_retries, salt, hash })
+    }
+}
+
+pub trait Pinstore {
+    /// Set the initial PIN, allowing the given number of consecutive failed attempts.
+    fn set_pin(&mut self, pin: &[u8], max_retries: u8, salt: Salt) -> Result<()>;
+    /// Check the PIN, counting the attempt before, so cutting power cannot yield extra retries.
+    fn verify_pin(&mut self, pin: &[u8]) -> Result<()>;
+    fn change_pin(&mut self, old_pin: &[u8], new_pin: &[u8], salt: Salt) -> Result<()>;
+    /// The remaining attempts, if there is a PIN.
+    fn pin_retries(&self) -> Result<Option<u8>>;
+}
+
+impl<S: Store> Pinstore for ClientPinstore<S> {
+    fn set_pin(&mut self, pin: &[u8], max_retries: u8, salt: Salt) -> Result<()> {
+        if self.read_pin()?.is_some() {
+            return Err(Error::PinAlreadySet);
+        }
+        self.store_pin(pin, max_retries, salt)
+    }
+
+    fn verify_pin(&mut self, pin: &[u8]) -> Result<()> {
+        let mut file = self.read_pin()?.ok_or(Error::UserPinNotInitialized)?;
+        if file.retries_left == 0 {
+            return Err(Error::PinLocked);
+        }
+        file.retries_left -= 1;
+        self.write_pin(&file)?;
+
+        // compare in constant time
+        let hash = hash(&self.key, &file.salt, pin);
+        if hash.iter().zip(file.hash.iter()).fold(0, |acc, (a, b)| acc | (a ^ b)) != 0 {
+            return Err(if file.retries_left == 0 { Error::PinLocked } else { Error::PinIncorrect });
+        }
+
+        file.retries_left = file.max_retries;
+        self.write_pin(&file)
+    }
+
+    fn change_pin(&mut self, old_pin: &[u8], new_pin: &[u8], salt: Salt) -> Result<()> {
+        self.verify_pin(old_pin)?;
+        let max_retries = self.read_pin()?.ok_or(Error::UserPinNotInitialized)?.max_retries;
+        self.store_pin(new_pin, max_retries, salt)
+    }
+
+    fn pin_retries(&self) -> Result<Option<u8>> {
+        Ok(self.read_pin()?.map(|file| file.retries_left))
+    }
+}
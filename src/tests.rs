@@ -216,6 +216,27 @@ macro_rules! setup {
     }
 }
 
+// A mounted store, with a platform type for the keystore, without a service.
+macro_rules! setup_store {
+    ($store_var:ident, $store:ident, $platform:ident) => {
+        let memory = create_memory!();
+
+        store!($store,
+            Internal: InternalStorage,
+            External: ExternalStorage,
+            Volatile: VolatileStorage
+        );
+        platform!($platform,
+            R: MockRng,
+            S: $store,
+            UI: UserInterface,
+        );
+
+        let $store_var = $store::claim().unwrap();
+        $store_var.mount(memory.0, memory.1, memory.2, memory.3, memory.4, memory.5, true).unwrap();
+    }
+}
+
 #[test]
 #[serial]
 fn dummy() {
@@ -460,3 +481,153 @@ fn rng() {
 
 }
 
+
+#[test]
+fn rfc6979_nonces() {
+    use crate::mechanisms::p256::Rfc6979;
+    use hex_literal::hex;
+    use sha2::Digest as _;
+
+    // RFC 6979, A.2.5 (ECDSA, 256 Bits (Prime Field)), with SHA-256
+    let secret_scalar = hex!("C9AFA9D845BA75166B5C215767B1D6934E50C3DB36E89B127B8A622B120F6721");
+    let secret_key = p256_cortex_m4::SecretKey::from_bytes(&secret_scalar).unwrap();
+    // message, k, r and s
+    let vectors: [(&[u8], [u8; 32], [u8; 32], [u8; 32]); 2] = [
+        (
+            b"sample",
+            hex!("A6E3C57DD01ABE90086538398355DD4C3B17AA873382B0F24D6129493D8AAD60"),
+            hex!("EFD48B2AACB6A8FD1140DD9CD45E81D69D2C877B56AAF991C34D0EA84EAF3716"),
+            hex!("F7CB1C942D657C41D436C7A1B6E29F65F3E900DBB9AFF4064DC4AB2F843ACDA8"),
+        ),
+        (
+            b"test",
+            hex!("D16B6AE827F17175E040871A1C7EC3500192C4C92677336EC2537ACAABEE1D8B"),
+            hex!("F1ABB023518351CD71D881567B1EA663ED3EFCF6C5132B354F28D3B0B7D38367"),
+            hex!("019F4113742A2B14BD25926B49C649155F267E60D3814B4C0CC84250E46F0083"),
+        ),
+    ];
+
+    for (message, expected_nonce, r, s) in vectors.iter() {
+        let mut digest = [0u8; 32];
+        digest.copy_from_slice(&sha2::Sha256::digest(message));
+
+        let mut nonce = [0u8; 32];
+        Rfc6979::new(&secret_scalar, &digest, None).fill_bytes(&mut nonce);
+        assert_eq!(&nonce, expected_nonce);
+
+        // the signature made with these nonces
+        let signature = secret_key.sign_prehashed(&digest, &mut Rfc6979::new(&secret_scalar, &digest, None));
+        let signature = signature.to_untagged_bytes();
+        assert_eq!(&signature[..32], &r[..]);
+        assert_eq!(&signature[32..], &s[..]);
+
+        // hedging changes the nonce
+        Rfc6979::new(&secret_scalar, &digest, Some(&[0u8; 32])).fill_bytes(&mut nonce);
+        assert_ne!(&nonce, expected_nonce);
+    }
+}
+
+#[test]
+#[serial]
+fn legacy_key_names() {
+    use crate::store::keystore::{ClientKeystore, Keystore as _};
+    use rand_core::SeedableRng as _;
+
+    setup_store!(store, Store, Platform);
+    let mut keystore = ClientKeystore::<Platform>::new("test".into(), chacha20::ChaCha8Rng::from_seed([0u8; 32]), store);
+
+    // key files used to be named by the hex of their ID without its zero bytes
+    let slot = UniqueId::from(0x9a);
+    let generated = UniqueId(hex_literal::hex!("00112233445566778899aabbccddee00"));
+    for (id, legacy_name) in [(slot, "9a"), (generated, "112233445566778899aabbccddee00")].iter() {
+        keystore.overwrite_key(Location::Internal, key::Secrecy::Secret, key::Kind::Ed255, id, &[0x42; 32]).unwrap();
+        let path = keystore.key_path(key::Secrecy::Secret, id);
+        let bytes: Bytes<consts::U128> = store::read(store, Location::Internal, &path).unwrap();
+        assert!(store::delete(store, Location::Internal, &path));
+        let mut legacy_path = keystore.key_directory(key::Secrecy::Secret);
+        legacy_path.push(&PathBuf::from(*legacy_name));
+        store::store(store, Location::Internal, &legacy_path, &bytes).unwrap();
+    }
+
+    // legacy names of special IDs are listed, other ones once the key was loaded
+    let (found, count) = keystore.find_keys(&ObjectTemplate::new(), 0).unwrap();
+    assert_eq!((&found[..], count), (&[slot][..], 1));
+
+    for id in [slot, generated].iter() {
+        assert_eq!(keystore.location(key::Secrecy::Secret, id), Some(Location::Internal));
+        let key = keystore.load_key(key::Secrecy::Secret, Some(key::Kind::Ed255), id).unwrap();
+        assert_eq!(&key.material[..], &[0x42; 32][..]);
+        // the file was renamed
+        assert!(store::exists(store, Location::Internal, &keystore.key_path(key::Secrecy::Secret, id)));
+    }
+    let mut directory_entries = 0;
+    store::for_each_file(store, Location::Internal, &keystore.key_directory(key::Secrecy::Secret), |_| directory_entries += 1).unwrap();
+    assert_eq!(directory_entries, 2);
+
+    let (found, count) = keystore.find_keys(&ObjectTemplate::new(), 0).unwrap();
+    assert_eq!(count, 2);
+    assert!(found.contains(&slot) && found.contains(&generated));
+}
+
+#[test]
+#[serial]
+fn seal_plaintext_keys() {
+    use crate::store::keystore::{ClientKeystore, Keks, Keystore as _};
+    use rand_core::SeedableRng as _;
+
+    setup_store!(store, Store, Platform);
+    let drbg = chacha20::ChaCha8Rng::from_seed([0u8; 32]);
+    let mut plaintext = ClientKeystore::<Platform>::new("test".into(), drbg.clone(), store);
+    let id = plaintext.store_key(Location::Internal, key::Secrecy::Secret, key::Kind::Ed255, &[0x42; 32]).unwrap();
+
+    // written before the platform had a hardware-unique key
+    let keks = Keks { current: (0, [0x17; 32]), previous: None };
+    let mut sealed = ClientKeystore::<Platform>::new("test".into(), drbg, store).with_keks(Some(keks));
+    assert_eq!(sealed.load_key(key::Secrecy::Secret, None, &id).err(), Some(Error::TamperedKey));
+
+    assert_eq!(sealed.seal_plaintext_keys(), Ok(1));
+    let key = sealed.load_key(key::Secrecy::Secret, None, &id).unwrap();
+    assert_eq!(&key.material[..], &[0x42; 32][..]);
+    assert_eq!(sealed.seal_plaintext_keys(), Ok(0));
+    assert_eq!(plaintext.load_key(key::Secrecy::Secret, None, &id).err(), Some(Error::TamperedKey));
+}
+
+#[test]
+#[serial]
+fn upgrade_key_files() {
+    use crate::store::keystore::{self, ClientKeystore, Keks, Keystore as _};
+    use rand_core::SeedableRng as _;
+
+    setup_store!(store, Store, Platform);
+    let drbg = chacha20::ChaCha8Rng::from_seed([0u8; 32]);
+    let fixtures: [(&[u8], &[u8]); 1] = [
+        (include_bytes!("../tests/fixtures/keys/v0-ed255.bin"), include_bytes!("../tests/fixtures/keys/v2-ed255.bin")),
+    ];
+
+    let keks = Keks { current: (0, [0x17; 32]), previous: None };
+    for (client_id, keks) in [("plaintext", None), ("sealed", Some(keks))].iter() {
+        let mut keystore = ClientKeystore::<Platform>::new(PathBuf::from(*client_id), drbg.clone(), store)
+            .with_keks(keks.clone());
+        for (i, (old, current)) in fixtures.iter().enumerate() {
+            // a key file written by earlier firmware
+            let id = UniqueId::from(i as u8 + 1);
+            let path = keystore.key_path(key::Secrecy::Secret, &id);
+            let planted = match keks {
+                Some(keks) => keystore::seal(&keks.current.1, 0, &[7; 12], path.as_ref().as_bytes(), old),
+                None => Bytes::try_from_slice(old).unwrap(),
+            };
+            store::store(store, Location::Internal, &path, &planted).unwrap();
+
+            let key = keystore.load_key(key::Secrecy::Secret, None, &id).unwrap();
+            assert_eq!(key, key::Key::try_deserialize(old).unwrap());
+
+            // rewritten in the current format
+            let stored: Bytes<consts::U512> = store::read(store, Location::Internal, &path).unwrap();
+            let serialized = match keks {
+                Some(keks) => keystore::open(&keks.current.1, path.as_ref().as_bytes(), &stored).unwrap(),
+                None => Bytes::try_from_slice(&stored).unwrap(),
+            };
+            assert_eq!(&serialized[..], *current);
+        }
+    }
+}
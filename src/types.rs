@@ -38,6 +38,19 @@ impl From<SpecialId> for Id {
     }
 }
 
+/// Certificates are objects, whose IDs are those of their certificate requests.
+impl From<UniqueId> for Id {
+    fn from(unique_id: UniqueId) -> Self {
+        Self(u128::from_be_bytes(unique_id.0))
+    }
+}
+
+impl From<Id> for UniqueId {
+    fn from(id: Id) -> Self {
+        Self(id.0.to_be_bytes())
+    }
+}
+
 pub mod ui {
     use super::*;
 
@@ -128,13 +141,33 @@ pub type ClientId = PathBuf;
 #[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 #[allow(clippy::large_enum_variant)]
 pub enum Attributes {
-    Certificate,
+    Certificate(CertificateAttributes),
     Counter,
     Data(DataAttributes),
     Key(KeyAttributes),
 }
 
-#[derive(Clone, Eq, PartialEq, Debug)]
+impl Attributes {
+    pub fn object_type(&self) -> ObjectType {
+        match self {
+            Attributes::Certificate(attributes) => ObjectType::Certificate(attributes.certificate_type),
+            Attributes::Counter => ObjectType::Counter,
+            Attributes::Data(_) => ObjectType::Data,
+            Attributes::Key(_) => ObjectType::Key(Secrecy::Public),
+        }
+    }
+
+    pub fn label(&self) -> Option<&crate::key::Label> {
+        match self {
+            Attributes::Certificate(attributes) => attributes.label.as_ref(),
+            Attributes::Counter => None,
+            Attributes::Data(attributes) => attributes.label.as_ref(),
+            Attributes::Key(attributes) => attributes.label.as_ref(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub enum CertificateType {
     // "identity", issued by certificate authority
     // --> authentication
@@ -150,10 +183,12 @@ pub enum CertificateType {
 //     Other,
 // }
 
-// #[derive(Clone, Default, Eq, PartialEq, Debug)]
-// pub struct CertificateAttributes {
-//     pub certificate_type CertificateType,
-// }
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct CertificateAttributes {
+    pub certificate_type: CertificateType,
+    pub label: Option<crate::key::Label>,
+    pub der: Message,
+}
 
 
 #[derive(Clone, Default, Eq, PartialEq, Debug, Serialize, Deserialize)]
@@ -163,6 +198,7 @@ pub struct DataAttributes {
     // DER-encoding of *type* of data object
     // pub object_id: Bytes<?>,
     pub kind: ShortData,
+    pub label: Option<crate::key::Label>,
     pub value: LongData,
 }
 
@@ -173,45 +209,14 @@ pub struct DataAttributes {
 // How do we handle defaults?
 //
 // Lookup seems a bit painful, on the other hand a struct of options is wasteful.
-#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+/// A public key to create as object, private keys are imported via `ImportKey`.
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub struct KeyAttributes {
-    // secrecy: Secrecy,
-    // object_id: Bytes,
-    // derive: bool, // can other keys be derived
-    // local: bool, // generated on token, or copied from such
-    // key_gen_mechanism: Mechanism, // only for local, how was key generated
-    // allowed_mechanisms: Vec<Mechanism>,
-
-    // never return naked private key
-    sensitive: bool,
-    // always_sensitive: bool,
-
-    // do not even return wrapped private key
-    extractable: bool,
-    // never_extractable: bool,
-
-    // do not save to disk
-    persistent: bool,
-}
-
-impl Default for KeyAttributes {
-    fn default() -> Self {
-        Self {
-            sensitive: true,
-            // always_sensitive: true,
-            extractable: false,
-            // never_extractable: true,
-            // cryptoki: token (vs session) object
-            // cryptoki: default false
-            persistent: false,
-        }
-    }
-}
-
-impl KeyAttributes {
-    pub fn new() -> Self {
-        Default::default()
-    }
+    /// the mechanism whose keys `public_key` is one of
+    pub mechanism: Mechanism,
+    pub format: KeySerialization,
+    pub label: Option<crate::key::Label>,
+    pub public_key: Message,
 }
 
 /// Non-exhaustive to make it unconstructable
@@ -233,14 +238,35 @@ impl TryFrom<ShortData> for Letters {
 
 /// Opaque key handle
 ///
-/// Ideally, this would be authenticated encryption
-/// around the information that allows locating the key.
+/// The object ID, with a tag by which the service authenticates the handle
+/// as issued to the client, for an object of the kind it had then.
 ///
 /// So e.g. users can't get at keys they don't own
 ///
+/// Handles used to be the object ID only, serialized as 16 bytes. Such handles
+/// deserialize without tag, see `legacy`, and the service only accepts them in
+/// `UpgradeHandle` requests, which return the tagged handle to persist instead.
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]//, Deserialize, Serialize)]
 pub struct ObjectHandle{
     pub object_id: UniqueId,
+    pub(crate) tag: [u8; 16],
+}
+
+impl ObjectHandle {
+    /// A handle without tag, which the service adds before handing it out.
+    pub(crate) fn new(object_id: UniqueId) -> Self {
+        Self { object_id, tag: [0u8; 16] }
+    }
+
+    /// A handle as persisted before handles were tagged, to upgrade via `UpgradeHandle`.
+    pub fn legacy(object_id: UniqueId) -> Self {
+        Self::new(object_id)
+    }
+
+    /// Whether the handle is without tag, as persisted before handles were tagged.
+    pub fn is_legacy(&self) -> bool {
+        self.tag == [0u8; 16]
+    }
 }
 
 // #[derive(Clone, Eq, PartialEq, Debug)]//, Deserialize, Serialize)]
@@ -279,7 +305,10 @@ impl serde::Serialize for ObjectHandle {
     where
         S: serde::Serializer,
     {
-        serializer.serialize_bytes(&self.object_id.0)
+        let mut bytes = [0u8; 32];
+        bytes[..16].copy_from_slice(&self.object_id.0);
+        bytes[16..].copy_from_slice(&self.tag);
+        serializer.serialize_bytes(&bytes)
     }
 }
 
@@ -295,7 +324,7 @@ impl<'de> serde::Deserialize<'de> for ObjectHandle {
             type Value = ObjectHandle;
 
             fn expecting(&self, formatter: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-                formatter.write_str("16 bytes")
+                formatter.write_str("32 bytes")
             }
 
             fn visit_bytes<E>(self, v: &[u8]) -> core::result::Result<Self::Value, E>
@@ -303,10 +332,15 @@ impl<'de> serde::Deserialize<'de> for ObjectHandle {
                 E: serde::de::Error,
             {
                 use core::convert::TryInto;
-                if v.len() != 16 {
-                    return Err(E::invalid_length(v.len(), &self));
+                match v.len() {
+                    32 => Ok(ObjectHandle {
+                        object_id: UniqueId(v[..16].try_into().unwrap()),
+                        tag: v[16..].try_into().unwrap(),
+                    }),
+                    // handles from before they were authenticated, see `ObjectHandle::legacy`
+                    16 => Ok(ObjectHandle::new(UniqueId(v.try_into().unwrap()))),
+                    _ => Err(E::invalid_length(v.len(), &self)),
                 }
-                Ok(ObjectHandle { object_id: UniqueId(v.try_into().unwrap()) } )
             }
         }
 
@@ -315,7 +349,7 @@ impl<'de> serde::Deserialize<'de> for ObjectHandle {
 }
 
 
-#[derive(Clone, Eq, PartialEq, Debug)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub enum ObjectType {
     Certificate(CertificateType),
     // TODO: maybe group under Feature(FeautureType), with FeatureType = Counter, ...
@@ -325,6 +359,76 @@ pub enum ObjectType {
     Key(Secrecy),
 }
 
+/// A key as listed by `ListKeysFirst` and `ListKeysNext`.
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct KeyEntry {
+    pub key: ObjectHandle,
+    pub secrecy: Secrecy,
+    pub location: Location,
+    pub info: crate::key::Info,
+}
+
+/// Search template of `FindObjects`, as in PKCS#11's `C_FindObjectsInit`.
+///
+/// Objects match if all attributes that are set match.
+#[derive(Clone, Default, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ObjectTemplate {
+    pub object_type: Option<ObjectType>,
+    pub kind: Option<crate::key::Kind>,
+    pub label: Option<crate::key::Label>,
+    pub location: Option<Location>,
+    /// flags the object must have (but it may have more)
+    pub flags: Option<crate::key::Flags>,
+}
+
+impl ObjectTemplate {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn set_object_type(mut self, object_type: ObjectType) -> Self {
+        self.object_type = Some(object_type);
+        self
+    }
+
+    pub fn set_kind(mut self, kind: crate::key::Kind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    pub fn set_label(mut self, label: crate::key::Label) -> Self {
+        self.label = Some(label);
+        self
+    }
+
+    pub fn set_location(mut self, location: Location) -> Self {
+        self.location = Some(location);
+        self
+    }
+
+    pub fn set_flags(mut self, flags: crate::key::Flags) -> Self {
+        self.flags = Some(flags);
+        self
+    }
+
+    pub fn matches_key(&self, info: &crate::key::Info, secrecy: Secrecy, location: Location) -> bool {
+        self.object_type.map_or(true, |object_type| object_type == ObjectType::Key(secrecy))
+            && self.kind.map_or(true, |kind| kind == info.kind)
+            && self.label.as_ref().map_or(true, |label| Some(label) == info.label.as_ref())
+            && self.location.map_or(true, |expected| expected == location)
+            && self.flags.map_or(true, |flags| info.flags.contains(flags))
+    }
+
+    /// Objects other than keys have neither kind nor flags.
+    pub fn matches_object(&self, attributes: &Attributes, location: Location) -> bool {
+        self.object_type.map_or(true, |object_type| object_type == attributes.object_type())
+            && self.kind.is_none()
+            && self.label.as_ref().map_or(true, |label| Some(label) == attributes.label())
+            && self.location.map_or(true, |expected| expected == location)
+            && self.flags.is_none()
+    }
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub struct PublicKeyAttributes {
     // never return naked private key
@@ -365,15 +469,28 @@ pub struct StorageAttributes {
     // each object must have a unique ID
     // unique_id: UniqueId,
 
-    // description of object
-    // label: String<MAX_LABEL_LENGTH>,
+    /// description of object, to find it with `FindObjects`
+    pub label: Option<crate::key::Label>,
 
     // // cryptoki: token (vs session) object
     // persistent: bool,
     pub persistence: Location,
 
-    // cryptoki: user must be logged in
-    // private: bool,
+    /// usage policy of created secret keys, enforced by the service
+    pub policy: crate::key::Policy,
+
+    /// cryptoki: whether created secret keys may be wrapped
+    pub extractable: bool,
+
+    /// cryptoki: whether created secret keys require the client's PIN to be verified
+    pub private: bool,
+
+    /// well-known ID for the key created by `GenerateKey`, `DeserializeKey`, `ImportKey`
+    /// or `UnwrapKey`, e.g. for PIV's slot 9A
+    pub slot: Option<SpecialId>,
+
+    /// whether a key in the slot may be replaced
+    pub replace_slot: bool,
 
     // modifiable: bool,
     // copyable: bool,
@@ -386,6 +503,33 @@ impl StorageAttributes {
         self.persistence = persistence;
         self
     }
+
+    pub fn set_policy(mut self, policy: crate::key::Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn set_extractable(mut self, extractable: bool) -> Self {
+        self.extractable = extractable;
+        self
+    }
+
+    pub fn set_private(mut self, private: bool) -> Self {
+        self.private = private;
+        self
+    }
+
+    /// Create the key in the slot, failing if there is a key unless `replace` is set.
+    pub fn set_slot(mut self, slot: SpecialId, replace: bool) -> Self {
+        self.slot = Some(slot);
+        self.replace_slot = replace;
+        self
+    }
+
+    pub fn set_label(mut self, label: crate::key::Label) -> Self {
+        self.label = Some(label);
+        self
+    }
 }
 
 impl StorageAttributes {
@@ -393,10 +537,16 @@ impl StorageAttributes {
     pub fn new() -> Self {
         Self {
             // unique_id,
-            // label: String::new(),
+            label: None,
             // persistent: false,
 
             persistence: Location::Volatile,
+            policy: Default::default(),
+            // needed to wrap keys, e.g. into FIDO credential IDs
+            extractable: true,
+            private: false,
+            slot: None,
+            replace_slot: false,
 
             // modifiable: true,
             // copyable: true,
@@ -422,6 +572,9 @@ pub enum Mechanism {
     // P256XSha256,
     P256,
     P256Prehashed,
+    /// P256 signatures with deterministic nonces (RFC 6979), unhedged
+    P256Deterministic,
+    P256PrehashedDeterministic,
     // clients can also do hashing by themselves
     Sha256,
     Tdes,
@@ -430,6 +583,50 @@ pub enum Mechanism {
     X255,
 }
 
+impl Mechanism {
+    /// Stable encoding, as used in key policies.
+    pub fn code(self) -> u8 {
+        match self {
+            Mechanism::Aes256Cbc => 1,
+            Mechanism::Chacha8Poly1305 => 2,
+            Mechanism::Ed255 => 3,
+            Mechanism::HmacSha1 => 4,
+            Mechanism::HmacSha256 => 5,
+            Mechanism::HmacSha512 => 6,
+            Mechanism::P256 => 7,
+            Mechanism::P256Prehashed => 8,
+            Mechanism::P256Deterministic => 9,
+            Mechanism::P256PrehashedDeterministic => 10,
+            Mechanism::Sha256 => 11,
+            Mechanism::Tdes => 12,
+            Mechanism::Totp => 13,
+            Mechanism::Trng => 14,
+            Mechanism::X255 => 15,
+        }
+    }
+
+    pub fn try_from_code(code: u8) -> Result<Self, crate::error::Error> {
+        Ok(match code {
+            1 => Mechanism::Aes256Cbc,
+            2 => Mechanism::Chacha8Poly1305,
+            3 => Mechanism::Ed255,
+            4 => Mechanism::HmacSha1,
+            5 => Mechanism::HmacSha256,
+            6 => Mechanism::HmacSha512,
+            7 => Mechanism::P256,
+            8 => Mechanism::P256Prehashed,
+            9 => Mechanism::P256Deterministic,
+            10 => Mechanism::P256PrehashedDeterministic,
+            11 => Mechanism::Sha256,
+            12 => Mechanism::Tdes,
+            13 => Mechanism::Totp,
+            14 => Mechanism::Trng,
+            15 => Mechanism::X255,
+            _ => return Err(crate::error::Error::InvalidSerializedKey),
+        })
+    }
+}
+
 pub type LongData = Bytes<MAX_LONG_DATA_LENGTH>;
 pub type MediumData = Bytes<MAX_MEDIUM_DATA_LENGTH>;
 pub type ShortData = Bytes<MAX_SHORT_DATA_LENGTH>;
@@ -440,26 +637,46 @@ pub type Message = Bytes<MAX_MESSAGE_LENGTH>;
 pub enum KeySerialization {
     // Asn1Der,
     Cose,
-    // Der,
+    /// `SubjectPublicKeyInfo` for public keys, PKCS#8 `PrivateKeyInfo` for private keys.
+    Der,
     EcdhEsHkdf256,
+    /// JSON Web Key, public keys only.
+    Jwk,
+    /// `authorized_keys` line without comment, public keys only (no X255), not deserializable.
+    OpenSsh,
+    /// `Der`, wrapped in PEM armor ("PUBLIC KEY" resp. "PRIVATE KEY").
+    Pem,
     Raw,
     Sec1,
 }
 
 pub type Signature = Bytes<MAX_SIGNATURE_LENGTH>;
 
+/// Encodings of signatures.
+///
+/// For ECDSA, `Raw` is the fixed-size concatenation of `r` and `s`, while `Asn1Der` is the
+/// `ECDSA-Sig-Value` structure of X.509 and WebAuthn. EdDSA signatures only have the `Raw` form.
+///
+/// `Cose` (RFC 8152, section 8) and `Jose` (RFC 7518, section 3.4) name the encodings used
+/// in COSE and JWS signatures, which coincide with `Raw` for all our mechanisms.
+///
+/// `OpenSsh` is the complete SSH signature blob (`ssh-ed25519`, `ecdsa-sha2-nistp256`),
+/// `OpenSshSk` that of FIDO security keys (`sk-ssh-ed25519@openssh.com`,
+/// `sk-ecdsa-sha2-nistp256@openssh.com`), with the given flags and counter appended.
+/// For the latter, the message to sign is the data the authenticator would have signed.
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub enum SignatureSerialization {
     Asn1Der,
-    // Cose,
+    Cose,
+    Jose,
+    OpenSsh,
+    OpenSshSk { flags: u8, counter: u32 },
     Raw,
     // Sec1,
 }
 
 pub type SpecialId = u8;
 
-// TODO: We rely on the RNG being good, so 15 zero bytes are improbable, and there
-// are no clashes between trussed-generated and special (app-chosen) IDs.
 // We may or may not want to model this in more detail as enum { Special, Random },
 // and make sure the randomly generated IDs are never in the "special" range.
 #[derive(Copy, Clone, Eq, PartialEq)]//, Deserialize, Serialize)]
@@ -477,13 +694,32 @@ impl From<SpecialId> for UniqueId {
 }
 
 impl UniqueId {
+    /// Whether this is a well-known ID, which may be reused for another key.
+    pub fn is_special(&self) -> bool {
+        self.0[..15].iter().all(|&byte| byte == 0)
+    }
+
+    /// The lowercase hex of all 16 bytes, which names the files of keys and objects.
     pub fn hex(&self) -> Bytes<consts::U32> {
         const HEX_CHARS: &[u8] = b"0123456789abcdef";
         let mut buffer = Bytes::new();
 
+        for byte in self.0.iter() {
+            buffer.push(HEX_CHARS[(byte >> 4) as usize]).unwrap();
+            buffer.push(HEX_CHARS[(byte & 0xf) as usize]).unwrap();
+        }
+
+        buffer
+    }
+
+    /// The hex that named key files before `hex` was lossless: zero bytes were skipped,
+    /// except the last byte, so e.g. special IDs were named by a single byte.
+    pub(crate) fn legacy_hex(&self) -> Bytes<consts::U32> {
+        const HEX_CHARS: &[u8] = b"0123456789abcdef";
+        let mut buffer = Bytes::new();
+
         for i in 0 .. self.0.len() {
             if self.0[i] == 0 && i != (self.0.len()-1) {
-                // Skip leading zeros.
                 continue;
             }
 
@@ -494,28 +730,18 @@ impl UniqueId {
         buffer
     }
 
+    /// Parses the output of `hex`; shorter input is left-padded with zero bytes,
+    /// so the legacy names of special IDs are parsed as well.
     #[allow(clippy::result_unit_err)]
     pub fn try_from_hex(hex: &[u8]) -> core::result::Result<Self, ()> {
-        // https://stackoverflow.com/a/52992629
-        // (0..hex.len())
-        // use hex::FromHex;
-        // let maybe_bytes = <[u8; 16]>::from_hex(hex).map_err(|e| ());
-        // maybe_bytes.map(|bytes| Self(Bytes::try_from_slice(&bytes).unwrap()))
-        if (hex.len() & 1) == 1 {
-            // panic!("hex len & 1 =  {}", hex.len() & 1);
-            return Err(());
-        }
-        if hex.len() > 32 {
-            // panic!("hex len {}", hex.len());
+        if (hex.len() & 1) == 1 || hex.len() > 32 || !hex.iter().all(u8::is_ascii_hexdigit) {
             return Err(());
         }
-        // let hex = core::str::from_utf8(hex).map_err(|e| ())?;
-        let hex = core::str::from_utf8(hex).unwrap();
-        // let hex = core::str::from_utf8_unchecked(hex);
+        let hex = core::str::from_utf8(hex).map_err(|_| ())?;
         let mut bytes = [0u8; 16];
+        let offset = 16 - hex.len() / 2;
         for i in 0..(hex.len() >> 1) {
-            // bytes[i] = u8::from_str_radix(&hex[i..][..2], 16).map_err(|e| ())?;
-            bytes[i] = u8::from_str_radix(&hex[2*i..][..2], 16).unwrap();
+            bytes[offset + i] = u8::from_str_radix(&hex[2*i..][..2], 16).map_err(|_| ())?;
         }
         Ok(UniqueId(bytes))
     }
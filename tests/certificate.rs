@@ -21,3 +21,32 @@ fn certificate_client() {
         assert!(try_syscall!(client.delete_certificate(id)).is_err());
     });
 }
+
+#[test]
+fn certificates_are_objects() {
+    use trussed::client::CryptoClient as _;
+    use trussed::types::{Attributes, CertificateAttributes, CertificateType, Id, Message, ObjectTemplate, ObjectType};
+
+    client::get(|client| {
+        let der = Message::try_from_slice(&[1u8, 2, 3]).unwrap();
+        let id = syscall!(client.write_certificate(Internal, &der)).id;
+        let template = ObjectTemplate::new().set_object_type(ObjectType::Certificate(CertificateType::PublicKey));
+        let found = syscall!(client.find_objects(template, 0)).objects;
+        assert_eq!(found.len(), 1);
+        assert_eq!(Id::from(found[0].object_id), id);
+
+        let attributes = CertificateAttributes {
+            certificate_type: CertificateType::PublicKey,
+            label: None,
+            der: Message::try_from_slice(&[4u8, 5, 6]).unwrap(),
+        };
+        let object = syscall!(client.create_object(Attributes::Certificate(attributes), Internal)).object;
+        let id = Id::from(object.object_id);
+        assert_eq!(syscall!(client.read_certificate(id)).der, &[4u8, 5, 6]);
+        syscall!(client.delete_certificate(id));
+        assert_eq!(
+            try_syscall!(client.get_attributes(object)).err(),
+            Some(trussed::error::Error::ObjectHandleInvalid),
+        );
+    });
+}
@@ -1,64 +1,156 @@
-trussed::platform!(Platform,
-    R: chacha20::ChaCha8Rng,
-    S: store::Store,
-    UI: ui::UserInterface,
-);
+/// The test platform, like those of `trussed::platform!`, but with a hardware-unique key
+/// only if the test chooses one.
+pub struct Platform {
+    rng: chacha20::ChaCha8Rng,
+    store: store::Store,
+    user_interface: ui::UserInterface,
+    huk: Option<trussed::platform::SoftwareHuk>,
+}
+
+impl Platform {
+    pub fn new(store: store::Store, huk: Option<trussed::platform::SoftwareHuk>) -> Self {
+        use rand_core::SeedableRng as _;
+        // causing a regression again
+        // let rng = chacha20::ChaCha8Rng::from_rng(rand_core::OsRng).unwrap();
+        let rng = chacha20::ChaCha8Rng::from_seed([42u8; 32]);
+        Self { rng, store, user_interface: ui::UserInterface::new(), huk }
+    }
+}
+
+unsafe impl trussed::platform::Platform for Platform {
+    type R = chacha20::ChaCha8Rng;
+    type S = store::Store;
+    type UI = ui::UserInterface;
+
+    fn user_interface(&mut self) -> &mut Self::UI { &mut self.user_interface }
+    fn rng(&mut self) -> &mut Self::R { &mut self.rng }
+    fn store(&self) -> Self::S { self.store }
+    fn hardware_unique_key(&mut self) -> Option<&mut dyn trussed::platform::HardwareUniqueKey> {
+        self.huk.as_mut().map(|huk| huk as &mut dyn trussed::platform::HardwareUniqueKey)
+    }
+}
+
+/// The HUK of the tests, under which key files are encrypted at rest.
+pub fn huk() -> trussed::platform::SoftwareHuk {
+    trussed::platform::SoftwareHuk::new([37u8; 32])
+}
+
+pub type Service = trussed::service::Service<Platform>;
 
 pub fn get<R>(
-        test: impl FnOnce(&mut trussed::ClientImplementation<&mut trussed::service::Service<Platform>>) -> R
+        test: impl FnOnce(&mut trussed::ClientImplementation<&mut Service>) -> R
     )
         -> R
 {
-    use trussed::Interchange as _;
-    unsafe { trussed::pipe::TrussedInterchange::reset_claims(); }
-    let trussed_platform = init_platform();
-    let mut trussed_service = trussed::service::Service::new(trussed_platform);
-
-    // preparations for attestation
-    let client_id = "attn";
-    let mut attn_client = trussed_service.try_as_new_client(client_id).unwrap();
-
-    use trussed::client::mechanisms::Ed255;
-    let _attn_ed255_key = trussed::syscall!(attn_client.generate_ed255_private_key(trussed::types::Location::Internal)).key;
-    use trussed::client::mechanisms::P256;
-    let _attn_p256_key = trussed::syscall!(attn_client.generate_p256_private_key(trussed::types::Location::Internal)).key;
-
-    // destroy this attestation client
-    unsafe { trussed::pipe::TrussedInterchange::reset_claims(); }
-
-    let client_id = "test";
-    let mut trussed_client = trussed_service.try_as_new_client(client_id).unwrap();
-    test(&mut trussed_client)
+    get_with(Some(huk()), test)
 }
 
-pub fn init_platform() -> Platform {
-    use rand_core::SeedableRng as _;
-    // causing a regression again
-    // let rng = chacha20::ChaCha8Rng::from_rng(rand_core::OsRng).unwrap();
-    let rng = chacha20::ChaCha8Rng::from_seed([42u8; 32]);
-    let store = store::Store::format(
-        store::InternalStorage::new(),
-        store::ExternalStorage::new(),
-        store::VolatileStorage::new(),
-        );
-    let ui = ui::UserInterface::new();
-
-    let platform = Platform::new(rng, store, ui);
-
-    platform
+/// Runs the test as client "test", on a platform with the given HUK, if any.
+pub fn get_with<R>(
+        huk: Option<trussed::platform::SoftwareHuk>,
+        test: impl FnOnce(&mut trussed::ClientImplementation<&mut Service>) -> R
+    )
+        -> R
+{
+    service_with(huk, |trussed_service| {
+        use trussed::Interchange as _;
+        unsafe { trussed::pipe::TrussedInterchange::reset_claims(); }
+
+        // preparations for attestation
+        let client_id = "attn";
+        let mut attn_client = trussed_service.try_as_new_client(client_id).unwrap();
+
+        use trussed::client::mechanisms::Ed255;
+        let _attn_ed255_key = trussed::syscall!(attn_client.generate_ed255_private_key(trussed::types::Location::Internal)).key;
+        use trussed::client::mechanisms::P256;
+        let _attn_p256_key = trussed::syscall!(attn_client.generate_p256_private_key(trussed::types::Location::Internal)).key;
+
+        // destroy this attestation client
+        unsafe { trussed::pipe::TrussedInterchange::reset_claims(); }
+
+        let client_id = "test";
+        let mut trussed_client = trussed_service.try_as_new_client(client_id).unwrap();
+        test(&mut trussed_client)
+    })
 }
 
+/// Runs the test with a fresh service, for tests that add their own clients.
+pub fn service<R>(test: impl FnOnce(&mut Service) -> R) -> R {
+    service_with(Some(huk()), test)
+}
+
+/// Runs the test with a fresh service on a platform with the given HUK, if any.
+pub fn service_with<R>(huk: Option<trussed::platform::SoftwareHuk>, test: impl FnOnce(&mut Service) -> R) -> R {
+    // the tests of a binary share the static storage and interchange
+    let _serial = Serial::lock();
+    let mut trussed_service = trussed::service::Service::new(Platform::new(store::format(), huk));
+    test(&mut trussed_service)
+}
+
+/// Runs the tests of a binary one after another, also if one of them panics.
+struct Serial;
+
+impl Serial {
+    fn lock() -> Self {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        while RUNNING.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            std::thread::yield_now();
+        }
+        Self
+    }
+}
+
+impl Drop for Serial {
+    fn drop(&mut self) {
+        RUNNING.store(false, std::sync::atomic::Ordering::Release);
+    }
+}
+
+static RUNNING: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
 pub mod ui {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
     use trussed::platform::{consent, reboot, ui};
-    pub struct UserInterface { start_time: std::time::Instant }
 
-    impl UserInterface { pub fn new() -> Self { Self { start_time: std::time::Instant::now() } } }
+    /// A user who is present unless a test says otherwise, and whose absence lets
+    /// time pass, so waiting for them times out at once.
+    pub struct UserInterface { start_time: std::time::Instant, waited: core::time::Duration }
+
+    impl UserInterface {
+        pub fn new() -> Self {
+            PRESENT.store(true, Ordering::Relaxed);
+            PRESENCE_CHECKS.store(0, Ordering::Relaxed);
+            Self { start_time: std::time::Instant::now(), waited: core::time::Duration::from_secs(0) }
+        }
+    }
+
+    static PRESENT: AtomicBool = AtomicBool::new(true);
+    static PRESENCE_CHECKS: AtomicUsize = AtomicUsize::new(0);
+
+    /// Whether the user confirms their presence from now on.
+    pub fn set_present(present: bool) {
+        PRESENT.store(present, Ordering::Relaxed);
+    }
+
+    /// How often the service asked for the user's presence since the platform was created.
+    pub fn presence_checks() -> usize {
+        PRESENCE_CHECKS.load(Ordering::Relaxed)
+    }
 
     impl trussed::platform::UserInterface for UserInterface {
-        fn check_user_presence(&mut self) -> consent::Level { consent::Level::Normal }
+        fn check_user_presence(&mut self) -> consent::Level {
+            PRESENCE_CHECKS.fetch_add(1, Ordering::Relaxed);
+            if PRESENT.load(Ordering::Relaxed) {
+                consent::Level::Normal
+            } else {
+                self.waited += core::time::Duration::from_secs(1);
+                consent::Level::None
+            }
+        }
         fn set_status(&mut self, _status: ui::Status) {}
         fn refresh(&mut self) {}
-        fn uptime(&mut self) -> core::time::Duration { self.start_time.elapsed() }
+        fn uptime(&mut self) -> core::time::Duration { self.start_time.elapsed() + self.waited }
+        fn time(&mut self) -> Option<core::time::Duration> { std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).ok() }
         fn reboot(&mut self, _to: reboot::To) -> ! { loop { continue; } }
     }
 }
@@ -77,4 +169,19 @@ pub mod store {
         External: ExternalStorage,
         Volatile: VolatileStorage
     );
+
+    /// Formats fresh storage; unlike `Store::format`, this works for each test,
+    /// as only the first call can claim the store.
+    pub fn format() -> Store {
+        let (ifs_alloc, ifs, efs_alloc, efs, vfs_alloc, vfs) =
+            Store::allocate(InternalStorage::new(), ExternalStorage::new(), VolatileStorage::new());
+        let store = Store::claim().unwrap_or(Store { __: core::marker::PhantomData });
+        store.mount(ifs_alloc, ifs, efs_alloc, efs, vfs_alloc, vfs, true).unwrap();
+        store
+    }
+
+    /// The formatted storage, e.g. to inspect the files a service wrote, or for another service.
+    pub fn get() -> Store {
+        Store { __: core::marker::PhantomData }
+    }
 }
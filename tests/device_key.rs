@@ -0,0 +1,93 @@
+use trussed::client::mechanisms::{Ed255, HmacSha256};
+use trussed::client::CryptoClient;
+use trussed::{syscall, try_syscall};
+
+mod client;
+
+use trussed::error::Error;
+use trussed::key::{Key, Kind};
+use trussed::types::Location::*;
+use trussed::types::{Bytes, Location, Mechanism, ObjectHandle, PathBuf, StorageAttributes, consts};
+
+/// The bytes of the client's key file.
+fn key_file(location: Location, directory: &str, key: ObjectHandle) -> Bytes<consts::U512> {
+    let mut path = PathBuf::from("test");
+    path.push(&PathBuf::from(directory));
+    path.push(&PathBuf::from(key.object_id.hex().as_ref()));
+    trussed::store::read(client::store::get(), location, &path).unwrap()
+}
+
+
+#[test]
+fn deterministic_device_keys() {
+    client::get(|client| {
+        let attributes = StorageAttributes::new().set_persistence(Volatile);
+        let first = syscall!(client.derive_device_key(b"credential ids", attributes.clone())).key;
+        let second = syscall!(client.derive_device_key(b"credential ids", attributes.clone())).key;
+        let other = syscall!(client.derive_device_key(b"backups", attributes)).key;
+        assert_ne!(first, second);
+
+        let first_mac = syscall!(client.sign_hmacsha256(first, b"message")).signature;
+        let second_mac = syscall!(client.sign_hmacsha256(second, b"message")).signature;
+        let other_mac = syscall!(client.sign_hmacsha256(other, b"message")).signature;
+        assert_eq!(first_mac, second_mac);
+        assert_ne!(first_mac, other_mac);
+    })
+}
+
+#[test]
+fn encrypted_keystore() {
+    client::get(|client| {
+        // with the platform's HUK, key files are transparently encrypted
+        let sk = syscall!(client.generate_ed255_private_key(Internal)).key;
+        let pk = syscall!(client.derive_ed255_public_key(sk, External)).key;
+        let signature = syscall!(client.sign_ed255(sk, b"message")).signature;
+        assert!(syscall!(client.verify_ed255(pk, b"message", &signature)).valid);
+        assert!(syscall!(client.exists(Mechanism::Ed255, sk)).exists);
+
+        // the files are sealed, not serialized keys
+        for bytes in [key_file(Internal, "sec", sk), key_file(External, "pub", pk)].iter() {
+            assert_eq!(bytes[0], 0x40);
+            assert!(Key::try_deserialize(bytes).is_err());
+        }
+    })
+}
+
+#[test]
+fn plaintext_keystore() {
+    client::get_with(None, |client| {
+        // without a HUK, key files are the serialized keys
+        let sk = syscall!(client.generate_ed255_private_key(Internal)).key;
+        let pk = syscall!(client.derive_ed255_public_key(sk, External)).key;
+        let signature = syscall!(client.sign_ed255(sk, b"message")).signature;
+        assert!(syscall!(client.verify_ed255(pk, b"message", &signature)).valid);
+        assert_eq!(Key::try_deserialize(&key_file(Internal, "sec", sk)).unwrap().kind, Kind::Ed255);
+        assert_eq!(Key::try_deserialize(&key_file(External, "pub", pk)).unwrap().kind, Kind::Ed255);
+
+        // and there are no device keys
+        let attributes = StorageAttributes::new().set_persistence(Volatile);
+        assert_eq!(
+            try_syscall!(client.derive_device_key(b"credential ids", attributes)).err(),
+            Some(Error::RequestNotAvailable),
+        );
+    })
+}
+
+#[test]
+fn keys_from_before_the_huk() {
+    client::service_with(None, |service| {
+        use trussed::Interchange as _;
+        unsafe { trussed::pipe::TrussedInterchange::reset_claims(); }
+        let mut client = service.try_as_new_client("test").unwrap();
+        let sk = syscall!(client.generate_ed255_private_key(Internal)).key;
+        let signature = syscall!(client.sign_ed255(sk, b"message")).signature;
+        drop(client);
+
+        // after an update, the platform has a HUK, and the key file is sealed when first used
+        let mut service = trussed::service::Service::new(client::Platform::new(client::store::get(), Some(client::huk())));
+        unsafe { trussed::pipe::TrussedInterchange::reset_claims(); }
+        let mut client = service.try_as_new_client("test").unwrap();
+        assert_eq!(syscall!(client.sign_ed255(sk, b"message")).signature, signature);
+        assert_eq!(key_file(Internal, "sec", sk)[0], 0x40);
+    })
+}
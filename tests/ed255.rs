@@ -0,0 +1,36 @@
+use trussed::client::mechanisms::Ed255;
+use trussed::client::CryptoClient;
+use trussed::syscall;
+
+mod client;
+
+use trussed::types::Location::*;
+use trussed::types::{Mechanism, SignatureSerialization};
+
+
+#[test]
+fn ed255_openssh_signatures() {
+    client::get(|client| {
+        let sk = syscall!(client.generate_ed255_private_key(Internal)).key;
+        let pk = syscall!(client.derive_ed255_public_key(sk, Volatile)).key;
+        let message = b"data to sign";
+
+        let raw = syscall!(client.sign_ed255(sk, message)).signature;
+        let blob = syscall!(client.sign(Mechanism::Ed255, sk, message, SignatureSerialization::OpenSsh)).signature;
+        // deterministic signatures
+        assert_eq!(&blob[..], &[&b"\0\0\0\x0bssh-ed25519\0\0\0\x40"[..], &raw[..]].concat()[..]);
+        assert!(syscall!(client.verify(Mechanism::Ed255, pk, message, &blob, SignatureSerialization::OpenSsh)).valid);
+
+        let format = SignatureSerialization::OpenSshSk { flags: 0x05, counter: 0x0102_0304 };
+        let blob = syscall!(client.sign(Mechanism::Ed255, sk, message, format)).signature;
+        assert_eq!(&blob[..30], b"\0\0\0\x1ask-ssh-ed25519@openssh.com");
+        assert_eq!(&blob[30..98], &[&b"\0\0\0\x40"[..], &raw[..]].concat()[..]);
+        assert_eq!(&blob[98..], &[0x05, 1, 2, 3, 4]);
+        assert!(syscall!(client.verify(Mechanism::Ed255, pk, message, &blob, format)).valid);
+        // the blob has other flags or another counter than expected
+        let other_flags = SignatureSerialization::OpenSshSk { flags: 0x04, counter: 0x0102_0304 };
+        let other_counter = SignatureSerialization::OpenSshSk { flags: 0x05, counter: 0x0102_0305 };
+        assert!(!syscall!(client.verify(Mechanism::Ed255, pk, message, &blob, other_flags)).valid);
+        assert!(!syscall!(client.verify(Mechanism::Ed255, pk, message, &blob, other_counter)).valid);
+    })
+}
@@ -0,0 +1,32 @@
+use trussed::client::mechanisms::Ed255;
+use trussed::client::{FilesystemClient, PinClient};
+use trussed::error::Error;
+use trussed::types::{Message, PathBuf};
+use trussed::{syscall, try_syscall};
+
+mod client;
+
+use trussed::types::Location::*;
+
+
+#[test]
+fn paths_stay_in_the_client_directory() {
+    client::get_with(None, |client| {
+        let data = Message::try_from_slice(b"data").unwrap();
+        syscall!(client.write_file(Internal, PathBuf::from("file"), data.clone(), None));
+        assert_eq!(syscall!(client.read_file(Internal, PathBuf::from("file"))).data, data);
+
+        // without a HUK, the handle key is derived from this device secret
+        syscall!(client.generate_ed255_private_key(Internal));
+        let path = PathBuf::from("../../trussed/dat/handle-key.bin");
+        assert_eq!(try_syscall!(client.read_file(Internal, path)).err(), Some(Error::InvalidPath));
+
+        // nor can the client remove its PIN file to reset its retries
+        syscall!(client.set_pin(b"123456", 3));
+        assert_eq!(try_syscall!(client.remove_file(Internal, PathBuf::from("../pin"))).err(), Some(Error::InvalidPath));
+        assert_eq!(syscall!(client.pin_retries()).retries, Some(3));
+
+        let path = PathBuf::from("dir/../../file");
+        assert_eq!(try_syscall!(client.write_file(Internal, path, data, None)).err(), Some(Error::InvalidPath));
+    })
+}
@@ -0,0 +1,99 @@
+use trussed::client::mechanisms::Ed255;
+use trussed::client::CryptoClient;
+use trussed::{syscall, try_syscall};
+
+mod client;
+
+use trussed::error::Error;
+use trussed::key::{Flags, Kind, Label, Secrecy};
+use trussed::types::Location::*;
+use trussed::types::{
+    Attributes, DataAttributes, KeyAttributes, KeySerialization, LongData, Mechanism, Message,
+    ObjectTemplate, ObjectType, ShortData, StorageAttributes,
+};
+
+
+#[test]
+fn find_by_label_and_location() {
+    client::get(|client| {
+        let label = Label::try_from_slice(b"signing").unwrap();
+        let attributes = StorageAttributes::new().set_persistence(Internal).set_label(label.clone());
+        let sk = syscall!(client.generate_key(Mechanism::Ed255, attributes.clone())).key;
+        let pk = syscall!(client.derive_key(
+            Mechanism::Ed255, sk, None, attributes.set_persistence(Volatile))).key;
+        let other = syscall!(client.generate_ed255_private_key(Internal)).key;
+
+        let found = syscall!(client.find_objects(ObjectTemplate::new().set_label(label.clone()), 0));
+        assert_eq!(found.num_objects, 2);
+        assert!(found.objects.contains(&sk) && found.objects.contains(&pk));
+
+        let template = ObjectTemplate::new().set_label(label).set_location(Internal);
+        let found = syscall!(client.find_objects(template, 0));
+        assert_eq!(&found.objects[..], &[sk]);
+
+        let template = ObjectTemplate::new().set_kind(Kind::Ed255).set_flags(Flags::SENSITIVE);
+        let found = syscall!(client.find_objects(template, 0));
+        assert_eq!(found.num_objects, 2);
+        assert!(found.objects.contains(&sk) && found.objects.contains(&other));
+    })
+}
+
+#[test]
+fn find_paged() {
+    client::get(|client| {
+        let label = Label::try_from_slice(b"many").unwrap();
+        let attributes = StorageAttributes::new().set_label(label.clone());
+        for _ in 0..20 {
+            syscall!(client.generate_key(Mechanism::Chacha8Poly1305, attributes.clone()));
+        }
+
+        let template = ObjectTemplate::new().set_label(label);
+        let first = syscall!(client.find_objects(template.clone(), 0));
+        assert_eq!(first.objects.len(), 16);
+        assert_eq!(first.num_objects, 20);
+
+        let rest = syscall!(client.find_objects(template, 16));
+        assert_eq!(rest.objects.len(), 4);
+        assert_eq!(rest.num_objects, 20);
+        assert!(rest.objects.iter().all(|object| !first.objects.contains(object)));
+    })
+}
+
+#[test]
+fn create_find_and_delete_objects() {
+    client::get(|client| {
+        let data = DataAttributes {
+            kind: ShortData::try_from_slice(b"config").unwrap(),
+            label: Some(Label::try_from_slice(b"settings").unwrap()),
+            value: LongData::try_from_slice(b"some state").unwrap(),
+        };
+        let data_object = syscall!(client.create_object(Attributes::Data(data), Internal)).object;
+
+        // RFC 8032, 7.1, test 1
+        let public_key = hex_literal::hex!("d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a");
+        let key = KeyAttributes {
+            mechanism: Mechanism::Ed255,
+            format: KeySerialization::Raw,
+            label: Some(Label::try_from_slice(b"peer").unwrap()),
+            public_key: Message::try_from_slice(&public_key).unwrap(),
+        };
+        let key_object = syscall!(client.create_object(Attributes::Key(key), Volatile)).object;
+        let serialized = syscall!(client.serialize_key(Mechanism::Ed255, key_object, KeySerialization::Raw)).serialized_key;
+        assert_eq!(&serialized[..], &public_key[..]);
+
+        let found = syscall!(client.find_objects(ObjectTemplate::new().set_object_type(ObjectType::Data), 0));
+        assert_eq!(&found.objects[..], &[data_object]);
+        let template = ObjectTemplate::new().set_label(Label::try_from_slice(b"peer").unwrap());
+        let found = syscall!(client.find_objects(template.set_object_type(ObjectType::Key(Secrecy::Public)), 0));
+        assert_eq!(&found.objects[..], &[key_object]);
+
+        assert!(syscall!(client.delete(data_object)).success);
+        let found = syscall!(client.find_objects(ObjectTemplate::new(), 0));
+        assert_eq!(&found.objects[..], &[key_object]);
+
+        assert_eq!(
+            try_syscall!(client.create_object(Attributes::Counter, Volatile)).err(),
+            Some(Error::RequestNotAvailable),
+        );
+    })
+}
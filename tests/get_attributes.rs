@@ -0,0 +1,100 @@
+use trussed::client::mechanisms::{Chacha8Poly1305, P256};
+use trussed::client::CryptoClient;
+use trussed::{syscall, try_syscall};
+
+mod client;
+
+use trussed::error::Error;
+use trussed::key::{Flags, Kind, Label, Secrecy};
+use trussed::types::Location::*;
+use trussed::types::{
+    Attributes, DataAttributes, LongData, Mechanism, ObjectType, ShortData, StorageAttributes,
+};
+
+
+#[test]
+fn key_attributes() {
+    client::get(|client| {
+        let label = Label::try_from_slice(b"signing").unwrap();
+        let attributes = StorageAttributes::new()
+            .set_persistence(Internal)
+            .set_label(label.clone())
+            .set_extractable(false);
+        let sk = syscall!(client.generate_key(Mechanism::P256, attributes)).key;
+        let pk = syscall!(client.derive_p256_public_key(sk, Volatile)).key;
+
+        let reply = syscall!(client.get_attributes(sk));
+        assert_eq!(reply.object_type, ObjectType::Key(Secrecy::Secret));
+        assert_eq!(reply.location, Internal);
+        assert!(reply.attributes.is_none());
+        let info = reply.key.unwrap();
+        assert_eq!(info.kind, Kind::P256);
+        assert_eq!(info.label, Some(label));
+        assert_eq!(info.flags,
+            Flags::LOCAL | Flags::SENSITIVE | Flags::ALWAYS_SENSITIVE | Flags::NEVER_EXTRACTABLE);
+        // the test platform has a clock
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+        let created = info.created.unwrap();
+        assert!(created <= now && now - created < 60);
+
+        let reply = syscall!(client.get_attributes(pk));
+        assert_eq!(reply.object_type, ObjectType::Key(Secrecy::Public));
+        assert_eq!(reply.location, Volatile);
+        let info = reply.key.unwrap();
+        assert_eq!(info.kind, Kind::P256);
+        assert!(!info.flags.contains(Flags::SENSITIVE));
+        assert!(info.created.is_some());
+    })
+}
+
+#[test]
+fn symmetric_key_attributes() {
+    client::get(|client| {
+        // symmetric keys generated on the device are local too
+        let attributes = StorageAttributes::new().set_persistence(Internal).set_extractable(false);
+        let key = syscall!(client.generate_key(Mechanism::Chacha8Poly1305, attributes)).key;
+        let info = syscall!(client.get_attributes(key)).key.unwrap();
+        assert_eq!(info.kind, Kind::Symmetric32Nonce(12));
+        assert_eq!(info.flags,
+            Flags::LOCAL | Flags::SENSITIVE | Flags::ALWAYS_SENSITIVE | Flags::NEVER_EXTRACTABLE);
+
+        let key = syscall!(client.generate_secret_key(16, Volatile)).key;
+        let info = syscall!(client.get_attributes(key)).key.unwrap();
+        assert_eq!(info.kind, Kind::Symmetric(16));
+        assert_eq!(info.flags,
+            Flags::LOCAL | Flags::SENSITIVE | Flags::ALWAYS_SENSITIVE | Flags::EXTRACTABLE);
+
+        // keys from outside are not
+        let wrapping_key = syscall!(client.generate_chacha8poly1305_key(Volatile)).key;
+        let wrapped_key = syscall!(client.wrap_key_chacha8poly1305(wrapping_key, key, b"")).wrapped_key;
+        let unwrapped_key = syscall!(client.unwrap_key_chacha8poly1305(wrapping_key, &wrapped_key, b"", Volatile))
+            .key.unwrap();
+        let info = syscall!(client.get_attributes(unwrapped_key)).key.unwrap();
+        assert!(!info.flags.contains(Flags::LOCAL));
+        assert!(!info.flags.contains(Flags::ALWAYS_SENSITIVE));
+    })
+}
+
+#[test]
+fn object_attributes() {
+    client::get(|client| {
+        let data = DataAttributes {
+            kind: ShortData::try_from_slice(b"config").unwrap(),
+            label: None,
+            value: LongData::try_from_slice(b"some state").unwrap(),
+        };
+        let object = syscall!(client.create_object(Attributes::Data(data.clone()), External)).object;
+
+        let reply = syscall!(client.get_attributes(object));
+        assert_eq!(reply.object_type, ObjectType::Data);
+        assert_eq!(reply.location, External);
+        assert!(reply.key.is_none());
+        assert_eq!(reply.attributes, Some(Attributes::Data(data)));
+
+        syscall!(client.delete(object));
+        assert_eq!(
+            try_syscall!(client.get_attributes(object)).err(),
+            Some(Error::ObjectHandleInvalid),
+        );
+    })
+}
@@ -0,0 +1,114 @@
+use trussed::client::mechanisms::{Chacha8Poly1305, Ed255};
+use trussed::client::CryptoClient;
+use trussed::{syscall, try_syscall};
+
+mod client;
+
+use trussed::error::Error;
+use trussed::key::Usage;
+use trussed::types::Location::*;
+use trussed::types::{KeySerialization, Mechanism, PathBuf, StorageAttributes};
+
+
+use client::Service;
+type Client<'a> = trussed::ClientImplementation<&'a mut Service>;
+
+fn as_client<R>(service: &mut Service, client_id: &str, f: impl FnOnce(&mut Client<'_>) -> R) -> R {
+    use trussed::Interchange as _;
+    unsafe { trussed::pipe::TrussedInterchange::reset_claims(); }
+    let mut client = service.try_as_new_client(client_id).unwrap();
+    f(&mut client)
+}
+
+#[test]
+fn grant_and_revoke() {
+    client::service(|service| {
+        let (sk, pk) = as_client(service, "alice", |alice| {
+            let sk = syscall!(alice.generate_ed255_private_key(Internal)).key;
+            let pk = syscall!(alice.derive_ed255_public_key(sk, Volatile)).key;
+            syscall!(alice.grant_key(sk, PathBuf::from("bob"), Usage::SIGN));
+            (sk, pk)
+        });
+
+        let signature = as_client(service, "bob", |bob| {
+            let keys = syscall!(bob.list_granted_keys(PathBuf::from("alice"))).keys;
+            assert_eq!(keys.len(), 1);
+            let key = keys[0];
+            assert_eq!(key.object_id, sk.object_id);
+
+            // usable for the granted operations only, and not exportable
+            assert_eq!(try_syscall!(bob.derive_ed255_public_key(key, Volatile)).err(), Some(Error::KeyFunctionNotPermitted));
+            assert!(try_syscall!(bob.serialize_ed255_key(key, KeySerialization::Raw)).is_err());
+            syscall!(bob.sign_ed255(key, b"message")).signature
+        });
+
+        as_client(service, "mallory", |mallory| {
+            assert!(syscall!(mallory.list_granted_keys(PathBuf::from("alice"))).keys.is_empty());
+            assert_eq!(try_syscall!(mallory.sign_ed255(sk, b"message")).err(), Some(Error::ObjectHandleInvalid));
+        });
+
+        as_client(service, "alice", |alice| {
+            assert!(syscall!(alice.verify_ed255(pk, b"message", &signature)).valid);
+            assert!(syscall!(alice.revoke_grant(sk, PathBuf::from("bob"))).revoked);
+            assert!(!syscall!(alice.revoke_grant(sk, PathBuf::from("bob"))).revoked);
+        });
+
+        as_client(service, "bob", |bob| {
+            assert!(syscall!(bob.list_granted_keys(PathBuf::from("alice"))).keys.is_empty());
+        });
+    })
+}
+
+#[test]
+fn granted_nonces() {
+    client::service(|service| {
+        let key = as_client(service, "alice", |alice| {
+            let key = syscall!(alice.generate_chacha8poly1305_key(Internal)).key;
+            syscall!(alice.grant_key(key, PathBuf::from("bob"), Usage::ENCRYPT | Usage::DECRYPT));
+            key
+        });
+
+        // the nonce advances in alice's key file, not in a copy of bob's
+        let (first, second) = as_client(service, "bob", |bob| {
+            let key = syscall!(bob.list_granted_keys(PathBuf::from("alice"))).keys[0];
+            let first = syscall!(bob.encrypt_chacha8poly1305(key, b"message", b"", None));
+            let second = syscall!(bob.encrypt_chacha8poly1305(key, b"message", b"", None));
+            assert!(syscall!(bob.list_keys_first(None, None)).entry.is_none());
+            (first, second)
+        });
+        assert_ne!(first.nonce, second.nonce);
+
+        as_client(service, "alice", |alice| {
+            let plaintext = syscall!(alice.decrypt_chacha8poly1305(
+                key, &second.ciphertext, b"", &second.nonce, &second.tag)).plaintext;
+            assert_eq!(&plaintext.unwrap()[..], b"message");
+            let third = syscall!(alice.encrypt_chacha8poly1305(key, b"message", b"", None));
+            assert!(third.nonce != first.nonce && third.nonce != second.nonce);
+        });
+    })
+}
+
+#[test]
+fn granted_keys_stay_with_their_owner() {
+    client::service(|service| {
+        as_client(service, "alice", |alice| {
+            let attributes = StorageAttributes::new().set_persistence(Internal).set_extractable(true);
+            let key = syscall!(alice.generate_key(Mechanism::Chacha8Poly1305, attributes)).key;
+            syscall!(alice.grant_key(key, PathBuf::from("bob"), Usage::WRAP));
+        });
+
+        as_client(service, "bob", |bob| {
+            let key = syscall!(bob.list_granted_keys(PathBuf::from("alice"))).keys[0];
+            let wrapping_key = syscall!(bob.generate_chacha8poly1305_key(Volatile)).key;
+
+            // the granted usage is for the key the request operates with,
+            // not for the key it wraps, copies or moves
+            assert_eq!(try_syscall!(bob.wrap_key_chacha8poly1305(wrapping_key, key, b"")).err(), Some(Error::KeyFunctionNotPermitted));
+            assert_eq!(try_syscall!(bob.copy_key(key, Volatile)).err(), Some(Error::KeyFunctionNotPermitted));
+            assert_eq!(try_syscall!(bob.move_key(key, Volatile)).err(), Some(Error::KeyFunctionNotPermitted));
+
+            // but it can wrap with it
+            syscall!(bob.wrap_key_chacha8poly1305(key, wrapping_key, b""));
+        });
+    })
+}
@@ -0,0 +1,89 @@
+//! Key files written by earlier firmware must keep loading.
+//!
+//! The fixtures are key files of each format version, and must never be changed.
+
+use trussed::error::Error;
+use trussed::key::{Flags, Key, Kind, Label, Material, Policy, Usage, SERIALIZATION_VERSION};
+use trussed::types::Mechanism;
+
+
+fn ed255_key() -> Key {
+    Key {
+        flags: Flags::LOCAL | Flags::SENSITIVE,
+        kind: Kind::Ed255,
+        policy: Policy::default(),
+        label: None,
+        created: None,
+        material: Material::try_from_slice(&(1..=32).collect::<Vec<u8>>()).unwrap(),
+    }
+}
+
+fn p256_key() -> Key {
+    Key {
+        flags: Flags::LOCAL | Flags::SENSITIVE | Flags::ALWAYS_SENSITIVE | Flags::NEVER_EXTRACTABLE,
+        kind: Kind::P256,
+        policy: Policy::new(Usage::SIGN | Usage::VERIFY).allow_mechanism(Mechanism::P256),
+        label: Some(Label::try_from_slice(b"fixture").unwrap()),
+        created: None,
+        material: Material::try_from_slice(&(0x21..=0x40).collect::<Vec<u8>>()).unwrap(),
+    }
+}
+
+#[test]
+fn version_0() {
+    let bytes = include_bytes!("fixtures/keys/v0-ed255.bin");
+    assert_eq!(Key::format_version(bytes), Ok(0));
+    assert_eq!(Key::try_deserialize(bytes), Ok(ed255_key()));
+
+    let bytes = include_bytes!("fixtures/keys/v0-chacha8poly1305.bin");
+    let key = Key::try_deserialize(bytes).unwrap();
+    assert_eq!(key.kind, Kind::Symmetric32Nonce(12));
+    assert_eq!(&key.material[..], &bytes[4..]);
+}
+
+#[test]
+fn current_version() {
+    assert_eq!(SERIALIZATION_VERSION, 2);
+
+    for (key, bytes) in [
+        (ed255_key(), &include_bytes!("fixtures/keys/v2-ed255.bin")[..]),
+        (p256_key(), &include_bytes!("fixtures/keys/v2-p256.bin")[..]),
+    ].iter() {
+        assert_eq!(Key::format_version(bytes), Ok(SERIALIZATION_VERSION));
+        assert_eq!(&key.serialize()[..], *bytes);
+        assert_eq!(Key::try_deserialize(bytes).as_ref(), Ok(key));
+    }
+}
+
+#[test]
+fn creation_time() {
+    let key = Key { created: Some(1_600_000_000), ..p256_key() };
+    let bytes = key.serialize();
+    assert_eq!(Key::format_version(&bytes), Ok(SERIALIZATION_VERSION));
+    assert_eq!(Key::try_deserialize(&bytes), Ok(key));
+}
+
+#[test]
+fn unknown_versions() {
+    let mut bytes = include_bytes!("fixtures/keys/v2-p256.bin").to_vec();
+    for version in [1, 0x80, SERIALIZATION_VERSION + 1].iter() {
+        bytes[0] = *version;
+        assert_eq!(Key::format_version(&bytes), Err(Error::InvalidSerializedKey));
+        assert_eq!(Key::try_deserialize(&bytes), Err(Error::InvalidSerializedKey));
+    }
+}
+
+#[test]
+fn stable_kind_codes() {
+    let kinds = [
+        (Kind::Shared(32), 1),
+        (Kind::Symmetric(32), 2),
+        (Kind::Symmetric32Nonce(12), 3),
+        (Kind::Ed255, 4),
+        (Kind::P256, 5),
+        (Kind::X255, 6),
+    ];
+    for (kind, code) in kinds.iter() {
+        assert_eq!(kind.code(), *code);
+    }
+}
@@ -0,0 +1,76 @@
+use trussed::client::mechanisms::Ed255;
+use trussed::client::CryptoClient;
+use trussed::{syscall, try_syscall};
+
+mod client;
+
+use trussed::error::Error;
+use trussed::key::Usage;
+use trussed::types::Location::*;
+use trussed::types::{KeySerialization, Mechanism, PathBuf, StorageAttributes};
+
+
+#[test]
+fn generate_into_slot() {
+    client::get(|client| {
+        assert!(syscall!(client.slot_key(0x9a)).key.is_none());
+
+        let attributes = StorageAttributes::new().set_persistence(Internal).set_slot(0x9a, false);
+        let sk = syscall!(client.generate_key(Mechanism::Ed255, attributes.clone())).key;
+        assert_eq!(syscall!(client.slot_key(0x9a)).key, Some(sk));
+        assert!(syscall!(client.slot_key(0x9c)).key.is_none());
+        let pk = syscall!(client.derive_ed255_public_key(sk, Volatile)).key;
+        let signature = syscall!(client.sign_ed255(sk, b"message")).signature;
+        assert!(syscall!(client.verify_ed255(pk, b"message", &signature)).valid);
+
+        assert_eq!(try_syscall!(client.generate_key(Mechanism::Ed255, attributes)).err(), Some(Error::SlotOccupied));
+        assert!(syscall!(client.exists(Mechanism::Ed255, sk)).exists);
+
+        // slot keys are not shared, as they may be replaced
+        assert_eq!(
+            try_syscall!(client.grant_key(sk, PathBuf::from("bob"), Usage::SIGN)).err(),
+            Some(Error::KeyFunctionNotPermitted),
+        );
+
+        let attributes = StorageAttributes::new().set_persistence(Volatile).set_slot(0x9a, true);
+        let replaced = syscall!(client.generate_key(Mechanism::Ed255, attributes)).key;
+        assert_eq!(replaced.object_id, sk.object_id);
+        let signature = syscall!(client.sign_ed255(replaced, b"message")).signature;
+        assert!(!syscall!(client.verify_ed255(pk, b"message", &signature)).valid);
+
+        syscall!(client.delete(replaced));
+        assert!(syscall!(client.slot_key(0x9a)).key.is_none());
+    })
+}
+
+#[test]
+fn deserialize_into_slot() {
+    client::get(|client| {
+        let sk = syscall!(client.generate_ed255_private_key(Internal)).key;
+        let pk = syscall!(client.derive_ed255_public_key(sk, Volatile)).key;
+        let serialized = syscall!(client.serialize_ed255_key(pk, KeySerialization::Raw)).serialized_key;
+
+        let attributes = StorageAttributes::new().set_persistence(Internal).set_slot(0x9e, false);
+        let slot_pk = syscall!(client.deserialize_key(Mechanism::Ed255, &serialized, KeySerialization::Raw, attributes)).key;
+        assert_eq!(syscall!(client.slot_key(0x9e)).key, Some(slot_pk));
+
+        let signature = syscall!(client.sign_ed255(sk, b"message")).signature;
+        assert!(syscall!(client.verify_ed255(slot_pk, b"message", &signature)).valid);
+    })
+}
+
+#[test]
+fn slot_keys_are_rewrapped() {
+    client::get(|client| {
+        let attributes = StorageAttributes::new().set_persistence(Internal).set_slot(0x9c, false);
+        let sk = syscall!(client.generate_key(Mechanism::Ed255, attributes)).key;
+        let pk = syscall!(client.derive_ed255_public_key(sk, Volatile)).key;
+
+        let reply = syscall!(client.rewrap_keys(b""));
+        assert_eq!((reply.version, reply.count), (1, 2));
+
+        assert_eq!(syscall!(client.slot_key(0x9c)).key, Some(sk));
+        let signature = syscall!(client.sign_ed255(sk, b"message")).signature;
+        assert!(syscall!(client.verify_ed255(pk, b"message", &signature)).valid);
+    })
+}
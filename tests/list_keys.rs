@@ -0,0 +1,100 @@
+use trussed::client::mechanisms::{Chacha8Poly1305, Ed255};
+use trussed::client::CryptoClient;
+use trussed::syscall;
+
+mod client;
+
+use trussed::key::{Kind, Secrecy};
+use trussed::types::Location::*;
+
+use client::Service;
+type Client<'a> = trussed::ClientImplementation<&'a mut Service>;
+
+fn as_client<R>(service: &mut Service, client_id: &str, f: impl FnOnce(&mut Client<'_>) -> R) -> R {
+    use trussed::Interchange as _;
+    unsafe { trussed::pipe::TrussedInterchange::reset_claims(); }
+    let mut client = service.try_as_new_client(client_id).unwrap();
+    f(&mut client)
+}
+
+
+#[test]
+fn list_all_keys() {
+    client::get(|client| {
+        let sk = syscall!(client.generate_ed255_private_key(Internal)).key;
+        let pk = syscall!(client.derive_ed255_public_key(sk, Volatile)).key;
+        let secret = syscall!(client.generate_chacha8poly1305_key(External)).key;
+
+        // ordered by location, then secrecy
+        let first = syscall!(client.list_keys_first(None, None)).entry.unwrap();
+        assert_eq!((first.key, first.secrecy, first.location), (sk, Secrecy::Secret, Internal));
+        assert_eq!(first.info.kind, Kind::Ed255);
+        let second = syscall!(client.list_keys_next()).entry.unwrap();
+        assert_eq!((second.key, second.secrecy, second.location), (secret, Secrecy::Secret, External));
+        let third = syscall!(client.list_keys_next()).entry.unwrap();
+        assert_eq!((third.key, third.secrecy, third.location), (pk, Secrecy::Public, Volatile));
+        assert!(syscall!(client.list_keys_next()).entry.is_none());
+        assert!(syscall!(client.list_keys_next()).entry.is_none());
+
+        let entry = syscall!(client.list_keys_first(None, Some(Secrecy::Public))).entry.unwrap();
+        assert_eq!(entry.key, pk);
+        assert!(syscall!(client.list_keys_next()).entry.is_none());
+
+        let entry = syscall!(client.list_keys_first(Some(Kind::Ed255), Some(Secrecy::Secret))).entry.unwrap();
+        assert_eq!(entry.key, sk);
+        assert!(syscall!(client.list_keys_next()).entry.is_none());
+    })
+}
+
+#[test]
+fn delete_while_listing() {
+    client::get(|client| {
+        for _ in 0..5 {
+            syscall!(client.generate_ed255_private_key(Volatile));
+        }
+
+        let mut entry = syscall!(client.list_keys_first(None, None)).entry;
+        let mut deleted = 0;
+        while let Some(current) = entry {
+            assert!(syscall!(client.delete(current.key)).success);
+            deleted += 1;
+            entry = syscall!(client.list_keys_next()).entry;
+        }
+        assert_eq!(deleted, 5);
+        assert!(syscall!(client.list_keys_first(None, None)).entry.is_none());
+    })
+}
+
+#[test]
+fn interleaved_listings() {
+    client::service(|service| {
+        let (alice_keys, first) = as_client(service, "alice", |alice| {
+            let keys = [
+                syscall!(alice.generate_ed255_private_key(Internal)).key,
+                syscall!(alice.generate_ed255_private_key(Volatile)).key,
+            ];
+            (keys, syscall!(alice.list_keys_first(None, None)).entry.unwrap())
+        });
+        assert_eq!(first.key, alice_keys[0]);
+
+        // each client has its own cursor, and never sees the keys of another
+        let bob_keys = as_client(service, "bob", |bob| {
+            let keys = [
+                syscall!(bob.generate_ed255_private_key(Internal)).key,
+                syscall!(bob.generate_ed255_private_key(Volatile)).key,
+            ];
+            assert_eq!(syscall!(bob.list_keys_first(None, None)).entry.unwrap().key, keys[0]);
+            keys
+        });
+
+        as_client(service, "alice", |alice| {
+            assert_eq!(syscall!(alice.list_keys_next()).entry.unwrap().key, alice_keys[1]);
+            assert!(syscall!(alice.list_keys_next()).entry.is_none());
+        });
+
+        as_client(service, "bob", |bob| {
+            assert_eq!(syscall!(bob.list_keys_next()).entry.unwrap().key, bob_keys[1]);
+            assert!(syscall!(bob.list_keys_next()).entry.is_none());
+        });
+    })
+}
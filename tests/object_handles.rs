@@ -0,0 +1,83 @@
+use trussed::client::mechanisms::Ed255;
+use trussed::client::CryptoClient;
+use trussed::error::Error;
+use trussed::types::{Mechanism, ObjectHandle};
+use trussed::{consts, syscall, try_syscall};
+
+mod client;
+
+use trussed::types::Location::*;
+
+
+fn tampered(handle: ObjectHandle) -> ObjectHandle {
+    let mut bytes: trussed::Bytes<consts::U64> = trussed::postcard_serialize_bytes(&handle).unwrap();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 1;
+    trussed::postcard_deserialize(&bytes).unwrap()
+}
+
+#[test]
+fn tampered_handle() {
+    client::get(|client| {
+        let sk = syscall!(client.generate_ed255_private_key(Internal)).key;
+        assert!(syscall!(client.exists(Mechanism::Ed255, sk)).exists);
+
+        let forged = tampered(sk);
+        assert_eq!(forged.object_id, sk.object_id);
+        assert_eq!(try_syscall!(client.exists(Mechanism::Ed255, forged)).err(), Some(Error::ObjectHandleInvalid));
+        assert_eq!(try_syscall!(client.sign_ed255(forged, b"message")).err(), Some(Error::ObjectHandleInvalid));
+    })
+}
+
+#[test]
+fn deleted_key() {
+    client::get(|client| {
+        let sk = syscall!(client.generate_ed255_private_key(Volatile)).key;
+        assert!(syscall!(client.delete(sk)).success);
+        assert_eq!(try_syscall!(client.exists(Mechanism::Ed255, sk)).err(), Some(Error::ObjectHandleInvalid));
+        assert_eq!(try_syscall!(client.delete(sk)).err(), Some(Error::ObjectHandleInvalid));
+    })
+}
+
+#[test]
+fn other_client() {
+    client::service(|service| {
+        use trussed::Interchange as _;
+        unsafe { trussed::pipe::TrussedInterchange::reset_claims(); }
+
+        let sk = {
+            let mut alice = service.try_as_new_client("alice").unwrap();
+            syscall!(alice.generate_ed255_private_key(Internal)).key
+        };
+
+        unsafe { trussed::pipe::TrussedInterchange::reset_claims(); }
+        let mut mallory = service.try_as_new_client("mallory").unwrap();
+        assert_eq!(try_syscall!(mallory.sign_ed255(sk, b"message")).err(), Some(Error::ObjectHandleInvalid));
+    })
+}
+
+#[test]
+fn legacy_handle() {
+    client::get(|client| {
+        let sk = syscall!(client.generate_ed255_private_key(Internal)).key;
+
+        // persisted as the object ID only, before handles were tagged
+        let tagged: trussed::Bytes<consts::U64> = trussed::postcard_serialize_bytes(&sk).unwrap();
+        assert_eq!(tagged[0], 32);
+        let mut bytes = tagged[..17].to_vec();
+        bytes[0] = 16;
+        let legacy: ObjectHandle = trussed::postcard_deserialize(&bytes).unwrap();
+        assert!(legacy.is_legacy());
+        assert_eq!(legacy, ObjectHandle::legacy(sk.object_id));
+        assert_eq!(try_syscall!(client.sign_ed255(legacy, b"message")).err(), Some(Error::ObjectHandleInvalid));
+
+        let upgraded = syscall!(client.upgrade_handle(legacy)).handle;
+        assert_eq!(upgraded, sk);
+        syscall!(client.sign_ed255(upgraded, b"message"));
+
+        // tagged handles, and untagged ones of objects that are not keys of the client, are not upgraded
+        assert_eq!(try_syscall!(client.upgrade_handle(sk)).err(), Some(Error::ObjectHandleInvalid));
+        assert!(syscall!(client.delete(sk)).success);
+        assert_eq!(try_syscall!(client.upgrade_handle(legacy)).err(), Some(Error::ObjectHandleInvalid));
+    })
+}
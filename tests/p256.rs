@@ -1,9 +1,10 @@
 use trussed::client::mechanisms::{HmacSha256, P256};
-use trussed::syscall;
+use trussed::{syscall, try_syscall};
 
 mod client;
 
 use trussed::types::Location::*;
+use trussed::types::SignatureSerialization;
 
 
 #[test]
@@ -23,3 +24,86 @@ fn p256_agree() {
         assert_eq!(derivative1, derivative2);
     })
 }
+
+#[test]
+fn p256_deterministic_signatures() {
+    client::get(|client| {
+        let sk = syscall!(client.generate_p256_private_key(Internal)).key;
+        let pk = syscall!(client.derive_p256_public_key(sk, Volatile)).key;
+        let message = b"sample";
+
+        let signature1 = syscall!(client.sign_p256_deterministic(sk, message, SignatureSerialization::Raw)).signature;
+        let signature2 = syscall!(client.sign_p256_deterministic(sk, message, SignatureSerialization::Raw)).signature;
+        assert_eq!(signature1, signature2);
+        assert!(syscall!(client.verify_p256(pk, message, &signature1)).valid);
+
+        // hedged nonces
+        let signature3 = syscall!(client.sign_p256(sk, message, SignatureSerialization::Raw)).signature;
+        let signature4 = syscall!(client.sign_p256(sk, message, SignatureSerialization::Raw)).signature;
+        assert_ne!(signature3, signature4);
+        assert_ne!(signature1, signature3);
+        assert!(syscall!(client.verify_p256(pk, message, &signature3)).valid);
+        assert!(syscall!(client.verify_p256(pk, message, &signature4)).valid);
+    })
+}
+
+#[test]
+fn p256_signature_formats() {
+    use trussed::client::CryptoClient;
+    use trussed::types::Mechanism;
+
+    client::get(|client| {
+        let sk = syscall!(client.generate_p256_private_key(Internal)).key;
+        let pk = syscall!(client.derive_p256_public_key(sk, Volatile)).key;
+        let message = b"sample";
+
+        let raw = syscall!(client.sign_p256_deterministic(sk, message, SignatureSerialization::Raw)).signature;
+        let der = syscall!(client.sign_p256_deterministic(sk, message, SignatureSerialization::Asn1Der)).signature;
+        let cose = syscall!(client.sign_p256_deterministic(sk, message, SignatureSerialization::Cose)).signature;
+        let jose = syscall!(client.sign_p256_deterministic(sk, message, SignatureSerialization::Jose)).signature;
+        assert_eq!(raw.len(), 64);
+        assert_eq!(raw, cose);
+        assert_eq!(raw, jose);
+        assert_eq!(der[0], 0x30);
+        assert_eq!(der[1] as usize, der.len() - 2);
+
+        for (signature, format) in [
+            (&raw, SignatureSerialization::Raw),
+            (&der, SignatureSerialization::Asn1Der),
+            (&cose, SignatureSerialization::Cose),
+            (&jose, SignatureSerialization::Jose),
+        ].iter() {
+            assert!(syscall!(client.verify(Mechanism::P256, pk, message, signature, *format)).valid);
+            assert!(!syscall!(client.verify(Mechanism::P256, pk, b"other", signature, *format)).valid);
+        }
+
+        // DER and fixed-size encodings are not interchangeable
+        assert!(try_syscall!(client.verify(Mechanism::P256, pk, message, &der, SignatureSerialization::Raw)).is_err());
+        assert!(try_syscall!(client.verify(Mechanism::P256, pk, message, &raw, SignatureSerialization::Asn1Der)).is_err());
+    })
+}
+
+#[test]
+fn p256_openssh_signatures() {
+    use trussed::client::CryptoClient;
+    use trussed::types::Mechanism;
+
+    client::get(|client| {
+        let sk = syscall!(client.generate_p256_private_key(Internal)).key;
+        let pk = syscall!(client.derive_p256_public_key(sk, Volatile)).key;
+        let message = b"data to sign";
+
+        let blob = syscall!(client.sign_p256(sk, message, SignatureSerialization::OpenSsh)).signature;
+        assert_eq!(&blob[..23], b"\0\0\0\x13ecdsa-sha2-nistp256");
+        assert!(syscall!(client.verify(Mechanism::P256, pk, message, &blob, SignatureSerialization::OpenSsh)).valid);
+
+        let format = SignatureSerialization::OpenSshSk { flags: 0x01, counter: 42 };
+        let blob = syscall!(client.sign_p256(sk, message, format)).signature;
+        assert_eq!(&blob[..38], b"\0\0\0\x22sk-ecdsa-sha2-nistp256@openssh.com");
+        assert_eq!(&blob[blob.len() - 5..], &[0x01, 0, 0, 0, 42]);
+        assert!(syscall!(client.verify(Mechanism::P256, pk, message, &blob, format)).valid);
+        let other_counter = SignatureSerialization::OpenSshSk { flags: 0x01, counter: 43 };
+        assert!(!syscall!(client.verify(Mechanism::P256, pk, message, &blob, other_counter)).valid);
+        assert!(try_syscall!(client.verify(Mechanism::P256, pk, message, &blob, SignatureSerialization::OpenSsh)).is_err());
+    })
+}
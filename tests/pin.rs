@@ -0,0 +1,97 @@
+use trussed::client::mechanisms::Ed255;
+use trussed::client::{CryptoClient, PinClient};
+use trussed::key::Usage;
+use trussed::{syscall, try_syscall};
+
+mod client;
+
+use trussed::error::Error;
+use trussed::types::Location::*;
+use trussed::types::{Mechanism, PathBuf, StorageAttributes};
+
+
+#[test]
+fn retries_and_lockout() {
+    client::get(|client| {
+        assert_eq!(syscall!(client.pin_retries()).retries, None);
+        assert_eq!(try_syscall!(client.verify_pin(b"123456")).err(), Some(Error::UserPinNotInitialized));
+        assert_eq!(try_syscall!(client.set_pin(b"123456", 0)).err(), Some(Error::ArgumentsBad));
+        assert_eq!(try_syscall!(client.set_pin(b"", 3)).err(), Some(Error::PinLenRange));
+
+        syscall!(client.set_pin(b"123456", 3));
+        assert_eq!(try_syscall!(client.set_pin(b"654321", 3)).err(), Some(Error::PinAlreadySet));
+
+        assert_eq!(try_syscall!(client.verify_pin(b"000000")).err(), Some(Error::PinIncorrect));
+        assert_eq!(syscall!(client.pin_retries()).retries, Some(2));
+        // a correct PIN resets the counter
+        syscall!(client.verify_pin(b"123456"));
+        assert_eq!(syscall!(client.pin_retries()).retries, Some(3));
+
+        assert_eq!(try_syscall!(client.change_pin(b"000000", b"654321")).err(), Some(Error::PinIncorrect));
+        syscall!(client.change_pin(b"123456", b"654321"));
+        assert_eq!(try_syscall!(client.verify_pin(b"123456")).err(), Some(Error::PinIncorrect));
+        assert_eq!(try_syscall!(client.verify_pin(b"123456")).err(), Some(Error::PinIncorrect));
+        assert_eq!(try_syscall!(client.verify_pin(b"123456")).err(), Some(Error::PinLocked));
+        assert_eq!(try_syscall!(client.verify_pin(b"654321")).err(), Some(Error::PinLocked));
+        assert_eq!(syscall!(client.pin_retries()).retries, Some(0));
+    })
+}
+
+#[test]
+fn private_keys() {
+    client::get(|client| {
+        syscall!(client.set_pin(b"123456", 3));
+
+        let attributes = StorageAttributes::new().set_persistence(Internal).set_private(true);
+        let sk = syscall!(client.generate_key(Mechanism::Ed255, attributes)).key;
+        assert_eq!(try_syscall!(client.sign_ed255(sk, b"message")).err(), Some(Error::UserNotLoggedIn));
+
+        syscall!(client.verify_pin(b"123456"));
+        syscall!(client.sign_ed255(sk, b"message"));
+
+        syscall!(client.logout());
+        assert_eq!(try_syscall!(client.sign_ed255(sk, b"message")).err(), Some(Error::UserNotLoggedIn));
+
+        // nor can other clients use them behind the PIN
+        assert_eq!(
+            try_syscall!(client.grant_key(sk, PathBuf::from("bob"), Usage::SIGN)).err(),
+            Some(Error::KeyFunctionNotPermitted),
+        );
+
+        // other keys do not need the PIN
+        let sk = syscall!(client.generate_ed255_private_key(Internal)).key;
+        syscall!(client.sign_ed255(sk, b"message"));
+    })
+}
+
+#[test]
+fn pin_file_needs_the_device() {
+    let bytes: trussed::types::Message = client::get(|client| {
+        syscall!(client.set_pin(b"123456", 3));
+        trussed::store::read(client::store::get(), Internal, &PathBuf::from("test/pin")).unwrap()
+    });
+
+    // the hash is not a plain HMAC of the PIN under the salt, which the file contains
+    use hmac::{Hmac, Mac, NewMac};
+    let mut mac = Hmac::<sha2::Sha256>::new_varkey(&bytes[2..18]).unwrap();
+    mac.update(b"123456");
+    assert_ne!(&bytes[18..], &mac.finalize().into_bytes()[..]);
+}
+
+#[test]
+fn pin_from_before_the_huk() {
+    client::service_with(None, |service| {
+        use trussed::Interchange as _;
+        unsafe { trussed::pipe::TrussedInterchange::reset_claims(); }
+        let mut client = service.try_as_new_client("test").unwrap();
+        syscall!(client.set_pin(b"123456", 3));
+        drop(client);
+
+        // after an update, the platform has a HUK, and the PIN still verifies
+        let mut service = trussed::service::Service::new(client::Platform::new(client::store::get(), Some(client::huk())));
+        unsafe { trussed::pipe::TrussedInterchange::reset_claims(); }
+        let mut client = service.try_as_new_client("test").unwrap();
+        syscall!(client.verify_pin(b"123456"));
+        assert_eq!(syscall!(client.pin_retries()).retries, Some(3));
+    })
+}
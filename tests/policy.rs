@@ -0,0 +1,168 @@
+use trussed::client::mechanisms::{Chacha8Poly1305, Ed255, HmacSha256, P256};
+use trussed::client::CryptoClient;
+use trussed::{syscall, try_syscall};
+
+mod client;
+
+use trussed::error::Error;
+use trussed::key::{Policy, TouchPolicy, Usage};
+use trussed::types::Location::*;
+use trussed::types::{KeySerialization, Mechanism, SignatureSerialization, StorageAttributes};
+
+
+#[test]
+fn signing_only_key() {
+    client::get(|client| {
+        let policy = Policy::new(Usage::SIGN).allow_mechanism(Mechanism::P256Deterministic);
+        let attributes = StorageAttributes::new().set_persistence(Internal).set_policy(policy);
+        let sk = syscall!(client.generate_key(Mechanism::P256, attributes)).key;
+        // deriving the public key is not restricted
+        let pk = syscall!(client.derive_p256_public_key(sk, Volatile)).key;
+        let message = b"data to sign";
+
+        let signature = syscall!(client.sign(Mechanism::P256Deterministic, sk, message, SignatureSerialization::Raw)).signature;
+        assert!(syscall!(client.verify_p256(pk, message, &signature)).valid);
+
+        assert_eq!(
+            try_syscall!(client.sign(Mechanism::P256, sk, message, SignatureSerialization::Raw)).err(),
+            Some(Error::KeyFunctionNotPermitted),
+        );
+        assert_eq!(
+            try_syscall!(client.agree_p256(sk, pk, Volatile)).err(),
+            Some(Error::KeyFunctionNotPermitted),
+        );
+    })
+}
+
+#[test]
+fn agreed_secret_policy() {
+    client::get(|client| {
+        let sk = syscall!(client.generate_p256_private_key(Internal)).key;
+        let pk = syscall!(client.derive_p256_public_key(sk, Volatile)).key;
+
+        let policy = Policy::new(Usage::DERIVE);
+        let attributes = StorageAttributes::new().set_persistence(Volatile).set_policy(policy);
+        let secret = syscall!(client.agree(Mechanism::P256, sk, pk, attributes)).shared_secret;
+
+        syscall!(client.hmacsha256_derive_key(secret, b"info", Volatile));
+        assert_eq!(
+            try_syscall!(client.sign_hmacsha256(secret, b"message")).err(),
+            Some(Error::KeyFunctionNotPermitted),
+        );
+    })
+}
+
+#[test]
+fn unrestricted_by_default() {
+    client::get(|client| {
+        let sk = syscall!(client.generate_ed255_private_key(Internal)).key;
+        let pk = syscall!(client.derive_ed255_public_key(sk, Volatile)).key;
+        let signature = syscall!(client.sign_ed255(sk, b"message")).signature;
+        assert!(syscall!(client.verify_ed255(pk, b"message", &signature)).valid);
+    })
+}
+
+#[test]
+fn non_extractable_key() {
+    client::get(|client| {
+        let wrapping_key = syscall!(client.generate_chacha8poly1305_key(Internal)).key;
+
+        let attributes = StorageAttributes::new().set_persistence(Internal).set_extractable(false);
+        let sk = syscall!(client.generate_key(Mechanism::Ed255, attributes)).key;
+        assert_eq!(
+            try_syscall!(client.wrap_key_chacha8poly1305(wrapping_key, sk, &[])).err(),
+            Some(Error::KeyUnextractable),
+        );
+        // secret keys are sensitive, they never leave in plaintext
+        assert_eq!(
+            try_syscall!(client.serialize_key(Mechanism::Ed255, sk, KeySerialization::Raw)).err(),
+            Some(Error::KeyUnextractable),
+        );
+
+        let sk = syscall!(client.generate_ed255_private_key(Internal)).key;
+        let wrapped_key = syscall!(client.wrap_key_chacha8poly1305(wrapping_key, sk, &[])).wrapped_key;
+        let unwrapped_key = syscall!(client.unwrap_key_chacha8poly1305(wrapping_key, &wrapped_key, &[], Volatile))
+            .key.unwrap();
+        let signature = syscall!(client.sign_ed255(unwrapped_key, b"message")).signature;
+        assert_eq!(signature, syscall!(client.sign_ed255(sk, b"message")).signature);
+    })
+}
+
+#[test]
+fn limited_uses() {
+    client::get(|client| {
+        let policy = Policy::default().limit_uses(2);
+        let attributes = StorageAttributes::new().set_persistence(Internal).set_policy(policy);
+        let sk = syscall!(client.generate_key(Mechanism::Ed255, attributes)).key;
+        for uses_remaining in (1..=2).rev() {
+            let info = syscall!(client.get_attributes(sk)).key.unwrap();
+            assert_eq!(info.policy.uses_remaining, Some(uses_remaining));
+            syscall!(client.sign_ed255(sk, b"message"));
+        }
+        assert_eq!(syscall!(client.get_attributes(sk)).key.unwrap().policy.uses_remaining, Some(0));
+        assert_eq!(try_syscall!(client.sign_ed255(sk, b"message")).err(), Some(Error::KeyExpired));
+    })
+}
+
+#[test]
+fn expired_key() {
+    client::get(|client| {
+        let attributes = StorageAttributes::new().set_persistence(Internal)
+            .set_policy(Policy::default().valid_until(1_000_000_000));
+        let sk = syscall!(client.generate_key(Mechanism::Ed255, attributes)).key;
+        assert_eq!(try_syscall!(client.sign_ed255(sk, b"message")).err(), Some(Error::KeyExpired));
+
+        let attributes = StorageAttributes::new().set_persistence(Internal)
+            .set_policy(Policy::default().valid_until(u64::MAX));
+        let sk = syscall!(client.generate_key(Mechanism::Ed255, attributes)).key;
+        syscall!(client.sign_ed255(sk, b"message"));
+    })
+}
+
+#[test]
+fn touch_policy() {
+    client::get(|client| {
+        let attributes = StorageAttributes::new().set_persistence(Internal)
+            .set_policy(Policy::default().require_touch(TouchPolicy::Always));
+        let always = syscall!(client.generate_key(Mechanism::Ed255, attributes)).key;
+        assert_eq!(syscall!(client.get_attributes(always)).key.unwrap().policy.touch, TouchPolicy::Always);
+        let attributes = StorageAttributes::new().set_persistence(Internal)
+            .set_policy(Policy::default().require_touch(TouchPolicy::Cached(15)));
+        let cached = syscall!(client.generate_key(Mechanism::Ed255, attributes)).key;
+        let untouched = syscall!(client.generate_ed255_private_key(Internal)).key;
+
+        // the user is asked for each use
+        let checks = client::ui::presence_checks();
+        syscall!(client.sign_ed255(always, b"message"));
+        syscall!(client.sign_ed255(always, b"message"));
+        assert_eq!(client::ui::presence_checks(), checks + 2);
+
+        // and once for a while
+        syscall!(client.sign_ed255(cached, b"message"));
+        syscall!(client.sign_ed255(cached, b"message"));
+        assert_eq!(client::ui::presence_checks(), checks + 2);
+
+        syscall!(client.sign_ed255(untouched, b"message"));
+        assert_eq!(client::ui::presence_checks(), checks + 2);
+
+        client::ui::set_present(false);
+        assert_eq!(try_syscall!(client.sign_ed255(always, b"message")).err(), Some(Error::UserNotPresent));
+        syscall!(client.sign_ed255(untouched, b"message"));
+    })
+}
+
+#[test]
+fn touch_policy_without_user() {
+    client::get(|client| {
+        client::ui::set_present(false);
+        let attributes = StorageAttributes::new().set_persistence(Internal)
+            .set_policy(Policy::default().require_touch(TouchPolicy::Cached(15)));
+        let sk = syscall!(client.generate_key(Mechanism::Ed255, attributes)).key;
+        assert_eq!(try_syscall!(client.sign_ed255(sk, b"message")).err(), Some(Error::UserNotPresent));
+
+        // declining does not count as a touch
+        assert_eq!(try_syscall!(client.sign_ed255(sk, b"message")).err(), Some(Error::UserNotPresent));
+        client::ui::set_present(true);
+        syscall!(client.sign_ed255(sk, b"message"));
+    })
+}
@@ -0,0 +1,56 @@
+use trussed::client::mechanisms::Ed255;
+use trussed::client::CryptoClient;
+use trussed::{syscall, try_syscall};
+
+mod client;
+
+use trussed::error::Error;
+use trussed::key::Policy;
+use trussed::types::Location::*;
+use trussed::types::{Mechanism, StorageAttributes};
+
+
+#[test]
+fn move_and_copy() {
+    client::get(|client| {
+        let sk = syscall!(client.generate_ed255_private_key(Volatile)).key;
+        let signature = syscall!(client.sign_ed255(sk, b"message")).signature;
+
+        syscall!(client.move_key(sk, Internal));
+        assert_eq!(syscall!(client.get_attributes(sk)).location, Internal);
+        assert_eq!(syscall!(client.sign_ed255(sk, b"message")).signature, signature);
+
+        let copy = syscall!(client.copy_key(sk, External)).key;
+        assert_ne!(copy, sk);
+        assert_eq!(syscall!(client.get_attributes(copy)).location, External);
+        assert_eq!(syscall!(client.get_attributes(copy)).key, syscall!(client.get_attributes(sk)).key);
+        assert_eq!(syscall!(client.sign_ed255(copy, b"message")).signature, signature);
+    })
+}
+
+#[test]
+fn non_extractable_keys_stay_on_device() {
+    client::get(|client| {
+        let attributes = StorageAttributes::new().set_persistence(Volatile).set_extractable(false);
+        let sk = syscall!(client.generate_key(Mechanism::Ed255, attributes)).key;
+
+        assert_eq!(try_syscall!(client.copy_key(sk, Volatile)).err(), Some(Error::KeyUnextractable));
+        assert_eq!(try_syscall!(client.move_key(sk, External)).err(), Some(Error::KeyUnextractable));
+        syscall!(client.move_key(sk, Internal));
+        assert_eq!(syscall!(client.get_attributes(sk)).location, Internal);
+    })
+}
+
+#[test]
+fn limited_keys_are_not_copied() {
+    client::get(|client| {
+        let attributes = StorageAttributes::new().set_persistence(Volatile).set_policy(Policy::default().limit_uses(1));
+        let sk = syscall!(client.generate_key(Mechanism::Ed255, attributes)).key;
+
+        // each copy would have its own use
+        assert_eq!(try_syscall!(client.copy_key(sk, Volatile)).err(), Some(Error::KeyUnextractable));
+        syscall!(client.move_key(sk, Internal));
+        syscall!(client.sign_ed255(sk, b"message"));
+        assert_eq!(try_syscall!(client.sign_ed255(sk, b"message")).err(), Some(Error::KeyExpired));
+    })
+}
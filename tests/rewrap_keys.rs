@@ -0,0 +1,54 @@
+use trussed::client::mechanisms::{Chacha8Poly1305, Ed255};
+use trussed::client::CryptoClient;
+use trussed::{syscall, try_syscall};
+
+mod client;
+
+use trussed::error::Error;
+use trussed::types::Location::*;
+use trussed::types::PathBuf;
+
+
+#[test]
+fn rewrap_all_locations() {
+    client::get(|client| {
+        let sk = syscall!(client.generate_ed255_private_key(Internal)).key;
+        let pk = syscall!(client.derive_ed255_public_key(sk, External)).key;
+        let secret = syscall!(client.generate_chacha8poly1305_key(Volatile)).key;
+        let ciphertext = syscall!(client.encrypt_chacha8poly1305(secret, b"message", b"", None));
+
+        let reply = syscall!(client.rewrap_keys(b"new PIN"));
+        assert_eq!((reply.version, reply.count), (1, 3));
+
+        // keys remain usable under the new KEK
+        let signature = syscall!(client.sign_ed255(sk, b"message")).signature;
+        assert!(syscall!(client.verify_ed255(pk, b"message", &signature)).valid);
+        let plaintext = syscall!(client.decrypt_chacha8poly1305(
+            secret, &ciphertext.ciphertext, b"", &ciphertext.nonce, &ciphertext.tag)).plaintext;
+        assert_eq!(&plaintext.unwrap()[..], b"message");
+
+        // keys written since, like the incremented nonce, are already under the new KEK,
+        // but are rewrapped again by the next rotation
+        let reply = syscall!(client.rewrap_keys(b""));
+        assert_eq!((reply.version, reply.count), (2, 3));
+    })
+}
+
+#[test]
+fn failed_rewrap_is_resumed() {
+    client::get(|client| {
+        let sk = syscall!(client.generate_ed255_private_key(Internal)).key;
+
+        // a key file that cannot be read, whatever its name, fails the rotation
+        let path = PathBuf::from("test/sec/broken");
+        trussed::store::store(client::store::get(), Internal, &path, b"not a key").unwrap();
+        assert_eq!(try_syscall!(client.rewrap_keys(b"")).err(), Some(Error::TamperedKey));
+
+        // which stays in progress, with the keys rewrapped so far still usable
+        let signature = syscall!(client.sign_ed255(sk, b"message")).signature;
+        assert!(trussed::store::delete(client::store::get(), Internal, &path));
+        let reply = syscall!(client.rewrap_keys(b""));
+        assert_eq!(reply.version, 1);
+        assert_eq!(syscall!(client.sign_ed255(sk, b"message")).signature, signature);
+    })
+}
@@ -0,0 +1,80 @@
+use trussed::client::mechanisms::{Ed255, X255};
+use trussed::client::CryptoClient;
+use trussed::{syscall, try_syscall};
+
+mod client;
+
+use trussed::error::Error;
+use trussed::types::Location::*;
+use trussed::types::Mechanism;
+
+
+#[test]
+fn close_session() {
+    client::get(|client| {
+        let outside = syscall!(client.generate_ed255_private_key(Volatile)).key;
+
+        syscall!(client.open_session());
+        assert_eq!(try_syscall!(client.open_session()).err(), Some(Error::SessionExists));
+        let sk = syscall!(client.generate_x255_secret_key(Volatile)).key;
+        let pk = syscall!(client.derive_x255_public_key(sk, Volatile)).key;
+        let persistent = syscall!(client.generate_ed255_private_key(Internal)).key;
+        let moved = syscall!(client.generate_ed255_private_key(Volatile)).key;
+        syscall!(client.move_key(moved, Internal));
+
+        assert_eq!(syscall!(client.close_session()).count, 2);
+        assert_eq!(try_syscall!(client.exists(Mechanism::X255, sk)).err(), Some(Error::ObjectHandleInvalid));
+        assert_eq!(try_syscall!(client.exists(Mechanism::X255, pk)).err(), Some(Error::ObjectHandleInvalid));
+        assert!(syscall!(client.exists(Mechanism::Ed255, outside)).exists);
+        assert!(syscall!(client.exists(Mechanism::Ed255, persistent)).exists);
+        assert!(syscall!(client.exists(Mechanism::Ed255, moved)).exists);
+
+        assert_eq!(syscall!(client.close_session()).count, 0);
+    })
+}
+
+#[test]
+fn bounded_session() {
+    client::get(|client| {
+        syscall!(client.open_session());
+        for _ in 0..16 {
+            syscall!(client.generate_ed255_private_key(Volatile));
+        }
+        assert_eq!(try_syscall!(client.generate_ed255_private_key(Volatile)).err(), Some(Error::SessionFull));
+        // persistent keys are not limited
+        syscall!(client.generate_ed255_private_key(Internal));
+        assert_eq!(syscall!(client.close_session()).count, 16);
+        syscall!(client.generate_ed255_private_key(Volatile));
+    })
+}
+
+#[test]
+fn deleted_keys_leave_the_session() {
+    client::get(|client| {
+        syscall!(client.open_session());
+        for _ in 0..32 {
+            let sk = syscall!(client.generate_ed255_private_key(Volatile)).key;
+            syscall!(client.delete(sk));
+        }
+        for _ in 0..16 {
+            let sk = syscall!(client.generate_ed255_private_key(Volatile)).key;
+            syscall!(client.move_key(sk, Internal));
+        }
+        let sk = syscall!(client.generate_ed255_private_key(Volatile)).key;
+        assert_eq!(syscall!(client.close_session()).count, 1);
+        assert_eq!(try_syscall!(client.exists(Mechanism::Ed255, sk)).err(), Some(Error::ObjectHandleInvalid));
+    })
+}
+
+#[test]
+fn bounded_volatile_keys() {
+    client::get(|client| {
+        for _ in 0..32 {
+            syscall!(client.generate_ed255_private_key(Volatile));
+        }
+        assert_eq!(try_syscall!(client.generate_ed255_private_key(Volatile)).err(), Some(Error::DeviceMemory));
+        syscall!(client.generate_ed255_private_key(Internal));
+        syscall!(client.delete_all(Volatile));
+        syscall!(client.generate_ed255_private_key(Volatile));
+    })
+}
@@ -0,0 +1,134 @@
+use trussed::client::mechanisms::{Chacha8Poly1305, Ed255};
+use trussed::client::CryptoClient;
+use trussed::{syscall, try_syscall};
+
+mod client;
+
+use trussed::error::Error;
+use trussed::key::{Flags, Kind, Label, Policy, Usage};
+use trussed::types::Location::*;
+use trussed::types::{Mechanism, Message, StorageAttributes};
+
+
+#[test]
+fn attributes_restored() {
+    client::get(|client| {
+        let wrapping_key = syscall!(client.generate_chacha8poly1305_key(Internal)).key;
+        let label = Label::try_from_slice(b"signing").unwrap();
+        let attributes = StorageAttributes::new()
+            .set_persistence(Internal)
+            .set_label(label.clone())
+            .set_policy(Policy::new(Usage::SIGN));
+        let sk = syscall!(client.generate_key(Mechanism::Ed255, attributes)).key;
+
+        let wrapped_key = syscall!(client.wrap_key_chacha8poly1305(wrapping_key, sk, b"context")).wrapped_key;
+        // envelope version and mechanism
+        assert_eq!(&wrapped_key[..2], &[1, Mechanism::Chacha8Poly1305.code()]);
+
+        let unwrapped_key = syscall!(client.unwrap_key_chacha8poly1305(wrapping_key, &wrapped_key, b"context", Volatile)).key.unwrap();
+        let info = syscall!(client.get_attributes(unwrapped_key)).key.unwrap();
+        assert_eq!(info.kind, Kind::Ed255);
+        assert_eq!(info.label, Some(label));
+        assert_eq!(info.policy.usage, Usage::SIGN);
+        // the key was outside the service
+        assert_eq!(info.flags, Flags::EXTRACTABLE | Flags::SENSITIVE);
+        assert_eq!(info.policy, syscall!(client.get_attributes(sk)).key.unwrap().policy);
+
+        assert_eq!(
+            syscall!(client.sign_ed255(unwrapped_key, b"message")).signature,
+            syscall!(client.sign_ed255(sk, b"message")).signature,
+        );
+
+        // the request may restrict the key further
+        let attributes = StorageAttributes::new().set_persistence(Volatile).set_extractable(false);
+        let unwrapped_key = syscall!(client.unwrap_key(
+            Mechanism::Chacha8Poly1305, wrapping_key, wrapped_key, b"context", attributes)).key.unwrap();
+        let info = syscall!(client.get_attributes(unwrapped_key)).key.unwrap();
+        assert_eq!(info.flags, Flags::SENSITIVE);
+        assert_eq!(info.policy.usage, Usage::SIGN);
+    })
+}
+
+#[test]
+fn precise_errors() {
+    client::get(|client| {
+        let wrapping_key = syscall!(client.generate_chacha8poly1305_key(Internal)).key;
+        let other_wrapping_key = syscall!(client.generate_chacha8poly1305_key(Internal)).key;
+        let sk = syscall!(client.generate_ed255_private_key(Internal)).key;
+        let wrapped_key = syscall!(client.wrap_key_chacha8poly1305(wrapping_key, sk, b"context")).wrapped_key;
+
+        let mut wrong_header = wrapped_key.clone();
+        // the key's header is authenticated
+        wrong_header[5] ^= 1;
+        let mut wrong_ciphertext = wrapped_key.clone();
+        let last = wrong_ciphertext.len() - 1;
+        wrong_ciphertext[last] ^= 1;
+        let mut wrong_version = wrapped_key.clone();
+        wrong_version[0] = 2;
+        let mut wrong_mechanism = wrapped_key.clone();
+        wrong_mechanism[1] = Mechanism::Aes256Cbc.code();
+
+        let cases: [(_, &[u8], &[u8], _); 7] = [
+            // failing authentication
+            (wrapping_key, &wrapped_key, b"other", Error::WrappedKeyInvalid),
+            (other_wrapping_key, &wrapped_key, b"context", Error::WrappedKeyInvalid),
+            (wrapping_key, &wrong_header, b"context", Error::WrappedKeyInvalid),
+            (wrapping_key, &wrong_ciphertext, b"context", Error::WrappedKeyInvalid),
+            // malformed
+            (wrapping_key, &wrong_version, b"context", Error::WrappedKeyInvalid),
+            (wrapping_key, &wrapped_key[..10], b"context", Error::WrappedKeyInvalid),
+            (wrapping_key, &wrong_mechanism, b"context", Error::MechanismInvalid),
+        ];
+        for (wrapping_key, wrapped_key, associated_data, error) in cases.iter() {
+            assert_eq!(
+                try_syscall!(client.unwrap_key_chacha8poly1305(*wrapping_key, wrapped_key, associated_data, Volatile)).err(),
+                Some(*error),
+            );
+        }
+
+        let unwrapped_key = syscall!(client.unwrap_key(
+            Mechanism::Chacha8Poly1305, wrapping_key, wrapped_key, b"context", StorageAttributes::new())).key.unwrap();
+        assert!(syscall!(client.exists(Mechanism::Ed255, unwrapped_key)).exists);
+    })
+}
+
+#[test]
+fn limited_keys_are_not_wrapped() {
+    client::get(|client| {
+        let wrapping_key = syscall!(client.generate_chacha8poly1305_key(Internal)).key;
+        let attributes = StorageAttributes::new().set_persistence(Internal).set_policy(Policy::default().limit_uses(1));
+        let sk = syscall!(client.generate_key(Mechanism::Ed255, attributes)).key;
+
+        // unwrapping it again and again would give it new uses
+        assert_eq!(
+            try_syscall!(client.wrap_key_chacha8poly1305(wrapping_key, sk, b"")).err(),
+            Some(Error::KeyUnextractable),
+        );
+    })
+}
+
+#[test]
+fn legacy_wrapped_keys() {
+    client::get(|client| {
+        let wrapping_key = syscall!(client.generate_chacha8poly1305_key(Internal)).key;
+
+        // a key wrapped before the envelope, like the credential IDs of FIDO authenticators:
+        // the postcard-serialized encryption of the serialized key
+        let serialized_key = include_bytes!("fixtures/keys/v0-ed255.bin");
+        let encrypted = syscall!(client.encrypt_chacha8poly1305(wrapping_key, serialized_key, b"", None));
+        let wrapped_key: Message = trussed::postcard_serialize_bytes(&encrypted).unwrap();
+
+        let sk = syscall!(client.unwrap_key_chacha8poly1305(wrapping_key, &wrapped_key, b"", Volatile)).key.unwrap();
+        let info = syscall!(client.get_attributes(sk)).key.unwrap();
+        assert_eq!(info.kind, Kind::Ed255);
+        let pk = syscall!(client.derive_ed255_public_key(sk, Volatile)).key;
+        let signature = syscall!(client.sign_ed255(sk, b"message")).signature;
+        assert!(syscall!(client.verify_ed255(pk, b"message", &signature)).valid);
+
+        assert!(syscall!(client.unwrap_key_chacha8poly1305(wrapping_key, &wrapped_key, b"other", Volatile)).key.is_none());
+        assert_eq!(
+            try_syscall!(client.unwrap_key_chacha8poly1305(wrapping_key, &wrapped_key[..wrapped_key.len() - 1], b"", Volatile)).err(),
+            Some(Error::WrappedKeyInvalid),
+        );
+    })
+}